@@ -0,0 +1,6 @@
+// The rustc version is sent as the _runtime_version connection attribute, it's looked up once at build time.
+fn main() {
+    if let Ok(version) = rustc_version::version() {
+        println!("cargo:rustc-env=RUSTC_VERSION={}", version);
+    }
+}
@@ -0,0 +1,422 @@
+use crate::client::{Conn, Dialer, SSL_REQUEST_PACKET_LEN};
+use crate::error::{MysqlError, ReplicationError};
+use crate::mysql;
+use crate::mysql::result::MysqlResult;
+use crate::packet;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+// AsyncConn is a connection to a MySQL server over a packet::AsyncConn, the async counterpart of Conn.
+// The state of the connection and the parsing of the packets are the ones of Conn, only the I/O is async,
+// a task waiting on it doesn't park a worker thread.
+#[derive(Debug)]
+pub struct AsyncConn {
+    _c: Conn,
+    _conn: Option<packet::AsyncConn>,
+    _password: String,
+    _charset: String,
+}
+
+// AsyncConnOption is called on the new AsyncConn before the handshake, see connect_async.
+pub type AsyncConnOption = dyn Fn(&mut AsyncConn) + Send + Sync;
+
+// Connect to a MySQL server, addr can be ip:port, or a unix socket domain like /var/sock.
+// Like dial it gives up connecting after 10 seconds.
+pub async fn connect_async(
+    addr: &str,
+    user: &str,
+    password: &str,
+    db_name: &str,
+    options: &[Box<AsyncConnOption>],
+) -> Result<AsyncConn, ReplicationError> {
+    let (ctx, _handle) = tokio_context::context::Context::with_timeout(Duration::from_secs(10));
+    let conn = packet::AsyncConn::connect(ctx, addr).await?;
+
+    AsyncConn::_connect(conn, addr, user, password, db_name, options).await
+}
+
+// Connect to a MySQL server using the given Dialer, it runs on the blocking threads of tokio.
+pub async fn connect_async_with_dialer(
+    addr: &str,
+    user: &str,
+    password: &str,
+    db_name: &str,
+    dialer: Arc<Dialer>,
+    options: &[Box<AsyncConnOption>],
+) -> Result<AsyncConn, ReplicationError> {
+    let proto = mysql::get_net_proto(addr);
+    let dial_addr = addr.to_string();
+    let stream = tokio::task::spawn_blocking(move || {
+        let (ctx, _handle) = tokio_context::context::Context::new();
+        dialer(ctx, &proto, &dial_addr)
+    })
+    .await
+    .map_err(|e| ReplicationError::new(format!("dial {} failed: {}", addr, e)))??;
+    let conn = packet::AsyncConn::new(packet::AsyncStream::from_std(stream)?);
+
+    AsyncConn::_connect(conn, addr, user, password, db_name, options).await
+}
+
+impl AsyncConn {
+    async fn _connect(
+        conn: packet::AsyncConn,
+        addr: &str,
+        user: &str,
+        password: &str,
+        db_name: &str,
+        options: &[Box<AsyncConnOption>],
+    ) -> Result<AsyncConn, ReplicationError> {
+        let mut c = AsyncConn {
+            _c: Conn::new(addr, user, password, db_name),
+            _conn: Some(conn),
+            _password: password.to_string(),
+            // use default charset here, utf-8
+            _charset: mysql::DEFAULT_CHARSET.to_string(),
+        };
+
+        // Apply configuration functions.
+        for option in options {
+            option(&mut c);
+        }
+
+        c._handshake().await?;
+
+        Ok(c)
+    }
+
+    async fn _handshake(&mut self) -> Result<(), ReplicationError> {
+        if let Err(e) = self._read_initial_handshake().await {
+            let _ = self.close().await;
+            return Err(ReplicationError::new(format!(
+                "readInitialHandshake: {}",
+                e
+            )));
+        }
+
+        if let Err(e) = self._write_auth_handshake().await {
+            let _ = self.close().await;
+            return Err(ReplicationError::new(format!("writeAuthHandshake: {}", e)));
+        }
+
+        if let Err(e) = self._handle_auth_result().await {
+            let _ = self.close().await;
+            return Err(ReplicationError::new(format!("handleAuthResult: {}", e)));
+        }
+
+        // Switch to compression mode
+        let compression = self._c.get_compression();
+        self._packet_conn()?.compression = compression;
+
+        Ok(())
+    }
+
+    async fn _read_initial_handshake(&mut self) -> Result<(), ReplicationError> {
+        let data = self.read_packet().await?;
+
+        self._c.parse_initial_handshake(&data)
+    }
+
+    async fn _write_auth_handshake(&mut self) -> Result<(), ReplicationError> {
+        let mut data = self._c.gen_auth_handshake()?;
+
+        // the SSLRequest packet, the TLS handshake follows it
+        if let Some(config) = self._c.get_tls_config() {
+            self.write_packet(&mut data[..SSL_REQUEST_PACKET_LEN].to_vec())
+                .await?;
+
+            // Switch to TLS, the sequence goes on
+            let host = self._c.get_host();
+            self._packet_conn()?
+                .upgrade_tls(Arc::new(config), &host)
+                .await?;
+        }
+
+        self.write_packet(&mut data).await
+    }
+
+    async fn _read_auth_result(&mut self) -> Result<(Vec<u8>, String), ReplicationError> {
+        let data = self
+            .read_packet()
+            .await
+            .map_err(|e| ReplicationError::new(format!("ReadPacket: {}", e)))?;
+
+        self._c.parse_auth_result(&data)
+    }
+
+    // see Conn::_handle_auth_result
+    async fn _handle_auth_result(&mut self) -> Result<(), ReplicationError> {
+        let (mut auth_data, switch_to_plugin) = self._read_auth_result().await?;
+
+        // handle auth switch, only support 'sha256_password', and 'caching_sha2_password'
+        if !switch_to_plugin.is_empty() {
+            let (auth, add_nul) = self._c.switch_auth_plugin(switch_to_plugin, &auth_data)?;
+            self._packet_conn()?
+                .write_auth_switch_packet(&auth, add_nul)
+                .await?;
+
+            // Read Result Packet
+            let (data, switch_to_plugin) = self._read_auth_result().await?;
+
+            // Do not allow to change the auth plugin more than once
+            if !switch_to_plugin.is_empty() {
+                return Err(ReplicationError::new(
+                    "can not switch auth plugin more than once".to_string(),
+                ));
+            }
+            auth_data = data;
+        }
+
+        match self._c.get_auth_plugin_name().as_str() {
+            // handle caching_sha2_password
+            mysql::AUTH_CACHING_SHA2_PASSWORD => {
+                if auth_data.is_empty() {
+                    // auth already succeeded
+                    return Ok(());
+                }
+
+                match auth_data[0] {
+                    mysql::CACHE_SHA2_FAST_AUTH => {
+                        let _ = self.read_ok_packet().await?;
+                        Ok(())
+                    }
+                    mysql::CACHE_SHA2_FULL_AUTH => {
+                        // need full authentication
+                        let password = self._password.clone();
+                        if self._c.is_secure_connection() {
+                            self._packet_conn()?
+                                .write_clear_auth_packet(&password)
+                                .await?;
+                        } else {
+                            let salt = self._c.get_salt();
+                            self._packet_conn()?
+                                .write_public_key_auth_packet(&password, &salt)
+                                .await?;
+                        }
+                        let _ = self.read_ok_packet().await?;
+                        Ok(())
+                    }
+                    _ => Err(ReplicationError::new(format!(
+                        "invalid packet {:x}",
+                        auth_data[0]
+                    ))),
+                }
+            }
+            mysql::AUTH_SHA256_PASSWORD => {
+                if auth_data.is_empty() {
+                    // auth already succeeded
+                    return Ok(());
+                }
+
+                let pub_key = openssl::pkey::PKey::public_key_from_pem(&auth_data)?;
+                // send encrypted password
+                let password = self._password.clone();
+                let salt = self._c.get_salt();
+                self._packet_conn()?
+                    .write_encrypted_password(&password, &salt, &pub_key)
+                    .await?;
+                let _ = self.read_ok_packet().await?;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn close(&mut self) -> Result<(), ReplicationError> {
+        if let Some(mut c) = self._conn.take() {
+            c.close().await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn read_packet(&mut self) -> Result<Vec<u8>, ReplicationError> {
+        self._packet_conn()?.read_packet().await
+    }
+
+    // ReadPacketInto reads the payload of the next packet into dst, see packet::Conn::read_packet_into.
+    pub async fn read_packet_into(&mut self, dst: &mut Vec<u8>) -> Result<(), ReplicationError> {
+        self._packet_conn()?.read_packet_into(dst).await
+    }
+
+    pub async fn write_packet(&mut self, data: &mut [u8]) -> Result<(), ReplicationError> {
+        self._packet_conn()?.write_packet(data).await
+    }
+
+    // SetReadTimeout sets the time a packet has to be read in, see packet::AsyncConn::set_read_timeout.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<(), ReplicationError> {
+        self._packet_conn()?.set_read_timeout(timeout);
+
+        Ok(())
+    }
+
+    // SetRecvBufferSize sets SO_RCVBUF of the socket, it's best set before the handshake.
+    pub fn set_recv_buffer_size(&mut self, size: usize) -> Result<(), ReplicationError> {
+        self._packet_conn()?.set_recv_buffer_size(size)
+    }
+
+    // TryClonePacketConn returns a packet::AsyncConn on the same stream, see packet::AsyncConn::try_clone.
+    pub fn try_clone_packet_conn(&self) -> Result<packet::AsyncConn, ReplicationError> {
+        Ok(self
+            ._conn
+            .as_ref()
+            .ok_or(ReplicationError::from(MysqlError::ErrBadConn))?
+            .try_clone())
+    }
+
+    pub fn reset_sequence(&mut self) {
+        if let Some(c) = self._conn.as_mut() {
+            c.reset_sequence();
+        }
+    }
+
+    fn _packet_conn(&mut self) -> Result<&mut packet::AsyncConn, ReplicationError> {
+        self._conn
+            .as_mut()
+            .ok_or(ReplicationError::from(MysqlError::ErrBadConn))
+    }
+
+    pub fn get_server_version(&self) -> String {
+        self._c.get_server_version()
+    }
+
+    pub fn is_tls(&self) -> bool {
+        self._conn.as_ref().is_some_and(|c| c.is_tls())
+    }
+
+    pub fn get_compression(&self) -> u8 {
+        self._conn
+            .as_ref()
+            .map(|c| c.compression)
+            .unwrap_or_default()
+    }
+
+    // Execute sends the command with COM_QUERY and reads the OK packet or the text protocol result set.
+    // An ERR packet is returned as ReplicationError::MyError.
+    pub async fn execute(&mut self, command: &str) -> Result<MysqlResult, ReplicationError> {
+        self._write_command_str(mysql::COM_QUERY, command).await?;
+
+        self._read_result().await
+    }
+
+    // SetTLSConfig: use user-specified TLS config, see mysql::new_client_tls_config
+    // pass to options when connect
+    pub fn set_tls_config(&mut self, config: rustls::ClientConfig) {
+        self._c.set_tls_config(config);
+    }
+
+    // SetCapability enables the use of a specific capability, e.g. CLIENT_COMPRESS
+    // pass to options when connect
+    pub fn set_capability(&mut self, cap: u32) {
+        self._c.set_capability(cap);
+    }
+
+    // UnsetCapability disables the use of a specific capability
+    pub fn unset_capability(&mut self, cap: u32) {
+        self._c.unset_capability(cap);
+    }
+
+    pub fn set_attributes(&mut self, attributes: HashMap<String, String>) {
+        self._c.set_attributes(attributes);
+    }
+
+    pub async fn set_charset(&mut self, charset: &str) -> Result<(), ReplicationError> {
+        if self._charset == charset {
+            return Ok(());
+        }
+
+        let _ = self.execute(&format!("SET NAMES {}", charset)).await?;
+        self._charset = charset.to_string();
+        Ok(())
+    }
+
+    pub fn get_charset(&self) -> String {
+        self._charset.clone()
+    }
+
+    pub fn get_connection_id(&self) -> u32 {
+        self._c.get_connection_id()
+    }
+
+    pub fn handle_ok_packet(&mut self, data: &[u8]) -> MysqlResult {
+        self._c.handle_ok_packet(data)
+    }
+
+    pub fn handle_error_packet(&self, data: &[u8]) -> ReplicationError {
+        self._c.handle_error_packet(data)
+    }
+
+    pub async fn read_ok_packet(&mut self) -> Result<MysqlResult, ReplicationError> {
+        let data = self.read_packet().await?;
+
+        match data.first() {
+            Some(&mysql::OK_HEADER) => Ok(self._c.handle_ok_packet(&data)),
+            Some(&mysql::ERR_HEADER) => Err(self._c.handle_error_packet(&data)),
+            Some(_) => Err(ReplicationError::new("invalid ok packet".to_string())),
+            None => Err(ReplicationError::from(MysqlError::ErrMalformPacket)),
+        }
+    }
+
+    async fn _write_command_str(&mut self, command: u8, arg: &str) -> Result<(), ReplicationError> {
+        self.reset_sequence();
+
+        let mut data = vec![0_u8; 4 + 1 + arg.len()];
+        data[4] = command;
+        data[5..].copy_from_slice(arg.as_bytes());
+
+        self.write_packet(&mut data).await
+    }
+
+    async fn _read_result(&mut self) -> Result<MysqlResult, ReplicationError> {
+        let data = self.read_packet().await?;
+
+        match data.first() {
+            Some(&mysql::OK_HEADER) => Ok(self._c.handle_ok_packet(&data)),
+            Some(&mysql::ERR_HEADER) => Err(self._c.handle_error_packet(&data)),
+            Some(&mysql::LOCAL_IN_FILE_HEADER) | None => {
+                Err(ReplicationError::from(MysqlError::ErrMalformPacket))
+            }
+            Some(_) => self._read_result_set(&data).await,
+        }
+    }
+
+    // the packets of the result set are read first, Conn builds the result set out of them
+    async fn _read_result_set(&mut self, data: &[u8]) -> Result<MysqlResult, ReplicationError> {
+        // column count
+        let (count, _, n) = mysql::length_encoded_int(data);
+
+        if n != data.len() {
+            return Err(ReplicationError::from(MysqlError::ErrMalformPacket));
+        }
+
+        let mut packets = vec![];
+        // there is no EOF packet after the column definitions with CLIENT_DEPRECATE_EOF
+        if self._c.get_capability() & mysql::CLIENT_DEPRECATE_EOF > 0 {
+            for _ in 0..count {
+                packets.push(self.read_packet().await?);
+            }
+        } else {
+            loop {
+                let packet = self.read_packet().await?;
+                let end = self._c.is_eof_packet(&packet);
+                packets.push(packet);
+                if end {
+                    break;
+                }
+            }
+        }
+
+        // the rows end with the EOF packet, or an OK packet if CLIENT_DEPRECATE_EOF is set, or an ERR packet
+        loop {
+            let packet = self.read_packet().await?;
+            let end = self._c.is_result_set_end_packet(&packet)
+                || matches!(packet.first(), Some(&mysql::ERR_HEADER) | None);
+            packets.push(packet);
+            if end {
+                break;
+            }
+        }
+
+        self._c.read_result_set_from(data, packets, false)
+    }
+}
@@ -0,0 +1,332 @@
+#[cfg(test)]
+mod tests {
+    use crate::client;
+    use crate::client::conn_test::tests::{
+        decrypt_password, new_cert, password_with_nul, read_command, read_packet, rsa_key,
+        start_compress_server, start_query_server, start_server, start_tls_server,
+        write_initial_handshake, write_ok, write_packet, Cert, PASSWORD, SALT, USER,
+    };
+    use crate::error::{MysqlError, ReplicationError};
+    use crate::mysql;
+    use std::net::TcpListener;
+    use std::os::unix::net::UnixListener;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    async fn connect(port: u16, password: &str) -> Result<client::AsyncConn, ReplicationError> {
+        client::connect_async(&format!("127.0.0.1:{}", port), USER, password, "test", &[]).await
+    }
+
+    #[tokio::test]
+    async fn test_native_password() -> Result<(), ReplicationError> {
+        let (port, rx) = start_server(
+            mysql::AUTH_NATIVE_PASSWORD,
+            Box::new(|_, resp, _| resp.auth == mysql::calc_password(SALT, PASSWORD.as_bytes())),
+        );
+
+        let mut c = connect(port, PASSWORD).await?;
+        let resp = rx.recv().unwrap();
+        assert_eq!(resp.user, USER);
+        assert_eq!(resp.db, "test");
+        assert_eq!(resp.plugin, mysql::AUTH_NATIVE_PASSWORD);
+        assert_eq!(resp.attributes.get("_client_name").unwrap(), "rs_mysql");
+        assert_eq!(c.get_server_version(), "8.0.33");
+        assert_eq!(c.get_connection_id(), 7);
+        assert!(!c.is_tls());
+
+        c.close().await?;
+
+        let (port, _rx) = start_server(
+            mysql::AUTH_NATIVE_PASSWORD,
+            Box::new(|_, resp, _| resp.auth == mysql::calc_password(SALT, PASSWORD.as_bytes())),
+        );
+        let err = connect(port, "wrong").await.unwrap_err();
+        assert!(err.to_string().contains("Access denied"), "{}", err);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_caching_sha2_password_full_auth() -> Result<(), ReplicationError> {
+        let (port, _rx) = start_server(
+            mysql::AUTH_CACHING_SHA2_PASSWORD,
+            Box::new(|conn, _, sequence| {
+                write_packet(
+                    conn,
+                    sequence,
+                    &[mysql::MORE_DATE_HEADER, mysql::CACHE_SHA2_FULL_AUTH],
+                );
+
+                // the connection is not secure, the client requests the public key
+                if read_packet(conn, sequence) != vec![2] {
+                    return false;
+                }
+                let (rsa, pem) = rsa_key();
+                let mut data = vec![mysql::MORE_DATE_HEADER];
+                data.extend(pem);
+                write_packet(conn, sequence, &data);
+
+                let enc = read_packet(conn, sequence);
+                decrypt_password(&rsa, &enc) == password_with_nul()
+            }),
+        );
+
+        let mut c = connect(port, PASSWORD).await?;
+
+        c.close().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_auth_switch_to_clear_password() -> Result<(), ReplicationError> {
+        let (port, _rx) = start_server(
+            mysql::AUTH_NATIVE_PASSWORD,
+            Box::new(|conn, _, sequence| {
+                let mut data = vec![mysql::EOF_HEADER];
+                data.extend(mysql::AUTH_CLEAR_PASSWORD.as_bytes());
+                data.push(0);
+                write_packet(conn, sequence, &data);
+
+                read_packet(conn, sequence) == password_with_nul()
+            }),
+        );
+
+        let mut c = connect(port, PASSWORD).await?;
+
+        c.close().await?;
+        Ok(())
+    }
+
+    async fn check_execute(deprecate_eof: bool) -> Result<(), ReplicationError> {
+        let port = start_query_server(deprecate_eof);
+        let mut c = connect(port, PASSWORD).await?;
+
+        let r = c.execute("SELECT id, name FROM t").await?;
+        let rs = r.result_set.as_ref().unwrap();
+        assert_eq!(rs.fields.len(), 2);
+        assert_eq!(rs.values.len(), 2);
+        assert_eq!(rs.get_string(0, 1)?, "a");
+        assert_eq!(rs.get_string_by_name(1, "id")?, "2");
+        assert_eq!(r.warnings, 1);
+        assert_eq!(r.status, 0x22);
+
+        let r = c.execute("INSERT INTO t VALUES (3, 'c')").await?;
+        assert!(r.result_set.is_none());
+        assert_eq!(r.affected_rows, 3);
+        assert_eq!(r.insert_id, 9);
+
+        match c.execute("BAD").await {
+            Err(ReplicationError::MyError(e)) => assert_eq!(e.code, 1064),
+            rs => panic!("expect MyError, got {:?}", rs),
+        }
+
+        // the connection is still usable after an ERR packet
+        let r = c.execute("SELECT id, name FROM t").await?;
+        assert_eq!(r.result_set.unwrap().values.len(), 2);
+
+        for query in ["EMPTY", "EMPTY ROW"] {
+            let err = c.execute(query).await.unwrap_err();
+            assert!(err
+                .to_string()
+                .contains(&MysqlError::ErrMalformPacket.to_string()));
+        }
+
+        c.close().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_execute() -> Result<(), ReplicationError> {
+        check_execute(false).await
+    }
+
+    #[tokio::test]
+    async fn test_execute_with_deprecate_eof() -> Result<(), ReplicationError> {
+        check_execute(true).await
+    }
+
+    async fn connect_tls(
+        host: &str,
+        port: u16,
+        ca: &Cert,
+    ) -> Result<client::AsyncConn, ReplicationError> {
+        let config = mysql::new_client_tls_config(
+            &ca.cert.to_pem().unwrap(),
+            b"",
+            b"",
+            mysql::SslMode::VerifyIdentity,
+        )?;
+        let option: Box<client::AsyncConnOption> =
+            Box::new(move |c| c.set_tls_config(config.clone()));
+
+        client::connect_async(
+            &format!("{}:{}", host, port),
+            USER,
+            PASSWORD,
+            "test",
+            &[option],
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_tls() -> Result<(), ReplicationError> {
+        let ca = new_cert(None);
+        let (port, rx) = start_tls_server(&new_cert(Some(&ca)));
+
+        let mut c = connect_tls("localhost", port, &ca).await?;
+        assert!(c.is_tls());
+        let response = rx.recv().unwrap();
+        assert!(response.capability & mysql::CLIENT_SSL > 0);
+        // sha256_password sends the password in clear text over TLS
+        assert_eq!(response.auth, password_with_nul());
+
+        let r = c.execute("SET NAMES utf8mb4").await?;
+        assert_eq!(
+            r.status & mysql::SERVER_STATUS_AUTOCOMMIT,
+            mysql::SERVER_STATUS_AUTOCOMMIT
+        );
+
+        // a clone waits to read while the command is written through the same TLS session
+        let mut reader = c.try_clone_packet_conn()?;
+        let handle = tokio::spawn(async move {
+            reader.sequence = 1;
+            reader.read_packet().await
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let mut data = vec![0_u8; 4];
+        data.push(mysql::COM_QUERY);
+        data.extend(b"SELECT 1");
+        c.reset_sequence();
+        c.write_packet(&mut data).await?;
+        assert_eq!(handle.await.unwrap()?[0], mysql::OK_HEADER);
+
+        c.close().await?;
+
+        // the certificate is issued for localhost only
+        let err = connect_tls("127.0.0.1", port, &ca).await.unwrap_err();
+        assert!(err.to_string().contains("TLS handshake failed"), "{}", err);
+
+        Ok(())
+    }
+
+    async fn check_compression(cap: u32, compression: u8) -> Result<(), ReplicationError> {
+        let (port, rx, commands) = start_compress_server(cap, compression);
+
+        let option: Box<client::AsyncConnOption> = Box::new(move |c| c.set_capability(cap));
+        let mut c = client::connect_async(
+            &format!("127.0.0.1:{}", port),
+            USER,
+            PASSWORD,
+            "test",
+            &[option],
+        )
+        .await?;
+        assert_eq!(rx.recv().unwrap().capability & cap, cap);
+        assert_eq!(c.get_compression(), compression);
+
+        // the query is long enough to be compressed
+        let query = format!("SELECT '{}'", "a".repeat(200));
+        let _ = c.execute(&query).await?;
+        let _ = c.execute("SELECT 1").await?;
+
+        assert_eq!(
+            commands.recv().unwrap(),
+            [&[mysql::COM_QUERY], query.as_bytes()].concat()
+        );
+        assert_eq!(commands.recv().unwrap(), b"\x03SELECT 1");
+
+        c.close().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_compress_zlib() -> Result<(), ReplicationError> {
+        check_compression(mysql::CLIENT_COMPRESS, mysql::MYSQL_COMPRESS_ZLIB).await
+    }
+
+    #[tokio::test]
+    async fn test_compress_zstd() -> Result<(), ReplicationError> {
+        check_compression(
+            mysql::CLIENT_ZSTD_COMPRESSION_ALGORITHM,
+            mysql::MYSQL_COMPRESS_ZSTD,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_unix_socket_with_dialer() -> Result<(), ReplicationError> {
+        let path = std::env::temp_dir().join(format!(
+            "async_conn_test_{}_mysqld.sock",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        thread::spawn(move || {
+            for _ in 0..2 {
+                let (mut conn, _) = listener.accept().unwrap();
+                let mut sequence = 0_u8;
+                write_initial_handshake(&mut conn, &mut sequence, mysql::AUTH_NATIVE_PASSWORD, 0);
+                let _ = read_packet(&mut conn, &mut sequence);
+                write_ok(&mut conn, &mut sequence);
+
+                while read_command(&mut conn).is_some() {
+                    let mut sequence = 1_u8;
+                    write_ok(&mut conn, &mut sequence);
+                }
+            }
+        });
+
+        let addr = path.to_str().unwrap();
+        let mut c = client::connect_async(addr, USER, PASSWORD, "test", &[]).await?;
+        c.execute("SET NAMES utf8mb4").await?;
+        c.close().await?;
+
+        // the blocking dialer runs on the blocking threads, its stream is converted
+        let dialer: Arc<client::Dialer> = Arc::new(client::dial);
+        let mut c =
+            client::connect_async_with_dialer(addr, USER, PASSWORD, "test", dialer, &[]).await?;
+        c.execute("SET NAMES utf8mb4").await?;
+        c.close().await?;
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_timeout() -> Result<(), ReplicationError> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut sequence = 0_u8;
+            write_initial_handshake(&mut conn, &mut sequence, mysql::AUTH_NATIVE_PASSWORD, 0);
+            let _ = read_packet(&mut conn, &mut sequence);
+            write_ok(&mut conn, &mut sequence);
+
+            // the commands are never answered
+            while read_command(&mut conn).is_some() {}
+        });
+
+        let option: Box<client::AsyncConnOption> = Box::new(|c| {
+            c.set_read_timeout(Some(Duration::from_millis(100)))
+                .unwrap()
+        });
+        let mut c = client::connect_async(
+            &format!("127.0.0.1:{}", port),
+            USER,
+            PASSWORD,
+            "test",
+            &[option],
+        )
+        .await?;
+
+        let err = c.execute("SELECT 1").await.unwrap_err();
+        assert!(err.is_timeout(), "{}", err);
+
+        c.close().await?;
+        Ok(())
+    }
+}
@@ -1,18 +1,27 @@
-use crate::error::ReplicationError;
+use crate::error::{MyError, MysqlError, ReplicationError};
+use crate::mysql;
 use crate::mysql::result::MysqlResult;
-use crate::mysql::FieldValue;
+use crate::mysql::resultset::{ResultSet, StreamingType};
+use crate::mysql::{Field, FieldValue, RowData};
+use crate::packet;
+use byteorder::{ByteOrder, LittleEndian};
 use std::collections::HashMap;
 use std::net;
+use std::net::ToSocketAddrs;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Debug, Default)]
 pub struct Conn {
-    _conn: Option<net::TcpStream>,
+    _conn: Option<packet::Conn>,
 
     _user: String,
     _password: String,
     _db: String,
     _tls_config: Option<rustls::ClientConfig>,
     _proto: String,
+    // the host of the address, the TLS certificate of the server is verified against it
+    _host: String,
 
     _server_version: String,
     // server capabilities
@@ -27,32 +36,48 @@ pub struct Conn {
     _connection_id: u32,
 }
 
+// the zstd compression level sent in the handshake response, the default of the MySQL clients
+const ZSTD_COMPRESSION_LEVEL: u8 = 3;
+
+// SSL_REQUEST_PACKET_LEN is the length of the SSLRequest packet with its header,
+// the capability flags, max packet size, charset and filler the handshake response starts with.
+pub const SSL_REQUEST_PACKET_LEN: usize = 4 + 4 + 4 + 1 + 23;
+
 // This function will be called for every row in resultset from ExecuteSelectStreaming.
-pub type SelectPerRowCallback = dyn Fn(Vec<FieldValue>) -> Result<(), ReplicationError>;
+pub type SelectPerRowCallback<'a> = dyn FnMut(Vec<FieldValue>) -> Result<(), ReplicationError> + 'a;
 
 // This function will be called once per result from ExecuteSelectStreaming
-pub type SelectPerResultCallback = dyn Fn(&MysqlResult) -> Result<(), ReplicationError>;
+pub type SelectPerResultCallback<'a> = dyn FnMut(&MysqlResult) -> Result<(), ReplicationError> + 'a;
 
 // This function will be called once per result from ExecuteMultiple
 pub type ExecPerResultCallback = dyn Fn(&MysqlResult, Result<(), ReplicationError>);
 
-fn get_net_proto(addr: &str) -> String {
-    let mut proto = "tcp";
-    if proto.contains("/") {
-        proto = "unix";
-    }
+// ConnOption is called on the new Conn before the handshake, see connect.
+pub type ConnOption = dyn Fn(&mut Conn);
 
-    proto.to_string()
+// NextPacket returns the next packet of a result set, read from the connection or already read.
+type NextPacket<'a> = dyn FnMut(&mut Conn) -> Result<Vec<u8>, ReplicationError> + 'a;
+
+fn auth_plugin_allowed(plugin_name: &str) -> bool {
+    [
+        mysql::AUTH_NATIVE_PASSWORD,
+        mysql::AUTH_SHA256_PASSWORD,
+        mysql::AUTH_CACHING_SHA2_PASSWORD,
+        mysql::AUTH_CLEAR_PASSWORD,
+    ]
+    .contains(&plugin_name)
 }
-/*
-func getNetProto(addr string) string {
-    proto := "tcp"
-    if strings.Contains(addr, "/") {
-        proto = "unix"
+
+// the host part of addr, without the port and the brackets of an IPv6 address
+fn get_host(addr: &str) -> String {
+    match addr.rsplit_once(':') {
+        Some((host, port)) if port.parse::<u16>().is_ok() => host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string(),
+        _ => addr.to_string(),
     }
-    return proto
 }
- */
 
 // Connect to a MySQL server, addr can be ip:port, or a unix socket domain like /var/sock.
 // Accepts a series of configuration functions as a variadic argument.
@@ -61,21 +86,53 @@ pub fn connect(
     user: &str,
     password: &str,
     db_name: &str,
+    options: &[Box<ConnOption>],
 ) -> Result<Conn, ReplicationError> {
-    /*
-       ctx, cancel := context.WithTimeout(context.Background(), time.Second*10)
-       defer cancel()
+    connect_with_dialer(addr, user, password, db_name, &dial, options)
+}
+
+// Dialer connects to the address on the named network ("tcp" or "unix") using the provided context.
+pub type Dialer = dyn Fn(tokio_context::context::Context, &str, &str) -> Result<packet::Stream, ReplicationError>
+    + Send
+    + Sync;
+
+// dial is the default Dialer, it gives up connecting over TCP after 10 seconds.
+pub fn dial(
+    _ctx: tokio_context::context::Context,
+    network: &str,
+    addr: &str,
+) -> Result<packet::Stream, ReplicationError> {
+    if network == "unix" {
+        return dial_unix(addr);
+    }
+
+    let mut last_err = ReplicationError::new(format!("can not resolve address {}", addr));
+    for socket_addr in addr.to_socket_addrs()? {
+        match net::TcpStream::connect_timeout(&socket_addr, Duration::from_secs(10)) {
+            Ok(conn) => return Ok(packet::Stream::Tcp(conn)),
+            Err(e) => last_err = ReplicationError::from(e),
+        }
+    }
 
-       dialer := &net.Dialer{}
+    Err(last_err)
+}
+
+#[cfg(unix)]
+fn dial_unix(addr: &str) -> Result<packet::Stream, ReplicationError> {
+    let conn = std::os::unix::net::UnixStream::connect(addr).map_err(|e| {
+        ReplicationError::new(format!("connect unix socket {} failed: {}", addr, e))
+    })?;
 
-       return ConnectWithDialer(ctx, "", addr, user, password, dbName, dialer.DialContext, options...)
-    */
-    Err(ReplicationError::new("".to_string()))
+    Ok(packet::Stream::Unix(conn))
 }
 
-// Dialer connects to the address on the named network using the provided context.
-pub type Dialer =
-    dyn Fn(tokio_context::context::Context, &str, &str) -> Result<net::TcpStream, ReplicationError>;
+#[cfg(not(unix))]
+fn dial_unix(addr: &str) -> Result<packet::Stream, ReplicationError> {
+    Err(ReplicationError::new(format!(
+        "unix socket {} is not supported on this platform",
+        addr
+    )))
+}
 
 // Connect to a MySQL server using the given Dialer.
 pub fn connect_with_dialer(
@@ -83,92 +140,1071 @@ pub fn connect_with_dialer(
     user: &str,
     password: &str,
     db_name: &str,
-    options: &Vec<Box<dyn Fn(&Conn)>>,
+    dialer: &Dialer,
+    options: &[Box<ConnOption>],
 ) -> Result<Conn, ReplicationError> {
-    let mut c = Conn::default();
-    c._attributes
-        .insert(String::from("_client_name"), String::from("rs_mysql"));
-    c._attributes
-        .insert(String::from("_os"), String::from(std::env::consts::OS));
-    c._attributes.insert(
-        String::from("_platform"),
-        String::from(std::env::consts::ARCH),
-    );
-    c._attributes.insert(
-        String::from("_runtime_version"),
-        rustc_version::version().unwrap().to_string(),
-    );
-
-    let proto = get_net_proto(addr);
-
-    let conn = net::TcpStream::connect(addr)?;
-    c._user = user.to_string();
-    c._password = password.to_string();
-    c._db = db_name.to_string();
-    c._proto = proto;
+    let mut c = Conn::new(addr, user, password, db_name);
+
+    let (ctx, _handle) = tokio_context::context::Context::new();
+    let conn = dialer(ctx, &c._proto, addr)?;
+    c._conn = Some(packet::Conn::new(conn));
+
+    // Apply configuration functions.
+    for option in options {
+        option(&mut c);
+    }
+
+    c._handshake()?;
 
     Ok(c)
 }
-/*
-func ConnectWithDialer(ctx context.Context, network string, addr string, user string, password string, dbName string, dialer Dialer, options ...func(*Conn)) (*Conn, error) {
-    c.user = user
-    c.password = password
-    c.db = dbName
-    c.proto = network
-    c.Conn = packet.NewConn(conn)
 
-    // use default charset here, utf-8
-    c.charset = DEFAULT_CHARSET
+impl Conn {
+    // New returns the state of a connection to addr before the handshake, without a transport.
+    // connect sets the transport, client::AsyncConn runs the handshake with its own.
+    pub fn new(addr: &str, user: &str, password: &str, db_name: &str) -> Conn {
+        let mut c = Conn::default();
+        c._attributes
+            .insert(String::from("_client_name"), String::from("rs_mysql"));
+        c._attributes
+            .insert(String::from("_os"), String::from(std::env::consts::OS));
+        c._attributes.insert(
+            String::from("_platform"),
+            String::from(std::env::consts::ARCH),
+        );
+        // RUSTC_VERSION is set by build.rs
+        if let Some(version) = option_env!("RUSTC_VERSION") {
+            c._attributes
+                .insert(String::from("_runtime_version"), String::from(version));
+        }
 
-    // Apply configuration functions.
-    for i := range options {
-        options[i](c)
+        c._user = user.to_string();
+        c._password = password.to_string();
+        c._db = db_name.to_string();
+        c._proto = mysql::get_net_proto(addr);
+        c._host = get_host(addr);
+
+        // use default charset here, utf-8
+        c._charset = mysql::DEFAULT_CHARSET.to_string();
+
+        c
     }
 
-    if c.tlsConfig != nil {
-        seq := c.Conn.Sequence
-        c.Conn = packet.NewTLSConn(conn)
-        c.Conn.Sequence = seq
+    fn _handshake(&mut self) -> Result<(), ReplicationError> {
+        if let Err(e) = self._read_initial_handshake() {
+            let _ = self.close();
+            return Err(ReplicationError::new(format!(
+                "readInitialHandshake: {}",
+                e
+            )));
+        }
+
+        if let Err(e) = self._write_auth_handshake() {
+            let _ = self.close();
+            return Err(ReplicationError::new(format!("writeAuthHandshake: {}", e)));
+        }
+
+        if let Err(e) = self._handle_auth_result() {
+            let _ = self.close();
+            return Err(ReplicationError::new(format!("handleAuthResult: {}", e)));
+        }
+
+        // Switch to compression mode
+        let compression = self.get_compression();
+        self._packet_conn()?.compression = compression;
+
+        // the TLS handshake is done, the transport can be read through a buffer
+        self._packet_conn()?.use_buffered_reader();
+
+        Ok(())
     }
 
-    if err = c.handshake(); err != nil {
-        return nil, errors.Trace(err)
+    fn _read_initial_handshake(&mut self) -> Result<(), ReplicationError> {
+        let data = self.read_packet()?;
+
+        self.parse_initial_handshake(&data)
     }
 
-    if c.ccaps&CLIENT_COMPRESS > 0 {
-        c.Conn.Compression = MYSQL_COMPRESS_ZLIB
-    } else if c.ccaps&CLIENT_ZSTD_COMPRESSION_ALGORITHM > 0 {
-        c.Conn.Compression = MYSQL_COMPRESS_ZSTD
+    // ParseInitialHandshake reads the server version, the connection id, the capabilities, the salt and
+    // the auth plugin of the initial handshake packet.
+    // See: http://dev.mysql.com/doc/internals/en/connection-phase-packets.html#packet-Protocol::Handshake
+    pub fn parse_initial_handshake(&mut self, data: &[u8]) -> Result<(), ReplicationError> {
+        if data.is_empty() {
+            return Err(ReplicationError::from(MysqlError::ErrMalformPacket));
+        }
+
+        if data[0] == mysql::ERR_HEADER {
+            return Err(ReplicationError::new(
+                "read initial handshake error".to_string(),
+            ));
+        }
+
+        if data[0] < mysql::MIN_PPOTOCOL_VERSION {
+            return Err(ReplicationError::new(format!(
+                "invalid protocol version {}, must >= 10",
+                data[0]
+            )));
+        }
+
+        // skip mysql version
+        // mysql version end with 0x00
+        let version_end = data[1..]
+            .iter()
+            .position(|&b| b == 0x00)
+            .ok_or(ReplicationError::from(MysqlError::ErrMalformPacket))?;
+        self._server_version = String::from_utf8_lossy(&data[1..1 + version_end]).to_string();
+        let mut pos = 1 + version_end + 1;
+
+        // connection id, the first 8 bytes of the salt, filter and the lower capability flags
+        if data.len() < pos + 4 + 8 + 1 + 2 {
+            return Err(ReplicationError::from(MysqlError::ErrMalformPacket));
+        }
+
+        // connection id length is 4
+        self._connection_id = LittleEndian::read_u32(&data[pos..pos + 4]);
+        pos += 4;
+
+        self._salt = data[pos..pos + 8].to_vec();
+        pos += 8;
+
+        // skip filter
+        pos += 1;
+
+        // capability lower 2 bytes
+        self._capability = LittleEndian::read_u16(&data[pos..pos + 2]) as u32;
+        // check protocol
+        if self._capability & mysql::CLIENT_PROTOCOL_41 == 0 {
+            return Err(ReplicationError::new(
+                "the MySQL server can not support protocol 41 and above required by the client"
+                    .to_string(),
+            ));
+        }
+        pos += 2;
+
+        if data.len() > pos {
+            // charset, status, the upper capability flags, auth data len, reserved and the rest of the salt
+            if data.len() < pos + 1 + 2 + 2 + 1 + 10 + 12 {
+                return Err(ReplicationError::from(MysqlError::ErrMalformPacket));
+            }
+
+            // skip server charset
+            pos += 1;
+
+            self._status = LittleEndian::read_u16(&data[pos..pos + 2]);
+            pos += 2;
+
+            // capability flags (upper 2 bytes)
+            self._capability |= (LittleEndian::read_u16(&data[pos..pos + 2]) as u32) << 16;
+            pos += 2;
+
+            // skip auth data len or [00]
+            // skip reserved (all [00])
+            pos += 10 + 1;
+
+            // The documentation is ambiguous about the length.
+            // The official Python library uses the fixed length 12
+            // mysql-proxy also use 12
+            // which is not documented but seems to work.
+            self._salt.extend_from_slice(&data[pos..pos + 12]);
+            pos += 13;
+
+            // auth plugin
+            let plugin = &data[pos.min(data.len())..];
+            if let Some(end) = plugin.iter().position(|&b| b == 0x00) {
+                self._auth_plugin_name = String::from_utf8_lossy(&plugin[..end]).to_string();
+            } else {
+                self._auth_plugin_name = String::from_utf8_lossy(plugin).to_string();
+            }
+        }
+
+        // if server gives no default auth plugin name, use a client default
+        if self._auth_plugin_name.is_empty() {
+            self._auth_plugin_name = mysql::AUTH_NATIVE_PASSWORD.to_string();
+        }
+
+        if !auth_plugin_allowed(&self._auth_plugin_name) {
+            return Err(ReplicationError::new(format!(
+                "unknown auth plugin name '{}'",
+                self._auth_plugin_name
+            )));
+        }
+
+        Ok(())
     }
 
-    return c, nil
-}
+    // IsSecureConnection tells whether the full password can be sent as clear text, over TLS or a unix socket.
+    pub fn is_secure_connection(&self) -> bool {
+        self._tls_config.is_some() || self._proto == "unix"
+    }
 
-func (c *Conn) handshake() error {
-    var err error
-    if err = c.readInitialHandshake(); err != nil {
-        c.Close()
-        return errors.Trace(fmt.Errorf("readInitialHandshake: %w", err))
+    // generate auth response data according to auth plugin
+    //
+    // NOTE: the returned boolean value indicates whether to add a \NUL to the end of data.
+    // it is quite tricky because MySQL server expects different formats of responses in different auth situations.
+    // here the \NUL needs to be added when sending back the empty password or cleartext password in 'sha256_password'
+    // authentication.
+    fn _gen_auth_response(&self, auth_data: &[u8]) -> Result<(Vec<u8>, bool), ReplicationError> {
+        // password hashing
+        match self._auth_plugin_name.as_str() {
+            mysql::AUTH_NATIVE_PASSWORD => Ok((
+                mysql::calc_password(
+                    &auth_data[..20.min(auth_data.len())],
+                    self._password.as_bytes(),
+                ),
+                false,
+            )),
+            mysql::AUTH_CACHING_SHA2_PASSWORD => Ok((
+                mysql::calc_caching_sha2_password(auth_data, self._password.as_bytes()),
+                false,
+            )),
+            mysql::AUTH_CLEAR_PASSWORD => Ok((self._password.as_bytes().to_vec(), true)),
+            mysql::AUTH_SHA256_PASSWORD => {
+                if self._password.is_empty() {
+                    return Ok((vec![], true));
+                }
+                if self.is_secure_connection() {
+                    // write cleartext auth packet
+                    // see: https://dev.mysql.com/doc/refman/8.0/en/sha256-pluggable-authentication.html
+                    Ok((self._password.as_bytes().to_vec(), true))
+                } else {
+                    // request public key from server
+                    // see: https://dev.mysql.com/doc/internals/en/public-key-retrieval.html
+                    Ok((vec![1], false))
+                }
+            }
+            // not reachable
+            _ => Err(ReplicationError::new(format!(
+                "auth plugin '{}' is not supported",
+                self._auth_plugin_name
+            ))),
+        }
     }
 
-    if err := c.writeAuthHandshake(); err != nil {
-        c.Close()
+    fn _write_auth_handshake(&mut self) -> Result<(), ReplicationError> {
+        let mut data = self.gen_auth_handshake()?;
+
+        // SSL Connection Request Packet
+        // http://dev.mysql.com/doc/internals/en/connection-phase-packets.html#packet-Protocol::SSLRequest
+        if let Some(config) = self._tls_config.clone() {
+            // Send TLS / SSL request packet
+            self.write_packet(&mut data[..SSL_REQUEST_PACKET_LEN].to_vec())?;
+
+            // Switch to TLS, the sequence goes on
+            let host = self._host.clone();
+            self._packet_conn()?.upgrade_tls(Arc::new(config), &host)?;
+        }
 
-        return errors.Trace(fmt.Errorf("writeAuthHandshake: %w", err))
+        self.write_packet(&mut data)
     }
 
-    if err := c.handleAuthResult(); err != nil {
-        c.Close()
-        return errors.Trace(fmt.Errorf("handleAuthResult: %w", err))
+    // GenAuthHandshake returns the handshake response packet, its header is filled by write_packet.
+    // The capabilities of the connection are the ones it asks for from then on. With a TLS config its first
+    // SSL_REQUEST_PACKET_LEN bytes are the SSLRequest packet, which is sent before the TLS handshake.
+    // See: http://dev.mysql.com/doc/internals/en/connection-phase-packets.html#packet-Protocol::HandshakeResponse
+    pub fn gen_auth_handshake(&mut self) -> Result<Vec<u8>, ReplicationError> {
+        // Adjust client capability flags based on server support
+        let mut capability = mysql::CLIENT_PROTOCOL_41
+            | mysql::CLIENT_SECURE_CONNECTION
+            | mysql::CLIENT_LONG_PASSWORD
+            | mysql::CLIENT_TRANSACTIONS
+            | mysql::CLIENT_PLUGIN_AUTH
+            | self._capability & mysql::CLIENT_LONG_FLAG
+            | self._capability & mysql::CLIENT_DEPRECATE_EOF;
+        // Adjust client capability flags on specific client requests
+        // Only flags that would make any sense setting and aren't handled elsewhere
+        // in the library are supported here
+        capability |= self._ccaps & mysql::CLIENT_FOUND_ROWS
+            | self._ccaps & mysql::CLIENT_IGNORE_SPACE
+            | self._ccaps & mysql::CLIENT_MULTI_STATEMENTS
+            | self._ccaps & mysql::CLIENT_MULTI_RESULTS
+            | self._ccaps & mysql::CLIENT_PS_MULTI_RESULTS
+            | self._ccaps & mysql::CLIENT_CONNECT_ATTRS;
+        // the compression is used only if the server supports it too, zlib is preferred like the MySQL clients do
+        if self._ccaps & self._capability & mysql::CLIENT_COMPRESS > 0 {
+            capability |= mysql::CLIENT_COMPRESS;
+        } else if self._ccaps & self._capability & mysql::CLIENT_ZSTD_COMPRESSION_ALGORITHM > 0 {
+            capability |= mysql::CLIENT_ZSTD_COMPRESSION_ALGORITHM;
+        }
+
+        let salt = self._salt.clone();
+        let (mut auth, add_nul) = self._gen_auth_response(&salt)?;
+        // the \NUL is part of the auth data, the server reads the db name right after it
+        if add_nul {
+            auth.push(0x00);
+        }
+
+        let attr_data = self._gen_attributes();
+        if !attr_data.is_empty() && self._capability & mysql::CLIENT_CONNECT_ATTRS > 0 {
+            capability |= mysql::CLIENT_CONNECT_ATTRS;
+        }
+
+        // encode length of the auth plugin data
+        // here we use the Length-Encoded-Integer(LEI) as the data length may not fit into one byte
+        // see: https://dev.mysql.com/doc/internals/en/integer.html#length-encoded-integer
+        let auth_resp_lei = mysql::put_length_encoded_int(auth.len() as u64);
+        if auth_resp_lei.len() > 1 {
+            // if the length can not be written in 1 byte, it must be written as a
+            // length encoded integer
+            capability |= mysql::CLIENT_PLUGIN_AUTH_LENENC_CLIENT_DATA;
+        }
+
+        if !self._db.is_empty() {
+            capability |= mysql::CLIENT_CONNECT_WITH_DB;
+        }
+
+        if self._tls_config.is_some() {
+            if self._capability & mysql::CLIENT_SSL == 0 {
+                return Err(ReplicationError::new(
+                    "the MySQL server does not support TLS".to_string(),
+                ));
+            }
+            capability |= mysql::CLIENT_SSL;
+        }
+
+        // packet header, the 4 bytes are filled by write_packet
+        let mut data = vec![0_u8; 4];
+
+        // capability [32 bit]
+        data.extend(mysql::uint32_to_bytes(capability));
+        // MaxPacketSize [32 bit] (none)
+        data.extend([0_u8; 4]);
+        // Charset [1 byte]
+        // use default collation id 33 here, is utf-8
+        data.push(mysql::DEFAULT_COLLATION_ID);
+        // Filler [23 bytes] (all 0x00)
+        data.extend([0_u8; 23]);
+
+        // User [null terminated string]
+        if !self._user.is_empty() {
+            data.extend(self._user.as_bytes());
+        }
+        data.push(0x00);
+
+        // auth [length encoded integer]
+        data.extend(auth_resp_lei);
+        data.extend(&auth);
+
+        // db [null terminated string]
+        if !self._db.is_empty() {
+            data.extend(self._db.as_bytes());
+            data.push(0x00);
+        }
+
+        // Assume native client during response
+        data.extend(self._auth_plugin_name.as_bytes());
+        data.push(0x00);
+
+        // connection attributes
+        if capability & mysql::CLIENT_CONNECT_ATTRS > 0 {
+            data.extend(attr_data);
+        }
+
+        // zstd_compression_level [1 byte]
+        if capability & mysql::CLIENT_ZSTD_COMPRESSION_ALGORITHM > 0 {
+            data.push(ZSTD_COMPRESSION_LEVEL);
+        }
+
+        self._capability = capability;
+        Ok(data)
     }
 
-    return nil
-}
+    // genAttributes encodes the connection attributes as a length encoded block of key/value pairs
+    fn _gen_attributes(&self) -> Vec<u8> {
+        if self._attributes.is_empty() {
+            return vec![];
+        }
+
+        let mut kv_data = vec![];
+        for (k, v) in &self._attributes {
+            kv_data.extend(mysql::put_length_encoded_string(k.as_bytes()));
+            kv_data.extend(mysql::put_length_encoded_string(v.as_bytes()));
+        }
+
+        let mut attr_data = mysql::put_length_encoded_int(kv_data.len() as u64);
+        attr_data.extend(kv_data);
+        attr_data
+    }
+
+    fn _read_auth_result(&mut self) -> Result<(Vec<u8>, String), ReplicationError> {
+        let data = self
+            .read_packet()
+            .map_err(|e| ReplicationError::new(format!("ReadPacket: {}", e)))?;
+
+        self.parse_auth_result(&data)
+    }
+
+    // ParseAuthResult returns the auth data of an auth result packet, and the plugin the server switches to if any.
+    pub fn parse_auth_result(
+        &mut self,
+        data: &[u8],
+    ) -> Result<(Vec<u8>, String), ReplicationError> {
+        if data.is_empty() {
+            return Err(ReplicationError::from(MysqlError::ErrMalformPacket));
+        }
+
+        // see: https://insidemysql.com/preparing-your-community-connector-for-mysql-8-part-2-sha256/
+        // packet indicator
+        match data[0] {
+            mysql::OK_HEADER => {
+                let _ = self.handle_ok_packet(data);
+                Ok((vec![], String::new()))
+            }
+            mysql::MORE_DATE_HEADER => Ok((data[1..].to_vec(), String::new())),
+            mysql::EOF_HEADER => {
+                // server wants to switch auth
+                if data.len() == 1 {
+                    // https://dev.mysql.com/doc/internals/en/connection-phase-packets.html#packet-Protocol::OldAuthSwitchRequest
+                    return Ok((vec![], mysql::AUTH_MYSQL_OLD_PASSWORD.to_string()));
+                }
+                let plugin_end_index = data
+                    .iter()
+                    .position(|&b| b == 0x00)
+                    .ok_or(ReplicationError::new("invalid packet".to_string()))?;
+                let plugin = String::from_utf8_lossy(&data[1..plugin_end_index]).to_string();
+                let auth_data = data[plugin_end_index + 1..].to_vec();
+                Ok((auth_data, plugin))
+            }
+            // Error otherwise
+            _ => Err(self.handle_error_packet(data)),
+        }
+    }
+
+    // SwitchAuthPlugin switches to the plugin of an auth switch request, auth_data is the new salt.
+    // Returns the auth response and whether a \NUL must be added to it, see gen_auth_response.
+    pub fn switch_auth_plugin(
+        &mut self,
+        plugin: String,
+        auth_data: &[u8],
+    ) -> Result<(Vec<u8>, bool), ReplicationError> {
+        if auth_data.len() >= 20 {
+            // get new salt
+            self._salt = auth_data[..20].to_vec();
+        }
+        self._auth_plugin_name = plugin;
+
+        let salt = self._salt.clone();
+        self._gen_auth_response(&salt)
+    }
+
+    fn _handle_auth_result(&mut self) -> Result<(), ReplicationError> {
+        let (mut auth_data, switch_to_plugin) = self._read_auth_result()?;
+
+        // handle auth switch, only support 'sha256_password', and 'caching_sha2_password'
+        if !switch_to_plugin.is_empty() {
+            let (auth, add_nul) = self.switch_auth_plugin(switch_to_plugin, &auth_data)?;
+            self._packet_conn()?
+                .write_auth_switch_packet(&auth, add_nul)?;
+
+            // Read Result Packet
+            let (data, switch_to_plugin) = self._read_auth_result()?;
+
+            // Do not allow to change the auth plugin more than once
+            if !switch_to_plugin.is_empty() {
+                return Err(ReplicationError::new(
+                    "can not switch auth plugin more than once".to_string(),
+                ));
+            }
+            auth_data = data;
+        }
+
+        match self._auth_plugin_name.as_str() {
+            // handle caching_sha2_password
+            mysql::AUTH_CACHING_SHA2_PASSWORD => {
+                if auth_data.is_empty() {
+                    // auth already succeeded
+                    return Ok(());
+                }
+
+                match auth_data[0] {
+                    mysql::CACHE_SHA2_FAST_AUTH => {
+                        let _ = self._read_ok()?;
+                        Ok(())
+                    }
+                    mysql::CACHE_SHA2_FULL_AUTH => {
+                        // need full authentication
+                        let password = self._password.clone();
+                        if self.is_secure_connection() {
+                            self._packet_conn()?.write_clear_auth_packet(&password)?;
+                        } else {
+                            let salt = self._salt.clone();
+                            self._packet_conn()?
+                                .write_public_key_auth_packet(&password, &salt)?;
+                        }
+                        let _ = self._read_ok()?;
+                        Ok(())
+                    }
+                    _ => Err(ReplicationError::new(format!(
+                        "invalid packet {:x}",
+                        auth_data[0]
+                    ))),
+                }
+            }
+            mysql::AUTH_SHA256_PASSWORD => {
+                if auth_data.is_empty() {
+                    // auth already succeeded
+                    return Ok(());
+                }
+
+                let pub_key = openssl::pkey::PKey::public_key_from_pem(&auth_data)?;
+                // send encrypted password
+                let password = self._password.clone();
+                let salt = self._salt.clone();
+                self._packet_conn()?
+                    .write_encrypted_password(&password, &salt, &pub_key)?;
+                let _ = self._read_ok()?;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn close(&mut self) -> Result<(), ReplicationError> {
+        if let Some(mut c) = self._conn.take() {
+            c.close()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read_packet(&mut self) -> Result<Vec<u8>, ReplicationError> {
+        self._packet_conn()?.read_packet()
+    }
+
+    // ReadPacketInto reads the payload of the next packet into dst, see packet::Conn::read_packet_into.
+    pub fn read_packet_into(&mut self, dst: &mut Vec<u8>) -> Result<(), ReplicationError> {
+        self._packet_conn()?.read_packet_into(dst)
+    }
+
+    pub fn write_packet(&mut self, data: &mut [u8]) -> Result<(), ReplicationError> {
+        self._packet_conn()?.write_packet(data)
+    }
+
+    // SetReadTimeout sets the time a packet has to be read in, see packet::Conn::set_read_timeout.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<(), ReplicationError> {
+        self._packet_conn()?.set_read_timeout(timeout)
+    }
+
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<(), ReplicationError> {
+        self._packet_conn()?.set_write_timeout(timeout)
+    }
+
+    // SetRecvBufferSize sets SO_RCVBUF of the socket, it's best set before the handshake.
+    pub fn set_recv_buffer_size(&mut self, size: usize) -> Result<(), ReplicationError> {
+        self._packet_conn()?.set_recv_buffer_size(size)
+    }
+
+    pub fn try_clone_packet_conn(&self) -> Result<packet::Conn, ReplicationError> {
+        self._conn
+            .as_ref()
+            .ok_or(ReplicationError::from(MysqlError::ErrBadConn))?
+            .try_clone()
+    }
+
+    pub fn reset_sequence(&mut self) {
+        if let Some(c) = self._conn.as_mut() {
+            c.reset_sequence();
+        }
+    }
+
+    fn _packet_conn(&mut self) -> Result<&mut packet::Conn, ReplicationError> {
+        self._conn
+            .as_mut()
+            .ok_or(ReplicationError::from(MysqlError::ErrBadConn))
+    }
+
+    pub fn get_server_version(&self) -> String {
+        self._server_version.clone()
+    }
+
+    pub fn get_capability(&self) -> u32 {
+        self._capability
+    }
+
+    // GetCompression returns the compression of the packets after the handshake, from the capabilities it asked for.
+    pub fn get_compression(&self) -> u8 {
+        if self._capability & mysql::CLIENT_COMPRESS > 0 {
+            mysql::MYSQL_COMPRESS_ZLIB
+        } else if self._capability & mysql::CLIENT_ZSTD_COMPRESSION_ALGORITHM > 0 {
+            mysql::MYSQL_COMPRESS_ZSTD
+        } else {
+            mysql::MYSQL_COMPRESS_NONE
+        }
+    }
+
+    pub fn get_auth_plugin_name(&self) -> String {
+        self._auth_plugin_name.clone()
+    }
+
+    pub fn get_salt(&self) -> Vec<u8> {
+        self._salt.clone()
+    }
+
+    pub fn get_tls_config(&self) -> Option<rustls::ClientConfig> {
+        self._tls_config.clone()
+    }
+
+    // GetHost returns the host of the address, the TLS certificate of the server is verified against it.
+    pub fn get_host(&self) -> String {
+        self._host.clone()
+    }
+
+    // Execute sends the command with COM_QUERY and reads the OK packet or the text protocol result set.
+    // An ERR packet is returned as ReplicationError::MyError.
+    pub fn execute(&mut self, command: &str) -> Result<MysqlResult, ReplicationError> {
+        self._exec(command)
+    }
+
+    // ExecuteSelectStreaming will call per_row_callback for every row in resultset
+    // WITHOUT saving any row data to MysqlResult.result_set.{values/row_datas} fields.
+    // When given, per_result_callback will be called once per result, after the columns are read.
+    //
+    // ExecuteSelectStreaming should be used only for SELECT queries with a large response resultset for memory preserving.
+    // If a callback returns an error, the rest of the result set is not read and the connection should be closed.
+    pub fn execute_select_streaming(
+        &mut self,
+        command: &str,
+        per_row_callback: &mut SelectPerRowCallback,
+        per_result_callback: Option<&mut SelectPerResultCallback>,
+    ) -> Result<MysqlResult, ReplicationError> {
+        self._write_command_str(mysql::COM_QUERY, command)?;
+
+        self._read_result_streaming(false, per_row_callback, per_result_callback)
+    }
+
+    // SetTLSConfig: use user-specified TLS config, see mysql::new_client_tls_config
+    // pass to options when connect
+    pub fn set_tls_config(&mut self, config: rustls::ClientConfig) {
+        self._tls_config = Some(config);
+    }
 
-func (c *Conn) Close() error {
-    return c.Conn.Close()
+    // SetCapability enables the use of a specific capability, e.g. CLIENT_COMPRESS
+    // pass to options when connect
+    pub fn set_capability(&mut self, cap: u32) {
+        self._ccaps |= cap;
+    }
+
+    // UnsetCapability disables the use of a specific capability
+    pub fn unset_capability(&mut self, cap: u32) {
+        self._ccaps &= !cap;
+    }
+
+    pub fn set_attributes(&mut self, attributes: HashMap<String, String>) {
+        for (k, v) in attributes {
+            self._attributes.insert(k, v);
+        }
+    }
+
+    pub fn set_charset(&mut self, charset: &str) -> Result<(), ReplicationError> {
+        if self._charset == charset {
+            return Ok(());
+        }
+
+        let _ = self._exec(&format!("SET NAMES {}", charset))?;
+        self._charset = charset.to_string();
+        Ok(())
+    }
+
+    pub fn get_charset(&self) -> String {
+        self._charset.clone()
+    }
+
+    pub fn get_connection_id(&self) -> u32 {
+        self._connection_id
+    }
+
+    pub fn handle_ok_packet(&mut self, data: &[u8]) -> MysqlResult {
+        let mut pos = 1_usize;
+        let mut r = MysqlResult::default();
+
+        let (affected_rows, _, n) = mysql::length_encoded_int(&data[pos..]);
+        r.affected_rows = affected_rows;
+        pos += n;
+        let (insert_id, _, n) = mysql::length_encoded_int(&data[pos..]);
+        r.insert_id = insert_id;
+        pos += n;
+
+        if self._capability & mysql::CLIENT_PROTOCOL_41 > 0 && data.len() >= pos + 4 {
+            r.status = LittleEndian::read_u16(&data[pos..]);
+            self._status = r.status;
+            pos += 2;
+
+            //todo:strict_mode, check warnings as error
+            r.warnings = LittleEndian::read_u16(&data[pos..]);
+        } else if self._capability & mysql::CLIENT_TRANSACTIONS > 0 && data.len() >= pos + 2 {
+            r.status = LittleEndian::read_u16(&data[pos..]);
+            self._status = r.status;
+        }
+
+        //new ok package will check CLIENT_SESSION_TRACK too, but I don't support it now.
+
+        //skip info
+        r
+    }
+
+    pub fn handle_error_packet(&self, data: &[u8]) -> ReplicationError {
+        let mut e = MyError {
+            code: 0,
+            message: String::new(),
+            state: String::new(),
+        };
+
+        let mut pos = 1_usize;
+        if data.len() < pos + 2 {
+            return ReplicationError::from(MysqlError::ErrMalformPacket);
+        }
+        e.code = LittleEndian::read_u16(&data[pos..]);
+        pos += 2;
+
+        if self._capability & mysql::CLIENT_PROTOCOL_41 > 0 && data.len() >= pos + 6 {
+            //skip '#'
+            pos += 1;
+            e.state = String::from_utf8_lossy(&data[pos..pos + 5]).to_string();
+            pos += 5;
+        }
+
+        e.message = String::from_utf8_lossy(&data[pos..]).to_string();
+
+        ReplicationError::from(e)
+    }
+
+    pub fn read_ok_packet(&mut self) -> Result<MysqlResult, ReplicationError> {
+        self._read_ok()
+    }
+
+    // ReadResult reads the OK packet or the result set of a command, binary is set for the rows of COM_STMT_EXECUTE.
+    pub fn read_result(&mut self, binary: bool) -> Result<MysqlResult, ReplicationError> {
+        self._read_result(binary)
+    }
+
+    // ReadUntilEOF skips the count definition packets and the EOF packet following them,
+    // there is no EOF packet if CLIENT_DEPRECATE_EOF is set.
+    pub fn read_until_eof(&mut self, count: usize) -> Result<(), ReplicationError> {
+        if self._capability & mysql::CLIENT_DEPRECATE_EOF > 0 {
+            for _ in 0..count {
+                let _ = self.read_packet()?;
+            }
+
+            return Ok(());
+        }
+
+        loop {
+            let data = self.read_packet()?;
+
+            if self.is_eof_packet(&data) {
+                return Ok(());
+            }
+        }
+    }
+
+    fn _read_ok(&mut self) -> Result<MysqlResult, ReplicationError> {
+        let data = self.read_packet()?;
+
+        match data.first() {
+            Some(&mysql::OK_HEADER) => Ok(self.handle_ok_packet(&data)),
+            Some(&mysql::ERR_HEADER) => Err(self.handle_error_packet(&data)),
+            Some(_) => Err(ReplicationError::new("invalid ok packet".to_string())),
+            None => Err(ReplicationError::from(MysqlError::ErrMalformPacket)),
+        }
+    }
+
+    fn _exec(&mut self, query: &str) -> Result<MysqlResult, ReplicationError> {
+        self._write_command_str(mysql::COM_QUERY, query)?;
+
+        self._read_result(false)
+    }
+
+    fn _write_command_str(&mut self, command: u8, arg: &str) -> Result<(), ReplicationError> {
+        self.reset_sequence();
+
+        let mut data = vec![0_u8; 4 + 1 + arg.len()];
+        data[4] = command;
+        data[5..].copy_from_slice(arg.as_bytes());
+
+        self.write_packet(&mut data)
+    }
+
+    fn _read_result(&mut self, binary: bool) -> Result<MysqlResult, ReplicationError> {
+        let data = self.read_packet()?;
+
+        match data.first() {
+            Some(&mysql::OK_HEADER) => Ok(self.handle_ok_packet(&data)),
+            Some(&mysql::ERR_HEADER) => Err(self.handle_error_packet(&data)),
+            Some(&mysql::LOCAL_IN_FILE_HEADER) | None => {
+                Err(ReplicationError::from(MysqlError::ErrMalformPacket))
+            }
+            Some(_) => self._read_result_set(&data, binary, &mut |c| c.read_packet()),
+        }
+    }
+
+    // ReadResultSetFrom builds the result set of the column count packet data from the column definition
+    // and row packets following it, the ones up to the end of the rows. It's how client::AsyncConn,
+    // which reads the packets itself, builds its result sets.
+    pub fn read_result_set_from(
+        &mut self,
+        data: &[u8],
+        packets: Vec<Vec<u8>>,
+        binary: bool,
+    ) -> Result<MysqlResult, ReplicationError> {
+        let mut packets = packets.into_iter();
+        self._read_result_set(data, binary, &mut |_| {
+            packets
+                .next()
+                .ok_or(ReplicationError::from(MysqlError::ErrMalformPacket))
+        })
+    }
+
+    fn _read_result_streaming(
+        &mut self,
+        binary: bool,
+        per_row_callback: &mut SelectPerRowCallback,
+        per_result_callback: Option<&mut SelectPerResultCallback>,
+    ) -> Result<MysqlResult, ReplicationError> {
+        let data = self.read_packet()?;
+
+        match data.first() {
+            // https://dev.mysql.com/doc/internals/en/com-query-response.html
+            // 14.6.4.1 COM_QUERY Response
+            // If the number of columns in the resultset is 0, this is a OK_Packet.
+            Some(&mysql::OK_HEADER) => {
+                let mut result = self.handle_ok_packet(&data);
+                result.result_set = Some(ResultSet::new());
+                Ok(result)
+            }
+            Some(&mysql::ERR_HEADER) => Err(self.handle_error_packet(&data)),
+            Some(&mysql::LOCAL_IN_FILE_HEADER) | None => {
+                Err(ReplicationError::from(MysqlError::ErrMalformPacket))
+            }
+            Some(_) => self._read_result_set_streaming(
+                &data,
+                binary,
+                per_row_callback,
+                per_result_callback,
+            ),
+        }
+    }
+
+    fn _read_result_set_streaming(
+        &mut self,
+        data: &[u8],
+        binary: bool,
+        per_row_callback: &mut SelectPerRowCallback,
+        per_result_callback: Option<&mut SelectPerResultCallback>,
+    ) -> Result<MysqlResult, ReplicationError> {
+        let (count, _, n) = mysql::length_encoded_int(data);
+
+        if n != data.len() {
+            return Err(ReplicationError::from(MysqlError::ErrMalformPacket));
+        }
+
+        let mut result = MysqlResult::default();
+        let mut result_set = ResultSet::new();
+        result_set.fields = Vec::with_capacity(count as usize);
+        // this is a streaming resultset
+        result_set.streaming = StreamingType::Select;
+
+        self._read_result_columns(&mut result, &mut result_set, count as usize, &mut |c| {
+            c.read_packet()
+        })?;
+        result.result_set = Some(result_set);
+
+        if let Some(per_result_callback) = per_result_callback {
+            per_result_callback(&result)?;
+        }
+
+        self._read_result_rows_streaming(&mut result, binary, per_row_callback)?;
+
+        // this resultset is done streaming
+        if let Some(result_set) = result.result_set.as_mut() {
+            result_set.streaming_done = true;
+        }
+
+        Ok(result)
+    }
+
+    fn _read_result_rows_streaming(
+        &mut self,
+        result: &mut MysqlResult,
+        binary: bool,
+        per_row_callback: &mut SelectPerRowCallback,
+    ) -> Result<(), ReplicationError> {
+        let fields = match &result.result_set {
+            Some(result_set) => result_set.fields.clone(),
+            None => vec![],
+        };
+
+        loop {
+            let data = self.read_packet()?;
+
+            // EOF Packet, or OK Packet if CLIENT_DEPRECATE_EOF is set
+            if self.is_result_set_end_packet(&data) {
+                self._read_result_set_end(result, &data);
+                return Ok(());
+            }
+
+            match data.first() {
+                Some(&mysql::ERR_HEADER) => return Err(self.handle_error_packet(&data)),
+                // a row has a value or NULL for every column
+                None => return Err(ReplicationError::from(MysqlError::ErrMalformPacket)),
+                _ => {}
+            }
+
+            // Parse this row
+            let mut dst = Vec::<FieldValue>::with_capacity(fields.len());
+            let row = RowData::new(data).parse(&fields, binary, &mut dst)?;
+
+            // Send the row to "userland" code
+            per_row_callback(row)?;
+        }
+    }
+
+    // the packets after the column count are read with next
+    fn _read_result_set(
+        &mut self,
+        data: &[u8],
+        binary: bool,
+        next: &mut NextPacket,
+    ) -> Result<MysqlResult, ReplicationError> {
+        // column count
+        let (count, _, n) = mysql::length_encoded_int(data);
+
+        if n != data.len() {
+            return Err(ReplicationError::from(MysqlError::ErrMalformPacket));
+        }
+
+        let mut result = MysqlResult::default();
+        let mut result_set = ResultSet::new();
+        result_set.fields = Vec::with_capacity(count as usize);
+
+        self._read_result_columns(&mut result, &mut result_set, count as usize, next)?;
+        self._read_result_rows(&mut result, &mut result_set, binary, next)?;
+
+        result.result_set = Some(result_set);
+        Ok(result)
+    }
+
+    pub fn is_eof_packet(&self, data: &[u8]) -> bool {
+        data.first() == Some(&mysql::EOF_HEADER) && data.len() <= 5
+    }
+
+    // With CLIENT_DEPRECATE_EOF the rows of a result set end with an OK packet using the 0xFE header.
+    pub fn is_result_set_end_packet(&self, data: &[u8]) -> bool {
+        if self._capability & mysql::CLIENT_DEPRECATE_EOF > 0 {
+            return data.first() == Some(&mysql::EOF_HEADER) && data.len() < mysql::MAX_PAYLOAD_LEN;
+        }
+
+        self.is_eof_packet(data)
+    }
+
+    fn _read_result_set_end(&mut self, result: &mut MysqlResult, data: &[u8]) {
+        if self._capability & mysql::CLIENT_DEPRECATE_EOF > 0 {
+            let r = self.handle_ok_packet(data);
+            result.status = r.status;
+            result.warnings = r.warnings;
+            return;
+        }
+
+        self._read_eof(result, data);
+    }
+
+    fn _read_eof(&mut self, result: &mut MysqlResult, data: &[u8]) {
+        // the warnings and the status follow the header
+        if self._capability & mysql::CLIENT_PROTOCOL_41 > 0 && data.len() >= 5 {
+            result.warnings = LittleEndian::read_u16(&data[1..]);
+            //todo add strict_mode, warning will be treat as error
+            result.status = LittleEndian::read_u16(&data[3..]);
+            self._status = result.status;
+        }
+    }
+
+    fn _read_result_columns(
+        &mut self,
+        result: &mut MysqlResult,
+        result_set: &mut ResultSet,
+        count: usize,
+        next: &mut NextPacket,
+    ) -> Result<(), ReplicationError> {
+        // there is no EOF packet after the column definitions
+        if self._capability & mysql::CLIENT_DEPRECATE_EOF > 0 {
+            for _ in 0..count {
+                let data = next(self)?;
+                self._read_result_column(result_set, data)?;
+            }
+
+            return Ok(());
+        }
+
+        loop {
+            let data = next(self)?;
+
+            // EOF Packet
+            if self.is_eof_packet(&data) {
+                self._read_eof(result, &data);
+
+                if result_set.fields.len() != count {
+                    return Err(ReplicationError::from(MysqlError::ErrMalformPacket));
+                }
+
+                return Ok(());
+            }
+
+            self._read_result_column(result_set, data)?;
+        }
+    }
+
+    fn _read_result_column(
+        &self,
+        result_set: &mut ResultSet,
+        data: Vec<u8>,
+    ) -> Result<(), ReplicationError> {
+        let mut field = Field::default();
+        field.parse(data)?;
+        result_set.field_names.insert(
+            String::from_utf8_lossy(&field.name).to_string(),
+            result_set.fields.len(),
+        );
+        result_set.fields.push(field);
+
+        Ok(())
+    }
+
+    fn _read_result_rows(
+        &mut self,
+        result: &mut MysqlResult,
+        result_set: &mut ResultSet,
+        binary: bool,
+        next: &mut NextPacket,
+    ) -> Result<(), ReplicationError> {
+        loop {
+            let data = next(self)?;
+
+            // EOF Packet, or OK Packet if CLIENT_DEPRECATE_EOF is set
+            if self.is_result_set_end_packet(&data) {
+                self._read_result_set_end(result, &data);
+                break;
+            }
+
+            match data.first() {
+                Some(&mysql::ERR_HEADER) => return Err(self.handle_error_packet(&data)),
+                // a row has a value or NULL for every column
+                None => return Err(ReplicationError::from(MysqlError::ErrMalformPacket)),
+                _ => {}
+            }
+
+            result_set.row_datas.push(RowData::new(data));
+        }
+
+        result_set.values = Vec::with_capacity(result_set.row_datas.len());
+        for row_data in &result_set.row_datas {
+            let mut dst = Vec::<FieldValue>::with_capacity(result_set.fields.len());
+            let values = row_data.parse(&result_set.fields, binary, &mut dst)?;
+            result_set.values.push(values);
+        }
+
+        Ok(())
+    }
 }
 
+/*
 func (c *Conn) Quit() error {
     if err := c.writeCommand(COM_QUIT); err != nil {
         return err
@@ -188,28 +1224,12 @@ func (c *Conn) Ping() error {
     return nil
 }
 
-// SetCapability enables the use of a specific capability
-func (c *Conn) SetCapability(cap uint32) {
-    c.ccaps |= cap
-}
-
-// UnsetCapability disables the use of a specific capability
-func (c *Conn) UnsetCapability(cap uint32) {
-    c.ccaps &= ^cap
-}
-
 // UseSSL: use default SSL
 // pass to options when connect
 func (c *Conn) UseSSL(insecureSkipVerify bool) {
     c.tlsConfig = &tls.Config{InsecureSkipVerify: insecureSkipVerify}
 }
 
-// SetTLSConfig: use user-specified TLS config
-// pass to options when connect
-func (c *Conn) SetTLSConfig(config *tls.Config) {
-    c.tlsConfig = config
-}
-
 func (c *Conn) UseDB(dbName string) error {
     if c.db == dbName {
         return nil
@@ -231,10 +1251,6 @@ func (c *Conn) GetDB() string {
     return c.db
 }
 
-func (c *Conn) GetServerVersion() string {
-    return c.serverVersion
-}
-
 func (c *Conn) CompareServerVersion(v string) (int, error) {
     return CompareServerVersions(c.serverVersion, v)
 }
@@ -315,28 +1331,6 @@ func (c *Conn) ExecuteMultiple(query string, perResultCallback ExecPerResultCall
     }}, nil
 }
 
-// ExecuteSelectStreaming will call perRowCallback for every row in resultset
-// WITHOUT saving any row data to Result.{Values/RawPkg/RowDatas} fields.
-// When given, perResultCallback will be called once per result
-//
-// ExecuteSelectStreaming should be used only for SELECT queries with a large response resultset for memory preserving.
-//
-// Example:
-//
-// var result mysql.Result
-// conn.ExecuteSelectStreaming(`SELECT ... LIMIT 100500`, &result, func(row []mysql.FieldValue) error {
-// // Use the row as you want.
-// // You must not save FieldValue.AsString() value after this callback is done. Copy it if you need.
-// return nil
-// }, nil)
-func (c *Conn) ExecuteSelectStreaming(command string, result *Result, perRowCallback SelectPerRowCallback, perResultCallback SelectPerResultCallback) error {
-    if err := c.writeCommandStr(COM_QUERY, command); err != nil {
-        return errors.Trace(err)
-    }
-
-    return c.readResultStreaming(false, result, perRowCallback, perResultCallback)
-}
-
 func (c *Conn) Begin() error {
     _, err := c.exec("BEGIN")
     return errors.Trace(err)
@@ -352,25 +1346,6 @@ func (c *Conn) Rollback() error {
     return errors.Trace(err)
 }
 
-func (c *Conn) SetAttributes(attributes map[string]string) {
-    for k, v := range attributes {
-        c.attributes[k] = v
-    }
-}
-
-func (c *Conn) SetCharset(charset string) error {
-    if c.charset == charset {
-        return nil
-    }
-
-    if _, err := c.exec(fmt.Sprintf("SET NAMES %s", charset)); err != nil {
-        return errors.Trace(err)
-    } else {
-        c.charset = charset
-        return nil
-    }
-}
-
 func (c *Conn) FieldList(table string, wildcard string) ([]*Field, error) {
     if err := c.writeCommandStrStr(COM_FIELD_LIST, table, wildcard); err != nil {
         return nil, errors.Trace(err)
@@ -418,35 +1393,6 @@ func (c *Conn) IsInTransaction() bool {
     return c.status&SERVER_STATUS_IN_TRANS > 0
 }
 
-func (c *Conn) GetCharset() string {
-    return c.charset
-}
-
-func (c *Conn) GetConnectionID() uint32 {
-    return c.connectionID
-}
-
-func (c *Conn) HandleOKPacket(data []byte) *Result {
-    r, _ := c.handleOKPacket(data)
-    return r
-}
-
-func (c *Conn) HandleErrorPacket(data []byte) error {
-    return c.handleErrorPacket(data)
-}
-
-func (c *Conn) ReadOKPacket() (*Result, error) {
-    return c.readOK()
-}
-
-func (c *Conn) exec(query string) (*Result, error) {
-    if err := c.writeCommandStr(COM_QUERY, query); err != nil {
-        return nil, errors.Trace(err)
-    }
-
-    return c.readResult(false)
-}
-
 func (c *Conn) CapabilityString() string {
     var caps []string
     capability := c.capability
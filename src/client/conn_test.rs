@@ -0,0 +1,1341 @@
+#[cfg(test)]
+pub mod tests {
+    use crate::client;
+    use crate::error::{MysqlError, ReplicationError};
+    use crate::mysql;
+    use crate::mysql::resultset::StreamingType;
+    use crate::mysql::FieldValueEnum;
+    use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+    use std::collections::HashMap;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::os::unix::net::UnixListener;
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::Duration;
+
+    pub const SALT: &[u8; 20] = b"0123456789abcdefghij";
+    pub const USER: &str = "root";
+    pub const PASSWORD: &str = "secret";
+    const STMT_ID: u32 = 7;
+    const STMT_PARAMS: usize = 8;
+
+    pub struct HandshakeResponse {
+        pub capability: u32,
+        pub user: String,
+        pub auth: Vec<u8>,
+        pub db: String,
+        pub plugin: String,
+        pub attributes: HashMap<String, String>,
+        zstd_compression_level: Option<u8>,
+    }
+
+    // AuthFlow finishes the authentication after the HandshakeResponse41, it returns whether the client is accepted.
+    pub type AuthFlow = Box<dyn Fn(&mut TcpStream, &HandshakeResponse, &mut u8) -> bool + Send>;
+
+    pub fn write_packet(conn: &mut impl Write, sequence: &mut u8, payload: &[u8]) {
+        let mut data = vec![0_u8; 4];
+        LittleEndian::write_u24(&mut data, payload.len() as u32);
+        data[3] = *sequence;
+        data.extend(payload);
+        conn.write_all(&data).unwrap();
+        *sequence = sequence.wrapping_add(1);
+    }
+
+    pub fn read_packet(conn: &mut impl Read, sequence: &mut u8) -> Vec<u8> {
+        let mut header = [0_u8; 4];
+        conn.read_exact(&mut header).unwrap();
+        assert_eq!(header[3], *sequence, "unexpected sequence");
+        *sequence = sequence.wrapping_add(1);
+        let mut data = vec![0_u8; LittleEndian::read_u24(&header) as usize];
+        conn.read_exact(&mut data).unwrap();
+        data
+    }
+
+    fn null_terminated(data: &[u8], pos: &mut usize) -> String {
+        let end = *pos + data[*pos..].iter().position(|&b| b == 0).unwrap();
+        let s = String::from_utf8_lossy(&data[*pos..end]).to_string();
+        *pos = end + 1;
+        s
+    }
+
+    fn length_encoded_string(data: &[u8], pos: &mut usize) -> String {
+        let (v, _, n) = mysql::length_encoded_string(&data[*pos..]).unwrap();
+        *pos += n;
+        String::from_utf8_lossy(&v).to_string()
+    }
+
+    fn parse_handshake_response(data: &[u8]) -> HandshakeResponse {
+        let capability = LittleEndian::read_u32(data);
+        // capability, max packet size, charset, filler
+        let mut pos = 4 + 4 + 1 + 23;
+        let user = null_terminated(data, &mut pos);
+
+        let (auth_len, _, n) = mysql::length_encoded_int(&data[pos..]);
+        pos += n;
+        let auth = data[pos..pos + auth_len as usize].to_vec();
+        pos += auth_len as usize;
+
+        let mut db = String::new();
+        if capability & mysql::CLIENT_CONNECT_WITH_DB > 0 {
+            db = null_terminated(data, &mut pos);
+        }
+        let plugin = null_terminated(data, &mut pos);
+
+        let mut attributes = HashMap::new();
+        if capability & mysql::CLIENT_CONNECT_ATTRS > 0 {
+            let (attrs_len, _, n) = mysql::length_encoded_int(&data[pos..]);
+            pos += n;
+            let end = pos + attrs_len as usize;
+            while pos < end {
+                let k = length_encoded_string(data, &mut pos);
+                let v = length_encoded_string(data, &mut pos);
+                attributes.insert(k, v);
+            }
+        }
+
+        let mut zstd_compression_level = None;
+        if capability & mysql::CLIENT_ZSTD_COMPRESSION_ALGORITHM > 0 {
+            zstd_compression_level = Some(data[pos]);
+            pos += 1;
+        }
+        assert_eq!(pos, data.len());
+
+        HandshakeResponse {
+            capability,
+            user,
+            auth,
+            db,
+            plugin,
+            attributes,
+            zstd_compression_level,
+        }
+    }
+
+    pub fn write_ok(conn: &mut impl Write, sequence: &mut u8) {
+        write_packet(conn, sequence, &[mysql::OK_HEADER, 0, 0, 0x02, 0, 0, 0]);
+    }
+
+    fn write_access_denied(conn: &mut TcpStream, sequence: &mut u8) {
+        write_packet(
+            conn,
+            sequence,
+            b"\xff\x15\x04#28000Access denied for user 'root'",
+        );
+    }
+
+    pub fn write_initial_handshake(
+        conn: &mut impl Write,
+        sequence: &mut u8,
+        plugin: &str,
+        extra_capability: u32,
+    ) {
+        let capability = mysql::CLIENT_LONG_PASSWORD
+            | mysql::CLIENT_PROTOCOL_41
+            | mysql::CLIENT_SECURE_CONNECTION
+            | mysql::CLIENT_TRANSACTIONS
+            | mysql::CLIENT_CONNECT_WITH_DB
+            | mysql::CLIENT_PLUGIN_AUTH
+            | mysql::CLIENT_CONNECT_ATTRS
+            | extra_capability;
+
+        let mut data = vec![mysql::MIN_PPOTOCOL_VERSION];
+        data.extend(b"8.0.33\0");
+        data.write_u32::<LittleEndian>(7).unwrap();
+        data.extend(&SALT[..8]);
+        data.push(0);
+        data.write_u16::<LittleEndian>(capability as u16).unwrap();
+        data.push(mysql::DEFAULT_COLLATION_ID);
+        data.write_u16::<LittleEndian>(mysql::SERVER_STATUS_AUTOCOMMIT)
+            .unwrap();
+        data.write_u16::<LittleEndian>((capability >> 16) as u16)
+            .unwrap();
+        data.push(21);
+        data.extend([0_u8; 10]);
+        data.extend(&SALT[8..]);
+        data.push(0);
+        data.extend(plugin.as_bytes());
+        data.push(0);
+
+        write_packet(conn, sequence, &data);
+    }
+
+    fn column_definition(name: &str, typ: u8) -> Vec<u8> {
+        let mut data = vec![];
+        for s in ["def", "test", "t", "t", name, name] {
+            data.extend(mysql::put_length_encoded_string(s.as_bytes()));
+        }
+        data.push(0x0c);
+        data.write_u16::<LittleEndian>(mysql::DEFAULT_COLLATION_ID as u16)
+            .unwrap();
+        data.write_u32::<LittleEndian>(64).unwrap();
+        data.push(typ);
+        data.write_u16::<LittleEndian>(0).unwrap();
+        data.push(0);
+        data.extend([0_u8; 2]);
+        data
+    }
+
+    // accept_client accepts one connection and authenticates it, it returns whether CLIENT_DEPRECATE_EOF is negotiated.
+    fn accept_client(listener: TcpListener, deprecate_eof: bool) -> (TcpStream, bool) {
+        let (mut conn, _) = listener.accept().unwrap();
+        let mut sequence = 0_u8;
+        let extra_capability = if deprecate_eof {
+            mysql::CLIENT_DEPRECATE_EOF
+        } else {
+            0
+        };
+        write_initial_handshake(
+            &mut conn,
+            &mut sequence,
+            mysql::AUTH_NATIVE_PASSWORD,
+            extra_capability,
+        );
+        let response = parse_handshake_response(&read_packet(&mut conn, &mut sequence));
+        write_ok(&mut conn, &mut sequence);
+
+        (conn, response.capability & mysql::CLIENT_DEPRECATE_EOF > 0)
+    }
+
+    // read_command reads a command packet, it returns None when the client is gone.
+    pub fn read_command(conn: &mut impl Read) -> Option<Vec<u8>> {
+        let mut header = [0_u8; 4];
+        if conn.read_exact(&mut header).is_err() {
+            return None;
+        }
+        let mut data = vec![0_u8; LittleEndian::read_u24(&header) as usize];
+        conn.read_exact(&mut data).unwrap();
+
+        Some(data)
+    }
+
+    fn write_eof(conn: &mut TcpStream, sequence: &mut u8) {
+        write_packet(conn, sequence, &[mysql::EOF_HEADER, 0, 0, 2, 0]);
+    }
+
+    // start_query_server accepts one connection and answers COM_QUERY:
+    // SELECT returns two rows of (id, name), BAD returns an ERR packet, EMPTY an empty packet, EMPTY ROW a result set
+    // with an empty row packet, anything else an OK packet.
+    // The result set ends with EOF packets, or an OK packet if CLIENT_DEPRECATE_EOF is negotiated.
+    pub fn start_query_server(deprecate_eof: bool) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        thread::spawn(move || {
+            let (mut conn, deprecate_eof) = accept_client(listener, deprecate_eof);
+
+            while let Some(data) = read_command(&mut conn) {
+                let mut sequence = 1_u8;
+
+                assert_eq!(data[0], mysql::COM_QUERY);
+                match &data[1..] {
+                    b"SELECT id, name FROM t" => {
+                        write_packet(&mut conn, &mut sequence, &[2]);
+                        write_packet(
+                            &mut conn,
+                            &mut sequence,
+                            &column_definition("id", mysql::MYSQL_TYPE_LONGLONG),
+                        );
+                        write_packet(
+                            &mut conn,
+                            &mut sequence,
+                            &column_definition("name", mysql::MYSQL_TYPE_VAR_STRING),
+                        );
+                        if !deprecate_eof {
+                            write_eof(&mut conn, &mut sequence);
+                        }
+                        for (id, name) in [("1", "a"), ("2", "b")] {
+                            let mut row = mysql::put_length_encoded_string(id.as_bytes());
+                            row.extend(mysql::put_length_encoded_string(name.as_bytes()));
+                            write_packet(&mut conn, &mut sequence, &row);
+                        }
+                        if deprecate_eof {
+                            // OK packet with the 0xFE header, 1 warning
+                            write_packet(
+                                &mut conn,
+                                &mut sequence,
+                                &[mysql::EOF_HEADER, 0, 0, 0x22, 0, 1, 0],
+                            );
+                        } else {
+                            write_packet(
+                                &mut conn,
+                                &mut sequence,
+                                &[mysql::EOF_HEADER, 1, 0, 0x22, 0],
+                            );
+                        }
+                    }
+                    b"BAD" => write_packet(
+                        &mut conn,
+                        &mut sequence,
+                        b"\xff\x28\x04#42000You have an error in your SQL syntax",
+                    ),
+                    b"EMPTY" => write_packet(&mut conn, &mut sequence, &[]),
+                    b"EMPTY ROW" => {
+                        write_packet(&mut conn, &mut sequence, &[1]);
+                        write_packet(
+                            &mut conn,
+                            &mut sequence,
+                            &column_definition("id", mysql::MYSQL_TYPE_LONGLONG),
+                        );
+                        if !deprecate_eof {
+                            write_eof(&mut conn, &mut sequence);
+                        }
+                        write_packet(&mut conn, &mut sequence, &[]);
+                    }
+                    _ => write_packet(
+                        &mut conn,
+                        &mut sequence,
+                        &[mysql::OK_HEADER, 3, 9, 0x02, 0, 0, 0],
+                    ),
+                }
+            }
+        });
+
+        port
+    }
+
+    fn write_result_set_end(conn: &mut TcpStream, sequence: &mut u8, deprecate_eof: bool) {
+        if deprecate_eof {
+            write_packet(conn, sequence, &[mysql::EOF_HEADER, 0, 0, 2, 0, 0, 0]);
+        } else {
+            write_eof(conn, sequence);
+        }
+    }
+
+    // start_stmt_server accepts one connection and answers the COM_STMT_* commands of a statement
+    // with STMT_PARAMS parameters and the columns (id LONGLONG, name VAR_STRING, score DOUBLE).
+    // COM_STMT_EXECUTE returns the binary rows (1, 'a', 1.5) and (2, NULL, 2.25).
+    // The payloads of the commands after COM_STMT_PREPARE are sent to the returned receiver.
+    fn start_stmt_server(deprecate_eof: bool) -> (u16, mpsc::Receiver<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let (mut conn, deprecate_eof) = accept_client(listener, deprecate_eof);
+            let columns = [
+                column_definition("id", mysql::MYSQL_TYPE_LONGLONG),
+                column_definition("name", mysql::MYSQL_TYPE_VAR_STRING),
+                column_definition("score", mysql::MYSQL_TYPE_DOUBLE),
+            ];
+
+            while let Some(data) = read_command(&mut conn) {
+                let mut sequence = 1_u8;
+
+                match data[0] {
+                    mysql::COM_STMT_PREPARE if &data[1..] == b"BAD" => write_packet(
+                        &mut conn,
+                        &mut sequence,
+                        b"\xff\x28\x04#42000You have an error in your SQL syntax",
+                    ),
+                    mysql::COM_STMT_PREPARE => {
+                        let mut ok = vec![mysql::OK_HEADER];
+                        ok.extend(mysql::uint32_to_bytes(STMT_ID));
+                        ok.extend(mysql::uint16_to_bytes(columns.len() as u16));
+                        ok.extend(mysql::uint16_to_bytes(STMT_PARAMS as u16));
+                        ok.extend([0, 1, 0]);
+                        write_packet(&mut conn, &mut sequence, &ok);
+
+                        for i in 0..STMT_PARAMS {
+                            let param = column_definition(&format!("?{}", i), 0xfd);
+                            write_packet(&mut conn, &mut sequence, &param);
+                        }
+                        if !deprecate_eof {
+                            write_eof(&mut conn, &mut sequence);
+                        }
+                        for column in &columns {
+                            write_packet(&mut conn, &mut sequence, column);
+                        }
+                        if !deprecate_eof {
+                            write_eof(&mut conn, &mut sequence);
+                        }
+                    }
+                    mysql::COM_STMT_EXECUTE => {
+                        tx.send(data).unwrap();
+
+                        write_packet(&mut conn, &mut sequence, &[columns.len() as u8]);
+                        for column in &columns {
+                            write_packet(&mut conn, &mut sequence, column);
+                        }
+                        if !deprecate_eof {
+                            write_eof(&mut conn, &mut sequence);
+                        }
+
+                        // OK header, null bitmap with an offset of 2 bits, values of the non-null columns
+                        let mut row = vec![mysql::OK_HEADER, 0];
+                        row.extend(mysql::uint64_to_bytes(1));
+                        row.extend(mysql::put_length_encoded_string(b"a"));
+                        row.extend(mysql::uint64_to_bytes(1.5_f64.to_bits()));
+                        write_packet(&mut conn, &mut sequence, &row);
+
+                        let mut row = vec![mysql::OK_HEADER, 1 << 3];
+                        row.extend(mysql::uint64_to_bytes(2));
+                        row.extend(mysql::uint64_to_bytes(2.25_f64.to_bits()));
+                        write_packet(&mut conn, &mut sequence, &row);
+
+                        write_result_set_end(&mut conn, &mut sequence, deprecate_eof);
+                    }
+                    mysql::COM_STMT_RESET => {
+                        tx.send(data).unwrap();
+                        write_ok(&mut conn, &mut sequence);
+                    }
+                    // COM_STMT_SEND_LONG_DATA and COM_STMT_CLOSE have no response
+                    _ => tx.send(data).unwrap(),
+                }
+            }
+        });
+
+        (port, rx)
+    }
+
+    // start_server accepts one connection, sends the initial handshake with `plugin` and runs `auth`.
+    pub fn start_server(
+        plugin: &'static str,
+        auth: AuthFlow,
+    ) -> (u16, mpsc::Receiver<HandshakeResponse>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut sequence = 0_u8;
+            write_initial_handshake(&mut conn, &mut sequence, plugin, 0);
+
+            let response = parse_handshake_response(&read_packet(&mut conn, &mut sequence));
+            if auth(&mut conn, &response, &mut sequence) {
+                write_ok(&mut conn, &mut sequence);
+            } else {
+                write_access_denied(&mut conn, &mut sequence);
+            }
+            tx.send(response).unwrap();
+
+            // wait for the client to go away
+            let mut buf = [0_u8; 1];
+            let _ = conn.read(&mut buf);
+        });
+
+        (port, rx)
+    }
+
+    fn connect(port: u16, password: &str) -> Result<client::Conn, ReplicationError> {
+        client::connect(&format!("127.0.0.1:{}", port), USER, password, "test", &[])
+    }
+
+    pub fn rsa_key() -> (openssl::rsa::Rsa<openssl::pkey::Private>, Vec<u8>) {
+        let rsa = openssl::rsa::Rsa::generate(2048).unwrap();
+        let pem = rsa.public_key_to_pem().unwrap();
+        (rsa, pem)
+    }
+
+    // decrypt_password reverses mysql::encrypt_password
+    pub fn decrypt_password(
+        rsa: &openssl::rsa::Rsa<openssl::pkey::Private>,
+        enc: &[u8],
+    ) -> Vec<u8> {
+        let mut plain = vec![0_u8; rsa.size() as usize];
+        let n = rsa
+            .private_decrypt(enc, &mut plain, openssl::rsa::Padding::PKCS1_OAEP)
+            .unwrap();
+        plain.truncate(n);
+        for i in 0..plain.len() {
+            plain[i] ^= SALT[i % SALT.len()];
+        }
+        plain
+    }
+
+    pub fn password_with_nul() -> Vec<u8> {
+        let mut password = PASSWORD.as_bytes().to_vec();
+        password.push(0);
+        password
+    }
+
+    #[test]
+    fn test_native_password() -> Result<(), ReplicationError> {
+        let (port, rx) = start_server(
+            mysql::AUTH_NATIVE_PASSWORD,
+            Box::new(|_, resp, _| resp.auth == mysql::calc_password(SALT, PASSWORD.as_bytes())),
+        );
+
+        let mut c = connect(port, PASSWORD)?;
+        let resp = rx.recv().unwrap();
+        assert_eq!(resp.user, USER);
+        assert_eq!(resp.db, "test");
+        assert_eq!(resp.plugin, mysql::AUTH_NATIVE_PASSWORD);
+        assert_eq!(c.get_server_version(), "8.0.33");
+        assert_eq!(c.get_connection_id(), 7);
+
+        // connection attributes
+        assert!(resp.capability & mysql::CLIENT_CONNECT_ATTRS > 0);
+        assert_eq!(resp.attributes.get("_client_name").unwrap(), "rs_mysql");
+        assert_eq!(resp.attributes.get("_os").unwrap(), std::env::consts::OS);
+        assert_eq!(
+            resp.attributes.get("_runtime_version").map(String::as_str),
+            option_env!("RUSTC_VERSION")
+        );
+
+        c.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_native_password_access_denied() {
+        let (port, _rx) = start_server(
+            mysql::AUTH_NATIVE_PASSWORD,
+            Box::new(|_, resp, _| resp.auth == mysql::calc_password(SALT, PASSWORD.as_bytes())),
+        );
+
+        let rs = connect(port, "wrong");
+        assert!(rs.is_err());
+        assert!(rs.err().unwrap().to_string().contains("Access denied"));
+    }
+
+    #[test]
+    fn test_caching_sha2_password_fast_auth() -> Result<(), ReplicationError> {
+        let (port, rx) = start_server(
+            mysql::AUTH_CACHING_SHA2_PASSWORD,
+            Box::new(|conn, resp, sequence| {
+                if resp.auth != mysql::calc_caching_sha2_password(SALT, PASSWORD.as_bytes()) {
+                    return false;
+                }
+                write_packet(
+                    conn,
+                    sequence,
+                    &[mysql::MORE_DATE_HEADER, mysql::CACHE_SHA2_FAST_AUTH],
+                );
+                true
+            }),
+        );
+
+        let mut c = connect(port, PASSWORD)?;
+        assert_eq!(rx.recv().unwrap().plugin, mysql::AUTH_CACHING_SHA2_PASSWORD);
+
+        c.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_caching_sha2_password_fast_auth_empty_ok() {
+        let (port, _rx) = start_server(
+            mysql::AUTH_CACHING_SHA2_PASSWORD,
+            Box::new(|conn, _, sequence| {
+                write_packet(
+                    conn,
+                    sequence,
+                    &[mysql::MORE_DATE_HEADER, mysql::CACHE_SHA2_FAST_AUTH],
+                );
+                write_packet(conn, sequence, &[]);
+                false
+            }),
+        );
+
+        let err = connect(port, PASSWORD).err().unwrap();
+        assert!(err
+            .to_string()
+            .contains(&MysqlError::ErrMalformPacket.to_string()));
+    }
+
+    #[test]
+    fn test_caching_sha2_password_full_auth() -> Result<(), ReplicationError> {
+        let (port, _rx) = start_server(
+            mysql::AUTH_CACHING_SHA2_PASSWORD,
+            Box::new(|conn, _, sequence| {
+                // the password is not cached yet, ask for the full authentication
+                write_packet(
+                    conn,
+                    sequence,
+                    &[mysql::MORE_DATE_HEADER, mysql::CACHE_SHA2_FULL_AUTH],
+                );
+
+                // the connection is not secure, the client requests the public key
+                if read_packet(conn, sequence) != vec![2] {
+                    return false;
+                }
+                let (rsa, pem) = rsa_key();
+                let mut data = vec![mysql::MORE_DATE_HEADER];
+                data.extend(pem);
+                write_packet(conn, sequence, &data);
+
+                let enc = read_packet(conn, sequence);
+                decrypt_password(&rsa, &enc) == password_with_nul()
+            }),
+        );
+
+        let mut c = connect(port, PASSWORD)?;
+
+        c.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_sha256_password() -> Result<(), ReplicationError> {
+        let (port, _rx) = start_server(
+            mysql::AUTH_SHA256_PASSWORD,
+            Box::new(|conn, resp, sequence| {
+                // the connection is not secure, the client requests the public key
+                if resp.auth != vec![1] {
+                    return false;
+                }
+                let (rsa, pem) = rsa_key();
+                let mut data = vec![mysql::MORE_DATE_HEADER];
+                data.extend(pem);
+                write_packet(conn, sequence, &data);
+
+                let enc = read_packet(conn, sequence);
+                decrypt_password(&rsa, &enc) == password_with_nul()
+            }),
+        );
+
+        let mut c = connect(port, PASSWORD)?;
+
+        c.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_auth_switch_to_clear_password() -> Result<(), ReplicationError> {
+        let (port, _rx) = start_server(
+            mysql::AUTH_NATIVE_PASSWORD,
+            Box::new(|conn, _, sequence| {
+                let mut data = vec![mysql::EOF_HEADER];
+                data.extend(mysql::AUTH_CLEAR_PASSWORD.as_bytes());
+                data.push(0);
+                write_packet(conn, sequence, &data);
+
+                read_packet(conn, sequence) == password_with_nul()
+            }),
+        );
+
+        let mut c = connect(port, PASSWORD)?;
+
+        c.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_old_auth_switch_request() {
+        let (port, _rx) = start_server(
+            mysql::AUTH_NATIVE_PASSWORD,
+            Box::new(|conn, _, sequence| {
+                write_packet(conn, sequence, &[mysql::EOF_HEADER]);
+                true
+            }),
+        );
+
+        let rs = connect(port, PASSWORD);
+        assert!(rs
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("auth plugin 'mysql_old_password' is not supported"));
+    }
+
+    #[test]
+    fn test_empty_auth_result() {
+        let (port, _rx) = start_server(
+            mysql::AUTH_NATIVE_PASSWORD,
+            Box::new(|conn, _, sequence| {
+                write_packet(conn, sequence, &[]);
+                true
+            }),
+        );
+
+        let err = connect(port, PASSWORD).err().unwrap();
+        assert!(err
+            .to_string()
+            .contains(&MysqlError::ErrMalformPacket.to_string()));
+    }
+
+    #[test]
+    fn test_malformed_initial_handshake() {
+        let mut handshake = vec![];
+        write_initial_handshake(&mut handshake, &mut 0, mysql::AUTH_NATIVE_PASSWORD, 0);
+        let handshake = handshake[4..].to_vec();
+        // empty, in the connection id, in the salt, in the salt after the upper capability flags
+        for len in [0, 10, 15, 40] {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let data = handshake[..len].to_vec();
+            thread::spawn(move || {
+                let (mut conn, _) = listener.accept().unwrap();
+                write_packet(&mut conn, &mut 0, &data);
+                let mut buf = [0_u8; 1];
+                let _ = conn.read(&mut buf);
+            });
+
+            let err = connect(port, PASSWORD).err().unwrap();
+            assert!(
+                err.to_string()
+                    .contains(&MysqlError::ErrMalformPacket.to_string()),
+                "handshake of {} bytes: {}",
+                len,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_unknown_auth_plugin() {
+        let (port, _rx) = start_server("dialog", Box::new(|_, _, _| true));
+
+        let rs = connect(port, PASSWORD);
+        assert!(rs.is_err());
+        assert!(rs
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("unknown auth plugin"));
+    }
+
+    fn check_execute(deprecate_eof: bool) -> Result<(), ReplicationError> {
+        let port = start_query_server(deprecate_eof);
+        let mut c = connect(port, PASSWORD)?;
+
+        let r = c.execute("SELECT id, name FROM t")?;
+        let rs = r.result_set.as_ref().unwrap();
+        assert_eq!(rs.fields.len(), 2);
+        assert_eq!(rs.values.len(), 2);
+        assert_eq!(rs.get_string(0, 1)?, "a");
+        assert_eq!(rs.get_string(1, 1)?, "b");
+        assert_eq!(rs.get_string_by_name(1, "id")?, "2");
+        assert_eq!(r.warnings, 1);
+        assert_eq!(r.status, 0x22);
+
+        let r = c.execute("INSERT INTO t VALUES (3, 'c')")?;
+        assert!(r.result_set.is_none());
+        assert_eq!(r.affected_rows, 3);
+        assert_eq!(r.insert_id, 9);
+
+        match c.execute("BAD") {
+            Err(ReplicationError::MyError(e)) => {
+                assert_eq!(e.code, 1064);
+                assert_eq!(e.state, "42000");
+                assert_eq!(e.message, "You have an error in your SQL syntax");
+            }
+            rs => panic!("expect MyError, got {:?}", rs),
+        }
+
+        // the connection is still usable after an ERR packet
+        let r = c.execute("SELECT id, name FROM t")?;
+        assert_eq!(r.result_set.unwrap().values.len(), 2);
+
+        for query in ["EMPTY", "EMPTY ROW"] {
+            let err = c.execute(query).err().unwrap();
+            assert!(err
+                .to_string()
+                .contains(&MysqlError::ErrMalformPacket.to_string()));
+        }
+
+        c.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute() -> Result<(), ReplicationError> {
+        check_execute(false)
+    }
+
+    #[test]
+    fn test_execute_with_deprecate_eof() -> Result<(), ReplicationError> {
+        check_execute(true)
+    }
+
+    fn check_execute_select_streaming(deprecate_eof: bool) -> Result<(), ReplicationError> {
+        let port = start_query_server(deprecate_eof);
+        let mut c = connect(port, PASSWORD)?;
+
+        let mut rows = vec![];
+        let mut field_names = vec![];
+        let r = c.execute_select_streaming(
+            "SELECT id, name FROM t",
+            &mut |row| {
+                rows.push(row);
+                Ok(())
+            },
+            Some(&mut |result| {
+                let rs = result.result_set.as_ref().unwrap();
+                assert!(rs.values.is_empty());
+                assert!(!rs.streaming_done);
+                field_names = rs.fields.iter().map(|f| f.name.clone()).collect();
+                Ok(())
+            }),
+        )?;
+
+        assert_eq!(field_names, vec![b"id".to_vec(), b"name".to_vec()]);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0][0].value(), FieldValueEnum::I64(1));
+        assert_eq!(
+            rows[1][1].as_string(),
+            FieldValueEnum::String(b"b".to_vec())
+        );
+        assert_eq!(r.warnings, 1);
+        assert_eq!(r.status, 0x22);
+
+        // rows are handed to the callback, never kept in the result set
+        let rs = r.result_set.as_ref().unwrap();
+        assert!(matches!(rs.streaming, StreamingType::Select));
+        assert!(rs.streaming_done);
+        assert!(rs.values.is_empty());
+        assert_eq!(rs.fields.len(), 2);
+
+        let r = c.execute_select_streaming(
+            "INSERT INTO t VALUES (3, 'c')",
+            &mut |_| panic!("no rows expected"),
+            None,
+        )?;
+        assert_eq!(r.affected_rows, 3);
+
+        for query in ["EMPTY", "EMPTY ROW"] {
+            let err = c
+                .execute_select_streaming(query, &mut |_| panic!("no rows expected"), None)
+                .unwrap_err();
+            assert!(err
+                .to_string()
+                .contains(&MysqlError::ErrMalformPacket.to_string()));
+        }
+
+        // an error from the callback stops the streaming
+        let mut count = 0;
+        let rs = c.execute_select_streaming(
+            "SELECT id, name FROM t",
+            &mut |_| {
+                count += 1;
+                Err(ReplicationError::new("stop".to_string()))
+            },
+            None,
+        );
+        assert_eq!(rs.unwrap_err().to_string(), "stop");
+        assert_eq!(count, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_select_streaming() -> Result<(), ReplicationError> {
+        check_execute_select_streaming(false)
+    }
+
+    #[test]
+    fn test_execute_select_streaming_with_deprecate_eof() -> Result<(), ReplicationError> {
+        check_execute_select_streaming(true)
+    }
+
+    fn check_stmt(deprecate_eof: bool) -> Result<(), ReplicationError> {
+        let (port, rx) = start_stmt_server(deprecate_eof);
+        let mut c = connect(port, PASSWORD)?;
+
+        match c.prepare("BAD") {
+            Err(ReplicationError::MyError(e)) => assert_eq!(e.code, 1064),
+            rs => panic!("expect MyError, got {:?}", rs),
+        }
+
+        let mut stmt = c.prepare("INSERT INTO t VALUES (?, ?, ?, ?, ?, ?, ?, ?)")?;
+        assert_eq!(stmt.get_id(), STMT_ID);
+        assert_eq!(stmt.param_num(), STMT_PARAMS);
+        assert_eq!(stmt.column_num(), 3);
+        assert_eq!(stmt.warnings_num(), 1);
+
+        let rs = stmt.execute(&[client::Value::Null]);
+        assert_eq!(
+            rs.unwrap_err().to_string(),
+            "argument mismatch, need 8 but got 1"
+        );
+
+        stmt.send_long_data(7, b"long")?;
+        let mut expected = vec![mysql::COM_STMT_SEND_LONG_DATA, 7, 0, 0, 0, 7, 0];
+        expected.extend(b"long");
+        assert_eq!(rx.recv().unwrap(), expected);
+
+        stmt.reset()?;
+        assert_eq!(rx.recv().unwrap(), vec![mysql::COM_STMT_RESET, 7, 0, 0, 0]);
+
+        let r = stmt.execute(&[
+            client::Value::Int(-2),
+            client::Value::UInt(u64::MAX),
+            client::Value::Null,
+            client::Value::Bool(true),
+            client::Value::Float(0.5),
+            client::Value::Double(1.5),
+            client::Value::String("ab".to_string()),
+            client::Value::Bytes(vec![0xff]),
+        ])?;
+
+        let mut expected = vec![mysql::COM_STMT_EXECUTE, 7, 0, 0, 0, 0, 1, 0, 0, 0];
+        // null bitmap, new-params-bound-flag
+        expected.extend([1 << 2, 1]);
+        expected.extend([
+            mysql::MYSQL_TYPE_LONGLONG,
+            0,
+            mysql::MYSQL_TYPE_LONGLONG,
+            0x80,
+            mysql::MYSQL_TYPE_NULL,
+            0,
+            mysql::MYSQL_TYPE_TINY,
+            0,
+            mysql::MYSQL_TYPE_FLOAT,
+            0,
+            mysql::MYSQL_TYPE_DOUBLE,
+            0,
+            mysql::MYSQL_TYPE_STRING,
+            0,
+            mysql::MYSQL_TYPE_STRING,
+            0,
+        ]);
+        expected.extend((-2_i64).to_le_bytes());
+        expected.extend(u64::MAX.to_le_bytes());
+        expected.push(1);
+        expected.extend(0.5_f32.to_le_bytes());
+        expected.extend(1.5_f64.to_le_bytes());
+        expected.extend([2, b'a', b'b']);
+        expected.extend([1, 0xff]);
+        assert_eq!(rx.recv().unwrap(), expected);
+
+        let rs = r.result_set.as_ref().unwrap();
+        assert_eq!(rs.fields.len(), 3);
+        assert_eq!(rs.values.len(), 2);
+        assert_eq!(rs.values[0][0].value(), FieldValueEnum::I64(1));
+        assert_eq!(
+            rs.values[0][1].value(),
+            FieldValueEnum::String(b"a".to_vec())
+        );
+        assert_eq!(rs.values[0][2].value(), FieldValueEnum::F64(1.5));
+        assert_eq!(rs.values[1][0].value(), FieldValueEnum::I64(2));
+        assert_eq!(rs.values[1][1].value(), FieldValueEnum::None);
+        assert_eq!(rs.values[1][2].value(), FieldValueEnum::F64(2.25));
+        assert_eq!(r.status, 2);
+
+        stmt.close()?;
+        assert_eq!(rx.recv().unwrap(), vec![mysql::COM_STMT_CLOSE, 7, 0, 0, 0]);
+
+        // a dropped Stmt is closed too, a closed one isn't closed again
+        drop(c.prepare("SELECT 1")?);
+        assert_eq!(rx.recv().unwrap(), vec![mysql::COM_STMT_CLOSE, 7, 0, 0, 0]);
+        assert!(rx
+            .recv_timeout(std::time::Duration::from_millis(100))
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stmt() -> Result<(), ReplicationError> {
+        check_stmt(false)
+    }
+
+    #[test]
+    fn test_stmt_with_deprecate_eof() -> Result<(), ReplicationError> {
+        check_stmt(true)
+    }
+
+    pub struct Cert {
+        pub cert: openssl::x509::X509,
+        key: openssl::pkey::PKey<openssl::pkey::Private>,
+    }
+
+    // new_cert issues a certificate for localhost signed by the issuer, or a self-signed CA certificate without issuer.
+    pub fn new_cert(issuer: Option<&Cert>) -> Cert {
+        use openssl::x509::extension::{
+            BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName,
+        };
+
+        let group =
+            openssl::ec::EcGroup::from_curve_name(openssl::nid::Nid::X9_62_PRIME256V1).unwrap();
+        let key = openssl::pkey::PKey::from_ec_key(openssl::ec::EcKey::generate(&group).unwrap())
+            .unwrap();
+
+        let mut name = openssl::x509::X509NameBuilder::new().unwrap();
+        let cn = if issuer.is_some() {
+            "localhost"
+        } else {
+            "Test CA"
+        };
+        name.append_entry_by_text("CN", cn).unwrap();
+        let name = name.build();
+
+        let mut builder = openssl::x509::X509Builder::new().unwrap();
+        builder.set_version(2).unwrap();
+        let serial = openssl::bn::BigNum::from_u32(if issuer.is_some() { 2 } else { 1 }).unwrap();
+        builder
+            .set_serial_number(&serial.to_asn1_integer().unwrap())
+            .unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&openssl::asn1::Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&openssl::asn1::Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+
+        match issuer {
+            Some(issuer) => {
+                builder.set_issuer_name(issuer.cert.subject_name()).unwrap();
+                let san = SubjectAlternativeName::new()
+                    .dns("localhost")
+                    .build(&builder.x509v3_context(Some(&issuer.cert), None))
+                    .unwrap();
+                builder.append_extension(san).unwrap();
+                builder
+                    .append_extension(ExtendedKeyUsage::new().server_auth().build().unwrap())
+                    .unwrap();
+                builder
+                    .sign(&issuer.key, openssl::hash::MessageDigest::sha256())
+                    .unwrap();
+            }
+            None => {
+                builder.set_issuer_name(&name).unwrap();
+                builder
+                    .append_extension(BasicConstraints::new().critical().ca().build().unwrap())
+                    .unwrap();
+                builder
+                    .append_extension(KeyUsage::new().critical().key_cert_sign().build().unwrap())
+                    .unwrap();
+                builder
+                    .sign(&key, openssl::hash::MessageDigest::sha256())
+                    .unwrap();
+            }
+        }
+
+        Cert {
+            cert: builder.build(),
+            key,
+        }
+    }
+
+    // start_tls_server switches every connection to TLS after the SSLRequest packet, it sends the HandshakeResponse41
+    // read over TLS to the returned receiver and answers every command with an OK packet.
+    pub fn start_tls_server(cert: &Cert) -> (u16, mpsc::Receiver<HandshakeResponse>) {
+        let config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![rustls::Certificate(cert.cert.to_der().unwrap())],
+                rustls::PrivateKey(cert.key.private_key_to_pkcs8().unwrap()),
+            )
+            .unwrap();
+        let config = Arc::new(config);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for conn in listener.incoming() {
+                let (mut conn, config, tx) = (conn.unwrap(), config.clone(), tx.clone());
+                thread::spawn(move || {
+                    let mut sequence = 0_u8;
+                    write_initial_handshake(
+                        &mut conn,
+                        &mut sequence,
+                        mysql::AUTH_SHA256_PASSWORD,
+                        mysql::CLIENT_SSL,
+                    );
+
+                    let ssl_request = read_packet(&mut conn, &mut sequence);
+                    assert_eq!(ssl_request.len(), 32);
+                    assert!(LittleEndian::read_u32(&ssl_request) & mysql::CLIENT_SSL > 0);
+
+                    let session = rustls::ServerConnection::new(config).unwrap();
+                    let mut tls = rustls::StreamOwned::new(session, conn);
+                    // the handshake fails when the client rejects the certificate
+                    while tls.conn.is_handshaking() {
+                        if tls.conn.complete_io(&mut tls.sock).is_err() {
+                            return;
+                        }
+                    }
+
+                    let response = parse_handshake_response(&read_packet(&mut tls, &mut sequence));
+                    write_ok(&mut tls, &mut sequence);
+                    tx.send(response).unwrap();
+
+                    while read_command(&mut tls).is_some() {
+                        let mut sequence = 1_u8;
+                        write_ok(&mut tls, &mut sequence);
+                    }
+                });
+            }
+        });
+
+        (port, rx)
+    }
+
+    fn connect_tls(
+        host: &str,
+        port: u16,
+        ca: &Cert,
+        ssl_mode: mysql::SslMode,
+    ) -> Result<client::Conn, ReplicationError> {
+        let config = mysql::new_client_tls_config(&ca.cert.to_pem().unwrap(), b"", b"", ssl_mode)?;
+        let option: Box<dyn Fn(&mut client::Conn)> =
+            Box::new(move |c| c.set_tls_config(config.clone()));
+
+        client::connect(
+            &format!("{}:{}", host, port),
+            USER,
+            PASSWORD,
+            "test",
+            &[option],
+        )
+    }
+
+    #[test]
+    fn test_tls_verify_identity() -> Result<(), ReplicationError> {
+        let ca = new_cert(None);
+        let (port, rx) = start_tls_server(&new_cert(Some(&ca)));
+
+        let mut c = connect_tls("localhost", port, &ca, mysql::SslMode::VerifyIdentity)?;
+        let response = rx.recv().unwrap();
+        assert!(response.capability & mysql::CLIENT_SSL > 0);
+        // sha256_password sends the password in clear text over TLS
+        assert_eq!(response.auth, password_with_nul());
+        assert_eq!(response.db, "test");
+        assert_eq!(response.plugin, mysql::AUTH_SHA256_PASSWORD);
+
+        let r = c.execute("SET NAMES utf8mb4")?;
+        assert_eq!(
+            r.status & mysql::SERVER_STATUS_AUTOCOMMIT,
+            mysql::SERVER_STATUS_AUTOCOMMIT
+        );
+
+        c.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_tls_clone_writes_while_reading() -> Result<(), ReplicationError> {
+        let ca = new_cert(None);
+        let (port, _rx) = start_tls_server(&new_cert(Some(&ca)));
+
+        let mut c = connect_tls("localhost", port, &ca, mysql::SslMode::VerifyIdentity)?;
+        let mut reader = c.try_clone_packet_conn()?;
+        let handle = thread::spawn(move || {
+            reader.sequence = 1;
+            reader.read_packet()
+        });
+
+        // the clone is blocked reading while the command is written through the same TLS session
+        thread::sleep(Duration::from_millis(100));
+        let mut data = vec![0_u8; 4];
+        data.push(mysql::COM_QUERY);
+        data.extend(b"SELECT 1");
+        c.reset_sequence();
+        c.write_packet(&mut data)?;
+
+        let data = handle.join().unwrap()?;
+        assert_eq!(data[0], mysql::OK_HEADER);
+
+        c.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_tls_verify_ca() -> Result<(), ReplicationError> {
+        let ca = new_cert(None);
+        let (port, _rx) = start_tls_server(&new_cert(Some(&ca)));
+
+        // the certificate is issued for localhost only
+        let err = connect_tls("127.0.0.1", port, &ca, mysql::SslMode::VerifyIdentity).unwrap_err();
+        assert!(err.to_string().contains("TLS handshake failed"), "{}", err);
+
+        let mut c = connect_tls("127.0.0.1", port, &ca, mysql::SslMode::VerifyCA)?;
+        c.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_tls_unknown_ca() {
+        let (port, _rx) = start_tls_server(&new_cert(Some(&new_cert(None))));
+
+        let err =
+            connect_tls("localhost", port, &new_cert(None), mysql::SslMode::VerifyCA).unwrap_err();
+        assert!(err.to_string().contains("TLS handshake failed"), "{}", err);
+    }
+
+    #[test]
+    fn test_tls_not_supported() {
+        let port = start_query_server(false);
+
+        let err =
+            connect_tls("localhost", port, &new_cert(None), mysql::SslMode::VerifyCA).unwrap_err();
+        assert!(err.to_string().contains("does not support TLS"), "{}", err);
+    }
+
+    #[test]
+    fn test_unix_socket() -> Result<(), ReplicationError> {
+        let path =
+            std::env::temp_dir().join(format!("conn_test_{}_mysqld.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut sequence = 0_u8;
+            write_initial_handshake(&mut conn, &mut sequence, mysql::AUTH_NATIVE_PASSWORD, 0);
+            let _ = read_packet(&mut conn, &mut sequence);
+            write_ok(&mut conn, &mut sequence);
+
+            while read_command(&mut conn).is_some() {
+                let mut sequence = 1_u8;
+                write_ok(&mut conn, &mut sequence);
+            }
+        });
+
+        let mut c = client::connect(path.to_str().unwrap(), USER, PASSWORD, "test", &[])?;
+        c.execute("SET NAMES utf8mb4")?;
+        c.close()?;
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    // read_compressed_command reads a command from a compressed packet, it returns None when the client is gone.
+    fn read_compressed_command(conn: &mut TcpStream, compression: u8) -> Option<(u8, Vec<u8>)> {
+        let mut header = [0_u8; 7];
+        if conn.read_exact(&mut header).is_err() {
+            return None;
+        }
+        let mut payload = vec![0_u8; LittleEndian::read_u24(&header) as usize];
+        conn.read_exact(&mut payload).unwrap();
+
+        if LittleEndian::read_u24(&header[4..]) > 0 {
+            payload = match compression {
+                mysql::MYSQL_COMPRESS_ZLIB => {
+                    let mut data = vec![];
+                    flate2::read::ZlibDecoder::new(payload.as_slice())
+                        .read_to_end(&mut data)
+                        .unwrap();
+                    data
+                }
+                _ => zstd::stream::decode_all(payload.as_slice()).unwrap(),
+            };
+        }
+
+        Some((header[3], read_command(&mut payload.as_slice()).unwrap()))
+    }
+
+    // start_compress_server accepts one connection advertising the capability, after the authentication every command
+    // is answered with an OK packet in an uncompressed compressed packet.
+    pub fn start_compress_server(
+        capability: u32,
+        compression: u8,
+    ) -> (
+        u16,
+        mpsc::Receiver<HandshakeResponse>,
+        mpsc::Receiver<Vec<u8>>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+        let (commands_tx, commands_rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut sequence = 0_u8;
+            write_initial_handshake(
+                &mut conn,
+                &mut sequence,
+                mysql::AUTH_NATIVE_PASSWORD,
+                capability,
+            );
+            let response = parse_handshake_response(&read_packet(&mut conn, &mut sequence));
+            write_ok(&mut conn, &mut sequence);
+            let compress = response.capability
+                & (mysql::CLIENT_COMPRESS | mysql::CLIENT_ZSTD_COMPRESSION_ALGORITHM)
+                > 0;
+            tx.send(response).unwrap();
+
+            if !compress {
+                while let Some(data) = read_command(&mut conn) {
+                    commands_tx.send(data).unwrap();
+                    let mut sequence = 1_u8;
+                    write_ok(&mut conn, &mut sequence);
+                }
+                return;
+            }
+
+            while let Some((compressed_sequence, data)) =
+                read_compressed_command(&mut conn, compression)
+            {
+                commands_tx.send(data).unwrap();
+                let mut ok = vec![];
+                let mut sequence = 1_u8;
+                write_ok(&mut ok, &mut sequence);
+
+                let mut packet = vec![0_u8; 7];
+                LittleEndian::write_u24(&mut packet, ok.len() as u32);
+                packet[3] = compressed_sequence + 1;
+                packet.extend(ok);
+                conn.write_all(&packet).unwrap();
+            }
+        });
+
+        (port, rx, commands_rx)
+    }
+
+    fn connect_with_capability(port: u16, cap: u32) -> Result<client::Conn, ReplicationError> {
+        let option: Box<dyn Fn(&mut client::Conn)> = Box::new(move |c| c.set_capability(cap));
+        client::connect(
+            &format!("127.0.0.1:{}", port),
+            USER,
+            PASSWORD,
+            "test",
+            &[option],
+        )
+    }
+
+    fn check_compression(cap: u32, compression: u8) -> Result<(), ReplicationError> {
+        let (port, rx, commands) = start_compress_server(cap, compression);
+
+        let mut c = connect_with_capability(port, cap)?;
+        let response = rx.recv().unwrap();
+        assert_eq!(response.capability & cap, cap);
+        let level = response.zstd_compression_level;
+        assert_eq!(level.is_some(), compression == mysql::MYSQL_COMPRESS_ZSTD);
+
+        // the query is long enough to be compressed
+        let query = format!("SELECT '{}'", "a".repeat(200));
+        let r = c.execute(&query)?;
+        assert_eq!(
+            r.status & mysql::SERVER_STATUS_AUTOCOMMIT,
+            mysql::SERVER_STATUS_AUTOCOMMIT
+        );
+        let _ = c.execute("SELECT 1")?;
+
+        assert_eq!(
+            commands.recv().unwrap(),
+            [&[mysql::COM_QUERY], query.as_bytes()].concat()
+        );
+        assert_eq!(commands.recv().unwrap(), b"\x03SELECT 1");
+
+        c.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_compress_zlib() -> Result<(), ReplicationError> {
+        check_compression(mysql::CLIENT_COMPRESS, mysql::MYSQL_COMPRESS_ZLIB)
+    }
+
+    #[test]
+    fn test_compress_zstd() -> Result<(), ReplicationError> {
+        check_compression(
+            mysql::CLIENT_ZSTD_COMPRESSION_ALGORITHM,
+            mysql::MYSQL_COMPRESS_ZSTD,
+        )
+    }
+
+    #[test]
+    fn test_compress_not_supported_by_server() -> Result<(), ReplicationError> {
+        let (port, rx, commands) = start_compress_server(0, mysql::MYSQL_COMPRESS_NONE);
+
+        let mut c = connect_with_capability(port, mysql::CLIENT_COMPRESS)?;
+        let response = rx.recv().unwrap();
+        assert_eq!(response.capability & mysql::CLIENT_COMPRESS, 0);
+
+        let _ = c.execute("SELECT 1")?;
+        assert_eq!(commands.recv().unwrap(), b"\x03SELECT 1");
+
+        c.close()?;
+        Ok(())
+    }
+}
@@ -1,3 +1,9 @@
+pub mod async_conn;
+mod async_conn_test;
 pub mod conn;
+mod conn_test;
+pub mod stmt;
 
+pub use async_conn::*;
 pub use conn::*;
+pub use stmt::*;
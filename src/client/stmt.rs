@@ -0,0 +1,241 @@
+use crate::client::Conn;
+use crate::error::{MysqlError, ReplicationError};
+use crate::mysql;
+use crate::mysql::result::MysqlResult;
+use byteorder::{ByteOrder, LittleEndian};
+
+// Value is a parameter of a prepared statement, it's sent with the binary protocol.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f32),
+    Double(f64),
+    String(String),
+    Bytes(Vec<u8>),
+}
+
+impl Value {
+    // returns the (type, flag) pair of the parameter type and the encoded value
+    fn _encode(&self) -> ((u8, u8), Vec<u8>) {
+        match self {
+            Value::Null => ((mysql::MYSQL_TYPE_NULL, 0), vec![]),
+            Value::Bool(v) => ((mysql::MYSQL_TYPE_TINY, 0), vec![*v as u8]),
+            Value::Int(v) => (
+                (mysql::MYSQL_TYPE_LONGLONG, 0),
+                mysql::uint64_to_bytes(*v as u64),
+            ),
+            Value::UInt(v) => (
+                (mysql::MYSQL_TYPE_LONGLONG, 0x80),
+                mysql::uint64_to_bytes(*v),
+            ),
+            Value::Float(v) => (
+                (mysql::MYSQL_TYPE_FLOAT, 0),
+                mysql::uint32_to_bytes(v.to_bits()),
+            ),
+            Value::Double(v) => (
+                (mysql::MYSQL_TYPE_DOUBLE, 0),
+                mysql::uint64_to_bytes(v.to_bits()),
+            ),
+            Value::String(v) => (
+                (mysql::MYSQL_TYPE_STRING, 0),
+                mysql::put_length_encoded_string(v.as_bytes()),
+            ),
+            Value::Bytes(v) => (
+                (mysql::MYSQL_TYPE_STRING, 0),
+                mysql::put_length_encoded_string(v),
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Stmt<'a> {
+    _conn: &'a mut Conn,
+    _id: u32,
+
+    _params: usize,
+    _columns: usize,
+    _warnings: usize,
+
+    _closed: bool,
+}
+
+impl<'a> Stmt<'a> {
+    pub fn get_id(&self) -> u32 {
+        self._id
+    }
+
+    pub fn param_num(&self) -> usize {
+        self._params
+    }
+
+    pub fn column_num(&self) -> usize {
+        self._columns
+    }
+
+    pub fn warnings_num(&self) -> usize {
+        self._warnings
+    }
+
+    // Execute sends COM_STMT_EXECUTE with the args bound to the parameters and reads
+    // the OK packet or the binary protocol result set.
+    pub fn execute(&mut self, args: &[Value]) -> Result<MysqlResult, ReplicationError> {
+        self._write(args)?;
+
+        self._conn.read_result(true)
+    }
+
+    // SendLongData sends the data of the parameter param_id in chunks before Execute, the server doesn't reply.
+    pub fn send_long_data(&mut self, param_id: u16, data: &[u8]) -> Result<(), ReplicationError> {
+        if param_id as usize >= self._params {
+            return Err(ReplicationError::new(format!(
+                "parameter index {} out of range, stmt has {} parameters",
+                param_id, self._params
+            )));
+        }
+
+        let mut buf = Vec::<u8>::with_capacity(4 + 1 + 4 + 2 + data.len());
+        buf.extend([0, 0, 0, 0]);
+        buf.push(mysql::COM_STMT_SEND_LONG_DATA);
+        buf.extend(mysql::uint32_to_bytes(self._id));
+        buf.extend(mysql::uint16_to_bytes(param_id));
+        buf.extend(data);
+
+        self._conn.reset_sequence();
+        self._conn.write_packet(&mut buf)
+    }
+
+    // Reset resets the data collected by SendLongData.
+    pub fn reset(&mut self) -> Result<(), ReplicationError> {
+        self._write_command(mysql::COM_STMT_RESET)?;
+
+        let _ = self._conn.read_ok_packet()?;
+        Ok(())
+    }
+
+    // Close deallocates the statement on the server, the server doesn't reply.
+    // A Stmt dropped without Close is closed too, but the error is lost then.
+    pub fn close(mut self) -> Result<(), ReplicationError> {
+        self._closed = true;
+        self._write_command(mysql::COM_STMT_CLOSE)
+    }
+
+    fn _write_command(&mut self, command: u8) -> Result<(), ReplicationError> {
+        let mut buf = vec![0_u8; 4];
+        buf.push(command);
+        buf.extend(mysql::uint32_to_bytes(self._id));
+
+        self._conn.reset_sequence();
+        self._conn.write_packet(&mut buf)
+    }
+
+    fn _write(&mut self, args: &[Value]) -> Result<(), ReplicationError> {
+        if args.len() != self._params {
+            return Err(ReplicationError::new(format!(
+                "argument mismatch, need {} but got {}",
+                self._params,
+                args.len()
+            )));
+        }
+
+        let mut null_bitmap = vec![0_u8; (args.len() + 7) >> 3];
+        let mut param_types = vec![0_u8; args.len() << 1];
+        let mut param_values = Vec::<u8>::new();
+
+        for (i, arg) in args.iter().enumerate() {
+            if *arg == Value::Null {
+                null_bitmap[i / 8] |= 1 << (i % 8);
+            }
+
+            let ((typ, flag), value) = arg._encode();
+            param_types[i << 1] = typ;
+            param_types[(i << 1) + 1] = flag;
+            param_values.extend(value);
+        }
+
+        let mut data = Vec::<u8>::with_capacity(
+            4 + 1 + 4 + 1 + 4 + null_bitmap.len() + 1 + param_types.len() + param_values.len(),
+        );
+        data.extend([0, 0, 0, 0]);
+        data.push(mysql::COM_STMT_EXECUTE);
+        data.extend(mysql::uint32_to_bytes(self._id));
+        // flag: CURSOR_TYPE_NO_CURSOR
+        data.push(0x00);
+        // iteration-count, always 1
+        data.extend([1, 0, 0, 0]);
+
+        if !args.is_empty() {
+            data.extend(null_bitmap);
+            // new-params-bound-flag, the types are always sent
+            data.push(1);
+            data.extend(param_types);
+            data.extend(param_values);
+        }
+
+        self._conn.reset_sequence();
+        self._conn.write_packet(&mut data)
+    }
+}
+
+impl<'a> Drop for Stmt<'a> {
+    fn drop(&mut self) {
+        if !self._closed {
+            // best effort, a failed write means a broken connection, the server frees the statement with it
+            let _ = self._write_command(mysql::COM_STMT_CLOSE);
+        }
+    }
+}
+
+impl Conn {
+    // Prepare sends COM_STMT_PREPARE, the returned Stmt borrows the connection until it's dropped or closed,
+    // both deallocate the statement on the server.
+    pub fn prepare(&mut self, query: &str) -> Result<Stmt<'_>, ReplicationError> {
+        let mut buf = vec![0_u8; 4];
+        buf.push(mysql::COM_STMT_PREPARE);
+        buf.extend(query.as_bytes());
+
+        self.reset_sequence();
+        self.write_packet(&mut buf)?;
+
+        let data = self.read_packet()?;
+        if data.first() == Some(&mysql::ERR_HEADER) {
+            return Err(self.handle_error_packet(&data));
+        } else if data.len() < 12 || data[0] != mysql::OK_HEADER {
+            return Err(ReplicationError::from(MysqlError::ErrMalformPacket));
+        }
+
+        let mut pos = 1_usize;
+        // for statement id
+        let id = LittleEndian::read_u32(&data[pos..]);
+        pos += 4;
+        // number columns
+        let columns = LittleEndian::read_u16(&data[pos..]) as usize;
+        pos += 2;
+        // number params
+        let params = LittleEndian::read_u16(&data[pos..]) as usize;
+        // skip the reserved filler
+        pos += 3;
+        // warnings
+        let warnings = LittleEndian::read_u16(&data[pos..]) as usize;
+
+        if params > 0 {
+            self.read_until_eof(params)?;
+        }
+
+        if columns > 0 {
+            self.read_until_eof(columns)?;
+        }
+
+        Ok(Stmt {
+            _conn: self,
+            _id: id,
+            _params: params,
+            _columns: columns,
+            _warnings: warnings,
+            _closed: false,
+        })
+    }
+}
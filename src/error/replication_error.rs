@@ -1,7 +1,7 @@
 use std::char::TryFromCharError;
 use std::fmt::{Display, Formatter, Pointer, Result as FmtResult};
 
-use crate::error::{EventError, MysqlError};
+use crate::error::{EventError, MyError, MysqlError};
 use hex::FromHexError;
 use std::io::Error as IoError;
 use uuid::Error as UuidError;
@@ -23,7 +23,10 @@ pub enum ReplicationError {
     AsyncChannelRecvError(async_channel::RecvError),
     EventError(EventError),
     MysqlError(MysqlError),
+    MyError(MyError),
     ErrorStack(openssl::error::ErrorStack),
+    // nothing was read from the server in the read timeout
+    ReadTimeout(std::time::Duration),
 }
 
 impl std::error::Error for ReplicationError {}
@@ -32,6 +35,11 @@ impl ReplicationError {
     pub fn new(s: String) -> ReplicationError {
         ReplicationError::NormalError(s)
     }
+
+    // IsTimeout tells an error of a server that sent nothing in the read timeout, e.g. a dead master.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, ReplicationError::ReadTimeout(_))
+    }
 }
 
 impl Display for ReplicationError {
@@ -53,8 +61,16 @@ impl Display for ReplicationError {
             }
             ReplicationError::AsyncChannelRecvError(ref e) => e.fmt(f),
             ReplicationError::MysqlError(ref e) => e.fmt(f),
+            ReplicationError::MyError(ref e) => e.fmt(f),
             ReplicationError::ParseFloatError(ref e) => e.fmt(f),
             ReplicationError::ErrorStack(ref e) => e.fmt(f),
+            ReplicationError::ReadTimeout(ref timeout) => {
+                write!(
+                    f,
+                    "read timeout, no packet read from the server in {:?}",
+                    timeout
+                )
+            }
         }
     }
 }
@@ -150,6 +166,13 @@ impl From<MysqlError> for ReplicationError {
     }
 }
 
+//将MyError转为 ReplicationError
+impl From<MyError> for ReplicationError {
+    fn from(error: MyError) -> ReplicationError {
+        ReplicationError::MyError(error)
+    }
+}
+
 //将ErrorStack转为 ReplicationError
 impl From<openssl::error::ErrorStack> for ReplicationError {
     fn from(error: openssl::error::ErrorStack) -> ReplicationError {
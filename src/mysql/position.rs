@@ -3,6 +3,7 @@ use std::cmp::Ordering;
 use std::fmt::{Display, Formatter};
 
 // For binlog filename + position based replication
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct Position {
     pub name: String,
     pub pos: u32,
@@ -10,6 +10,10 @@ use crate::mysql::{
 pub struct RowData(Vec<u8>);
 
 impl RowData {
+    pub fn new(data: Vec<u8>) -> RowData {
+        RowData(data)
+    }
+
     pub fn parse(
         &self,
         f: &Vec<Field>,
@@ -96,7 +100,7 @@ impl RowData {
         let null_bitmap = self.0[1..pos].to_vec();
 
         for i in 0..data.len() {
-            if null_bitmap[((i + 2) / 8) & (1 << (i + 2) % 8)] > 0 {
+            if null_bitmap[(i + 2) / 8] & (1 << ((i + 2) % 8)) > 0 {
                 data[i].typ = FieldValueType::None;
                 continue;
             }
@@ -167,7 +171,7 @@ impl RowData {
                     continue;
                 }
                 mysql::MYSQL_TYPE_DOUBLE => {
-                    let v = ParseBinary::f64_little_endian(&self.0[pos..pos + 4])?;
+                    let v = ParseBinary::f64_little_endian(&self.0[pos..pos + 8])?;
                     data[i].typ = FieldValueType::Float;
                     data[i].value = v.to_bits();
                     pos += 8;
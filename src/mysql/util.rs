@@ -12,6 +12,8 @@ use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::io;
 use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::sync::Arc;
+use std::time::SystemTime;
 
 pub fn pstack() -> String {
     let bt = Backtrace::new();
@@ -94,17 +96,100 @@ pub fn encrypt_password(
         plain[i] ^= seed[j]
     }
 
-    let sha1_digest = openssl::sha::sha1(&plain);
-    let mut encrypted_data = Vec::<u8>::new();
-    let _ = rsa.public_encrypt(
-        &sha1_digest,
+    // RSA_PKCS1_OAEP_PADDING uses sha1 as the hash function
+    let mut encrypted_data = vec![0_u8; rsa.size() as usize];
+    let n = rsa.public_encrypt(
+        &plain,
         &mut encrypted_data,
         openssl::rsa::Padding::PKCS1_OAEP,
     )?;
+    encrypted_data.truncate(n);
 
     Ok(encrypted_data)
 }
 
+// SslMode is how the certificate of the server is verified on a TLS connection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SslMode {
+    // VerifyCA checks the certificate is signed by the CA, the host name is not checked.
+    VerifyCA,
+    // VerifyIdentity also checks the host name of the connection against the certificate.
+    VerifyIdentity,
+}
+
+// NewClientTLSConfig: generate TLS config for client side
+// cert_pem and key_pem are the client certificate for X509 authentication, they can be empty.
+pub fn new_client_tls_config(
+    ca_pem: &[u8],
+    cert_pem: &[u8],
+    key_pem: &[u8],
+    ssl_mode: SslMode,
+) -> Result<rustls::ClientConfig, ReplicationError> {
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in openssl::x509::X509::stack_from_pem(ca_pem)? {
+        roots
+            .add(&rustls::Certificate(cert.to_der()?))
+            .map_err(|e| ReplicationError::new(format!("invalid CA certificate: {}", e)))?;
+    }
+    if roots.is_empty() {
+        return Err(ReplicationError::new(
+            "failed to add ca PEM, no certificate found".to_string(),
+        ));
+    }
+
+    let builder = rustls::ClientConfig::builder().with_safe_defaults();
+    let builder = match ssl_mode {
+        SslMode::VerifyCA => {
+            builder.with_custom_certificate_verifier(Arc::new(CaOnlyVerifier { roots }))
+        }
+        SslMode::VerifyIdentity => builder.with_custom_certificate_verifier(Arc::new(
+            rustls::client::WebPkiVerifier::new(roots, None),
+        )),
+    };
+
+    if cert_pem.is_empty() && key_pem.is_empty() {
+        return Ok(builder.with_no_client_auth());
+    }
+
+    let mut certs = vec![];
+    for cert in openssl::x509::X509::stack_from_pem(cert_pem)? {
+        certs.push(rustls::Certificate(cert.to_der()?));
+    }
+    let key = openssl::pkey::PKey::private_key_from_pem(key_pem)?.private_key_to_pkcs8()?;
+
+    builder
+        .with_client_auth_cert(certs, rustls::PrivateKey(key))
+        .map_err(|e| ReplicationError::new(format!("invalid client certificate: {}", e)))
+}
+
+// CaOnlyVerifier verifies the certificate chain of the server like rustls::client::WebPkiVerifier,
+// but accepts any server name.
+struct CaOnlyVerifier {
+    roots: rustls::RootCertStore,
+}
+
+impl rustls::client::ServerCertVerifier for CaOnlyVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        intermediates: &[rustls::Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        let cert = rustls::server::ParsedCertificate::try_from(end_entity)?;
+        rustls::client::verify_server_cert_signed_by_trust_anchor(
+            &cert,
+            &self.roots,
+            intermediates,
+            now,
+        )?;
+
+        Ok(rustls::client::ServerCertVerified::assertion())
+    }
+}
+
 pub fn decompress_mariadb_data(data: &[u8]) -> io::Result<Vec<u8>> {
     // algorithm always 0=zlib
     // algorithm := (data[pos] & 0x07) >> 4
@@ -391,7 +476,7 @@ pub fn format_binary_time(n: usize, data: &[u8]) -> io::Result<Vec<u8>> {
         return Ok("0000-00-00".as_bytes().to_vec());
     }
 
-    let sign = if data[0] == 1 { '-' } else { char::from(0) };
+    let sign = if data[0] == 1 { "-" } else { "" };
 
     match n {
         8 => {
@@ -1,7 +1,7 @@
 #[cfg(test)]
 mod tests {
     use crate::error::ReplicationError;
-    use crate::mysql::compare_server_versions;
+    use crate::mysql::{compare_server_versions, get_net_proto};
     use std::cmp::Ordering;
 
     #[test]
@@ -57,4 +57,11 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_get_net_proto() {
+        assert_eq!(get_net_proto("127.0.0.1:3306"), "tcp");
+        assert_eq!(get_net_proto("[::1]:3306"), "tcp");
+        assert_eq!(get_net_proto("/var/run/mysqld/mysqld.sock"), "unix");
+    }
 }
@@ -0,0 +1,437 @@
+use crate::error::{MysqlError, ReplicationError};
+use crate::mysql;
+use crate::packet::{decompress, new_compressed_packet, AsyncStream};
+use byteorder::{ByteOrder, LittleEndian};
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::select;
+use tokio_context::context;
+
+pub const ERR_CONTEXT_CANCELED: &str = "context canceled";
+
+// the size of the read buffer, like the one of packet::Conn
+const _READ_BUF_SIZE: usize = 64 * 1024;
+
+// AsyncConn reads and writes the packets of the MySQL protocol over a tokio stream, the async counterpart of Conn.
+// A task waiting on it doesn't park a worker thread, see client::AsyncConn for the handshake.
+#[derive(Debug)]
+pub struct AsyncConn<S = AsyncStream> {
+    // the writes go to the stream directly
+    _conn: BufReader<S>,
+    // reading a packet fails with ReadTimeout if it takes longer
+    _read_timeout: Option<Duration>,
+    pub sequence: u8,
+    pub compression: u8,
+    pub compressed_sequence: u8,
+    // the payload of the last compressed packet read, packets are read out of it from _decompressed_pos
+    _decompressed: Vec<u8>,
+    _decompressed_pos: usize,
+}
+
+impl AsyncConn<AsyncStream> {
+    // Connect dials addr, ip:port or the path of a unix socket, it gives up when ctx is done.
+    pub async fn connect(
+        mut ctx: context::Context,
+        addr: &str,
+    ) -> Result<AsyncConn<AsyncStream>, ReplicationError> {
+        select! {
+            _ = ctx.done() => {
+                Err(ReplicationError::new(format!("connect {}: {}", addr, ERR_CONTEXT_CANCELED)))
+            }
+            rs = AsyncConn::_dial(addr) => {
+                Ok(AsyncConn::new(rs?))
+            }
+        }
+    }
+
+    async fn _dial(addr: &str) -> Result<AsyncStream, ReplicationError> {
+        #[cfg(unix)]
+        if mysql::get_net_proto(addr) == "unix" {
+            let conn = tokio::net::UnixStream::connect(addr).await.map_err(|e| {
+                ReplicationError::new(format!("connect unix socket {} failed: {}", addr, e))
+            })?;
+            return Ok(AsyncStream::Unix(Arc::new(conn)));
+        }
+
+        Ok(AsyncStream::Tcp(Arc::new(
+            tokio::net::TcpStream::connect(addr).await?,
+        )))
+    }
+
+    // UpgradeTls switches the connection to TLS after the SSLRequest packet, the sequence goes on.
+    pub async fn upgrade_tls(
+        &mut self,
+        config: Arc<rustls::ClientConfig>,
+        server_name: &str,
+    ) -> Result<(), ReplicationError> {
+        if !self._conn.buffer().is_empty() {
+            return Err(ReplicationError::new(
+                "can't upgrade to TLS with buffered data".to_string(),
+            ));
+        }
+
+        let conn = self
+            ._conn
+            .get_ref()
+            .upgrade_tls(config, server_name)
+            .await?;
+        self._conn = BufReader::with_capacity(_READ_BUF_SIZE, conn);
+
+        Ok(())
+    }
+
+    pub fn is_tls(&self) -> bool {
+        self._conn.get_ref().is_tls()
+    }
+
+    pub fn set_recv_buffer_size(&self, size: usize) -> Result<(), ReplicationError> {
+        self._conn.get_ref().set_recv_buffer_size(size)?;
+
+        Ok(())
+    }
+
+    // TryClone returns an AsyncConn on the same stream, so packets can be written while another task is reading.
+    pub fn try_clone(&self) -> AsyncConn<AsyncStream> {
+        let mut c = AsyncConn::new(self._conn.get_ref().try_clone());
+        c.compression = self.compression;
+        c
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncConn<S> {
+    pub fn new(conn: S) -> AsyncConn<S> {
+        AsyncConn {
+            _conn: BufReader::with_capacity(_READ_BUF_SIZE, conn),
+            _read_timeout: None,
+            sequence: 0,
+            compression: 0,
+            compressed_sequence: 0,
+            _decompressed: vec![],
+            _decompressed_pos: 0,
+        }
+    }
+
+    // SetReadTimeout sets the time a packet has to be read in.
+    // A read timing out fails with ReplicationError::ReadTimeout, None or a zero timeout disables it.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self._read_timeout = timeout.filter(|t| !t.is_zero());
+    }
+
+    pub async fn read_packet(&mut self) -> Result<Vec<u8>, ReplicationError> {
+        let mut buf = Vec::<u8>::new();
+        self.read_packet_into(&mut buf).await?;
+
+        Ok(buf)
+    }
+
+    // ReadPacketInto reads the payload of the next packet into dst, which is cleared first,
+    // see Conn::read_packet_into.
+    pub async fn read_packet_into(&mut self, dst: &mut Vec<u8>) -> Result<(), ReplicationError> {
+        dst.clear();
+
+        match self._read_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self._read_payloads(dst))
+                .await
+                .map_err(|_| ReplicationError::ReadTimeout(timeout))?,
+            None => self._read_payloads(dst).await,
+        }
+    }
+
+    // reads the payloads of a packet and of the packets continuing it to the end of buf
+    async fn _read_payloads(&mut self, buf: &mut Vec<u8>) -> Result<(), ReplicationError> {
+        loop {
+            let length = self._read_header().await?;
+
+            let start = buf.len();
+            buf.resize(start + length, 0);
+            self._read_exact(&mut buf[start..]).await.map_err(|e| {
+                ReplicationError::new(format!(
+                    "{}. io.ReadFull(payload) failed. err {}, expected {}",
+                    MysqlError::ErrBadConn,
+                    e,
+                    length
+                ))
+            })?;
+
+            // a payload of MAX_PAYLOAD_LEN bytes is continued by the next packet
+            if length < mysql::MAX_PAYLOAD_LEN {
+                return Ok(());
+            }
+        }
+    }
+
+    // reads a packet header and checks its sequence, returns the payload length
+    async fn _read_header(&mut self) -> Result<usize, ReplicationError> {
+        let mut header = [0_u8; 4];
+        self._read_exact(&mut header).await.map_err(|e| {
+            ReplicationError::new(format!(
+                "{}. io.ReadFull(header) failed. err: {}",
+                MysqlError::ErrBadConn,
+                e
+            ))
+        })?;
+
+        let length = LittleEndian::read_u24(&header) as usize;
+        let sequence = header[3];
+
+        // like the MySQL clients, the sequence of the packets inside the compressed packets is not checked
+        if sequence != self.sequence && self.compression == mysql::MYSQL_COMPRESS_NONE {
+            return Err(ReplicationError::new(format!(
+                "invalid sequence {} != {}",
+                sequence, self.sequence
+            )));
+        }
+        self.sequence = sequence.wrapping_add(1);
+
+        Ok(length)
+    }
+
+    // reads the packets out of the compressed packets of the stream if the compression is on
+    async fn _read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        if self.compression == mysql::MYSQL_COMPRESS_NONE {
+            self._conn.read_exact(buf).await?;
+            return Ok(());
+        }
+
+        let mut pos = 0;
+        while pos < buf.len() {
+            while self._decompressed_pos >= self._decompressed.len() {
+                self._read_compressed_packet().await?;
+            }
+
+            let n = (buf.len() - pos).min(self._decompressed.len() - self._decompressed_pos);
+            buf[pos..pos + n].copy_from_slice(
+                &self._decompressed[self._decompressed_pos..self._decompressed_pos + n],
+            );
+            self._decompressed_pos += n;
+            pos += n;
+        }
+
+        Ok(())
+    }
+
+    async fn _read_compressed_packet(&mut self) -> io::Result<()> {
+        let mut header = [0_u8; 7];
+        self._conn.read_exact(&mut header).await?;
+
+        let compressed_length = LittleEndian::read_u24(&header) as usize;
+        let uncompressed_length = LittleEndian::read_u24(&header[4..]) as usize;
+        // the sequence is not checked, see CompressedReader
+        self.compressed_sequence = header[3].wrapping_add(1);
+
+        let mut payload = vec![0_u8; compressed_length];
+        self._conn.read_exact(&mut payload).await?;
+
+        self._decompressed = decompress(self.compression, payload, uncompressed_length)?;
+        self._decompressed_pos = 0;
+        Ok(())
+    }
+
+    // ReadPacketWithContext is ReadPacket that gives up when ctx is done,
+    // a packet may be read partially then, so the connection must be closed after the cancellation.
+    pub async fn read_packet_with_context(
+        &mut self,
+        mut ctx: context::Context,
+    ) -> Result<Vec<u8>, ReplicationError> {
+        select! {
+            _ = ctx.done() => {
+                Err(ReplicationError::new(ERR_CONTEXT_CANCELED.to_string()))
+            }
+            rs = self.read_packet() => {
+                rs
+            }
+        }
+    }
+
+    // WritePacket: data already has 4 bytes header
+    // will modify data inplace
+    pub async fn write_packet(&mut self, mut data: &mut [u8]) -> Result<(), ReplicationError> {
+        let mut length = data.len() - 4;
+        while length >= mysql::MAX_PAYLOAD_LEN {
+            data[0] = 0xff;
+            data[1] = 0xff;
+            data[2] = 0xff;
+            data[3] = self.sequence;
+
+            let write_len = 4 + mysql::MAX_PAYLOAD_LEN;
+            self._write_raw(&data[..write_len]).await.map_err(|e| {
+                ReplicationError::new(format!(
+                    "{}. Write(payload portion) failed. err {}",
+                    MysqlError::ErrBadConn,
+                    e
+                ))
+            })?;
+
+            self.sequence = self.sequence.wrapping_add(1);
+            length -= mysql::MAX_PAYLOAD_LEN;
+            data = &mut data[mysql::MAX_PAYLOAD_LEN..];
+        }
+
+        data[0] = length as u8;
+        data[1] = (length >> 8) as u8;
+        data[2] = (length >> 16) as u8;
+        data[3] = self.sequence;
+
+        self._write_raw(data).await.map_err(|e| {
+            ReplicationError::new(format!(
+                "{}. Write failed. err {}",
+                MysqlError::ErrBadConn,
+                e
+            ))
+        })?;
+        self._conn.flush().await?;
+
+        self.sequence = self.sequence.wrapping_add(1);
+
+        Ok(())
+    }
+
+    // writes the packets in data to the stream, in compressed packets if the compression is on
+    async fn _write_raw(&mut self, data: &[u8]) -> Result<(), ReplicationError> {
+        match self.compression {
+            mysql::MYSQL_COMPRESS_NONE => {
+                self._conn.write_all(data).await?;
+                Ok(())
+            }
+            mysql::MYSQL_COMPRESS_ZLIB | mysql::MYSQL_COMPRESS_ZSTD => {
+                // the length of a compressed packet is 3 bytes too
+                for chunk in data.chunks(mysql::MAX_PAYLOAD_LEN) {
+                    let compressed_packet =
+                        new_compressed_packet(self.compression, self.compressed_sequence, chunk)?;
+                    self.compressed_sequence = self.compressed_sequence.wrapping_add(1);
+                    self._conn.write_all(&compressed_packet).await?;
+                }
+                Ok(())
+            }
+            _ => Err(ReplicationError::new(format!(
+                "Unsuppored compression algorithm {}",
+                self.compression
+            ))),
+        }
+    }
+
+    // WritePacketWithContext is WritePacket that gives up when ctx is done,
+    // a packet may be written partially then, so the connection must be closed after the cancellation.
+    pub async fn write_packet_with_context(
+        &mut self,
+        mut ctx: context::Context,
+        data: &mut [u8],
+    ) -> Result<(), ReplicationError> {
+        select! {
+            _ = ctx.done() => {
+                Err(ReplicationError::new(ERR_CONTEXT_CANCELED.to_string()))
+            }
+            rs = self.write_packet(data) => {
+                rs
+            }
+        }
+    }
+
+    // WriteClearAuthPacket: Client clear text authentication packet
+    // http://dev.mysql.com/doc/internals/en/connection-phase-packets.html#packet-Protocol::AuthSwitchResponse
+    pub async fn write_clear_auth_packet(
+        &mut self,
+        password: &str,
+    ) -> Result<(), ReplicationError> {
+        // the clear password [null terminated string]
+        let mut data = vec![0_u8; 4];
+        data.extend(password.as_bytes());
+        data.push(0x00);
+
+        self.write_packet(&mut data)
+            .await
+            .map_err(|e| ReplicationError::new(format!("{} WritePacket failed", e)))
+    }
+
+    // WritePublicKeyAuthPacket: Caching sha2 authentication. Public key request and send encrypted password
+    // http://dev.mysql.com/doc/internals/en/connection-phase-packets.html#packet-Protocol::AuthSwitchResponse
+    pub async fn write_public_key_auth_packet(
+        &mut self,
+        password: &str,
+        cipher: &[u8],
+    ) -> Result<(), ReplicationError> {
+        // request public key
+        let mut data = vec![0_u8; 4 + 1];
+        data[4] = 2; // cachingSha2PasswordRequestPublicKey
+
+        self.write_packet(&mut data)
+            .await
+            .map_err(|e| ReplicationError::new(format!("{} WritePacket(single byte) failed", e)))?;
+
+        let data = self
+            .read_packet()
+            .await
+            .map_err(|e| ReplicationError::new(format!("{} ReadPacket failed", e)))?;
+
+        // skip the more data header 0x01
+        let pub_key = openssl::pkey::PKey::public_key_from_pem(data.get(1..).unwrap_or_default())
+            .map_err(|e| {
+            ReplicationError::new(format!("{}.  public_key_from_pem failed", e))
+        })?;
+
+        let encrypted_data = mysql::encrypt_password(password, cipher, &pub_key)?;
+
+        let mut data = vec![0_u8; 4];
+        data.extend(encrypted_data);
+
+        self.write_packet(&mut data)
+            .await
+            .map_err(|e| ReplicationError::new(format!("{}.  WritePacket failed", e)))
+    }
+
+    pub async fn write_encrypted_password(
+        &mut self,
+        password: &str,
+        seed: &[u8],
+        pub_key: &openssl::pkey::PKey<openssl::pkey::Public>,
+    ) -> Result<(), ReplicationError> {
+        let enc = mysql::encrypt_password(password, seed, pub_key)
+            .map_err(|e| ReplicationError::new(format!("{}. EncryptPassword failed", e)))?;
+
+        self.write_auth_switch_packet(&enc, false)
+            .await
+            .map_err(|e| ReplicationError::new(format!("{}. WriteAuthSwitchPacket failed", e)))
+    }
+
+    // http://dev.mysql.com/doc/internals/en/connection-phase-packets.html#packet-Protocol::AuthSwitchResponse
+    pub async fn write_auth_switch_packet(
+        &mut self,
+        auth_data: &[u8],
+        add_nul: bool,
+    ) -> Result<(), ReplicationError> {
+        // the auth data [EOF]
+        let mut data = vec![0_u8; 4];
+        data.extend(auth_data);
+        if add_nul {
+            data.push(0x00);
+        }
+
+        self.write_packet(&mut data)
+            .await
+            .map_err(|e| ReplicationError::new(format!("{}. WritePacket failed", e)))
+    }
+
+    pub fn reset_sequence(&mut self) {
+        self.sequence = 0;
+        self.compressed_sequence = 0;
+    }
+
+    // Close shuts down the write side of the stream, the server sees the end of the connection.
+    pub async fn close(&mut self) -> Result<(), ReplicationError> {
+        self.sequence = 0;
+        self._conn.shutdown().await?;
+
+        Ok(())
+    }
+
+    pub fn get_ref(&self) -> &S {
+        self._conn.get_ref()
+    }
+
+    // IntoInner returns the stream, the data read into the buffer but not returned by a read is lost.
+    pub fn into_inner(self) -> S {
+        self._conn.into_inner()
+    }
+}
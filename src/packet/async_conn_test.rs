@@ -0,0 +1,103 @@
+#[cfg(test)]
+mod tests {
+    use crate::error::ReplicationError;
+    use crate::mysql;
+    use crate::packet::{AsyncConn, ERR_CONTEXT_CANCELED};
+    use std::time::Duration;
+    use tokio_context::context;
+
+    fn packet(payload: &[u8]) -> Vec<u8> {
+        let mut data = vec![0_u8; 4];
+        data.extend(payload);
+        data
+    }
+
+    #[tokio::test]
+    async fn test_write_read_packet() -> Result<(), ReplicationError> {
+        let (client, server) = tokio::io::duplex(1024);
+        let (mut client, mut server) = (AsyncConn::new(client), AsyncConn::new(server));
+
+        client.write_packet(&mut packet(b"\x03SELECT 1")).await?;
+        client.write_packet(&mut packet(b"\x03SELECT 2")).await?;
+        assert_eq!(client.sequence, 2);
+
+        assert_eq!(server.read_packet().await?, b"\x03SELECT 1");
+        assert_eq!(server.read_packet().await?, b"\x03SELECT 2");
+        assert_eq!(server.sequence, 2);
+
+        // the sequence of the next packet doesn't match after a reset
+        server.reset_sequence();
+        client.write_packet(&mut packet(b"\x0e")).await?;
+        let err = server.read_packet().await.unwrap_err();
+        assert_eq!(err.to_string(), "invalid sequence 2 != 0");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_write_read_big_packet() -> Result<(), ReplicationError> {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let (mut client, mut server) = (AsyncConn::new(client), AsyncConn::new(server));
+
+        // the payload is split into a packet of MAX_PAYLOAD_LEN bytes and a packet of the rest
+        let payload: Vec<u8> = (0..mysql::MAX_PAYLOAD_LEN + 10).map(|i| i as u8).collect();
+        let expected = payload.clone();
+        let writer = tokio::spawn(async move {
+            client.write_packet(&mut packet(&payload)).await.unwrap();
+            client
+        });
+
+        assert_eq!(server.read_packet().await?, expected);
+        assert_eq!(server.sequence, 2);
+        assert_eq!(writer.await.unwrap().sequence, 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_packet_with_context_canceled() -> Result<(), ReplicationError> {
+        let (_client, server) = tokio::io::duplex(1024);
+        let mut server = AsyncConn::new(server);
+
+        let (ctx, handle) = context::Context::new();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            handle.cancel();
+        });
+
+        let err =
+            tokio::time::timeout(Duration::from_secs(5), server.read_packet_with_context(ctx))
+                .await
+                .expect("the read must be canceled")
+                .unwrap_err();
+        assert_eq!(err.to_string(), ERR_CONTEXT_CANCELED);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_connect() -> Result<(), ReplicationError> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?.to_string();
+        let server = tokio::spawn(async move {
+            let (conn, _) = listener.accept().await.unwrap();
+            let mut conn = AsyncConn::new(conn);
+            conn.write_packet(&mut packet(&[mysql::OK_HEADER, 0, 0, 0x02, 0, 0, 0]))
+                .await
+                .unwrap();
+            // the client closes the connection
+            assert!(conn.read_packet().await.is_err());
+        });
+
+        let (ctx, _handle) = context::Context::new();
+        let mut c = AsyncConn::connect(ctx, &addr).await?;
+        let data = c
+            .read_packet_with_context(context::Context::new().0)
+            .await?;
+        assert_eq!(data[0], mysql::OK_HEADER);
+        c.close().await?;
+
+        server.await.unwrap();
+        Ok(())
+    }
+}
@@ -0,0 +1,423 @@
+use crate::error::ReplicationError;
+use crate::packet::Stream;
+use std::future::poll_fn;
+use std::io;
+use std::io::{Read, Write};
+use std::net;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+
+// the size of the socket reads of a TLS stream, a TLS record is at most 16KB plus the overhead
+const _TLS_READ_BUF_SIZE: usize = 18 * 1024;
+
+// AsyncStream is the transport of an AsyncConn, a TCP socket, a unix domain socket or the TLS session
+// upgraded from a TCP socket after the SSLRequest packet.
+// Its clones share the socket, so one task can write (e.g. semi-sync ACKs) while another one is waiting to read.
+// Only one clone should read, and only one should write at a time.
+#[derive(Debug)]
+pub enum AsyncStream {
+    Tcp(Arc<TcpStream>),
+    #[cfg(unix)]
+    Unix(Arc<UnixStream>),
+    Tls(AsyncTlsStream),
+}
+
+impl AsyncStream {
+    // FromStd converts a stream of a Dialer, it must be called in a tokio runtime.
+    pub fn from_std(stream: Stream) -> Result<AsyncStream, ReplicationError> {
+        match stream {
+            Stream::Tcp(s) => {
+                s.set_nonblocking(true)?;
+                Ok(AsyncStream::Tcp(Arc::new(TcpStream::from_std(s)?)))
+            }
+            #[cfg(unix)]
+            Stream::Unix(s) => {
+                s.set_nonblocking(true)?;
+                Ok(AsyncStream::Unix(Arc::new(UnixStream::from_std(s)?)))
+            }
+            Stream::Tls(_) => Err(ReplicationError::new(
+                "a TLS stream can't be converted, the TLS handshake must run on the AsyncStream"
+                    .to_string(),
+            )),
+        }
+    }
+
+    // UpgradeTls runs the TLS handshake over the TCP socket, server_name is the name the certificate is verified against.
+    pub async fn upgrade_tls(
+        &self,
+        config: Arc<rustls::ClientConfig>,
+        server_name: &str,
+    ) -> Result<AsyncStream, ReplicationError> {
+        match self {
+            AsyncStream::Tcp(sock) => Ok(AsyncStream::Tls(
+                AsyncTlsStream::connect(sock.clone(), config, server_name).await?,
+            )),
+            #[cfg(unix)]
+            AsyncStream::Unix(_) => Err(ReplicationError::new(
+                "TLS is not supported over a unix socket".to_string(),
+            )),
+            AsyncStream::Tls(_) => Err(ReplicationError::new(
+                "the connection is already using TLS".to_string(),
+            )),
+        }
+    }
+
+    pub fn is_tls(&self) -> bool {
+        matches!(self, AsyncStream::Tls(_))
+    }
+
+    pub fn try_clone(&self) -> AsyncStream {
+        match self {
+            AsyncStream::Tcp(s) => AsyncStream::Tcp(s.clone()),
+            #[cfg(unix)]
+            AsyncStream::Unix(s) => AsyncStream::Unix(s.clone()),
+            AsyncStream::Tls(s) => AsyncStream::Tls(s.try_clone()),
+        }
+    }
+
+    // Close shuts both directions of the socket down, the reads waiting on any clone of the stream return.
+    pub fn close(&self) -> io::Result<()> {
+        match self {
+            AsyncStream::Tcp(s) => socket2::SockRef::from(s.as_ref()).shutdown(net::Shutdown::Both),
+            #[cfg(unix)]
+            AsyncStream::Unix(s) => {
+                socket2::SockRef::from(s.as_ref()).shutdown(net::Shutdown::Both)
+            }
+            AsyncStream::Tls(s) => s.close(),
+        }
+    }
+
+    // SetRecvBufferSize sets SO_RCVBUF, the size of the receive buffer of the operating system.
+    pub fn set_recv_buffer_size(&self, size: usize) -> io::Result<()> {
+        match self {
+            AsyncStream::Tcp(s) => socket2::SockRef::from(s.as_ref()).set_recv_buffer_size(size),
+            #[cfg(unix)]
+            AsyncStream::Unix(s) => socket2::SockRef::from(s.as_ref()).set_recv_buffer_size(size),
+            AsyncStream::Tls(s) => {
+                socket2::SockRef::from(s._sock.as_ref()).set_recv_buffer_size(size)
+            }
+        }
+    }
+}
+
+// the sockets are polled through a shared reference, tokio keeps a waker for the reads and one for the writes
+fn _poll_read_tcp(
+    sock: &TcpStream,
+    cx: &mut Context<'_>,
+    buf: &mut [u8],
+) -> Poll<io::Result<usize>> {
+    loop {
+        ready!(sock.poll_read_ready(cx))?;
+        match sock.try_read(buf) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            rs => return Poll::Ready(rs),
+        }
+    }
+}
+
+fn _poll_write_tcp(sock: &TcpStream, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+    loop {
+        ready!(sock.poll_write_ready(cx))?;
+        match sock.try_write(buf) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            rs => return Poll::Ready(rs),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn _poll_read_unix(
+    sock: &UnixStream,
+    cx: &mut Context<'_>,
+    buf: &mut [u8],
+) -> Poll<io::Result<usize>> {
+    loop {
+        ready!(sock.poll_read_ready(cx))?;
+        match sock.try_read(buf) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            rs => return Poll::Ready(rs),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn _poll_write_unix(
+    sock: &UnixStream,
+    cx: &mut Context<'_>,
+    buf: &[u8],
+) -> Poll<io::Result<usize>> {
+    loop {
+        ready!(sock.poll_write_ready(cx))?;
+        match sock.try_write(buf) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            rs => return Poll::Ready(rs),
+        }
+    }
+}
+
+impl AsyncRead for AsyncStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let n = match self.get_mut() {
+            AsyncStream::Tcp(s) => ready!(_poll_read_tcp(s, cx, buf.initialize_unfilled()))?,
+            #[cfg(unix)]
+            AsyncStream::Unix(s) => ready!(_poll_read_unix(s, cx, buf.initialize_unfilled()))?,
+            AsyncStream::Tls(s) => ready!(s.poll_read(cx, buf.initialize_unfilled()))?,
+        };
+        buf.advance(n);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for AsyncStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            AsyncStream::Tcp(s) => _poll_write_tcp(s, cx, buf),
+            #[cfg(unix)]
+            AsyncStream::Unix(s) => _poll_write_unix(s, cx, buf),
+            AsyncStream::Tls(s) => s.poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            AsyncStream::Tls(s) => s.poll_flush(cx),
+            _ => Poll::Ready(Ok(())),
+        }
+    }
+
+    // the write side is shut down, the peer sees the end of the stream
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            AsyncStream::Tcp(s) => {
+                Poll::Ready(socket2::SockRef::from(s.as_ref()).shutdown(net::Shutdown::Write))
+            }
+            #[cfg(unix)]
+            AsyncStream::Unix(s) => {
+                Poll::Ready(socket2::SockRef::from(s.as_ref()).shutdown(net::Shutdown::Write))
+            }
+            AsyncStream::Tls(s) => s.poll_shutdown(cx),
+        }
+    }
+}
+
+// AsyncTlsStream is a client TLS session over a TCP socket, the async counterpart of TlsStream.
+// Its clones share the session, the socket is read without holding the session lock.
+#[derive(Debug)]
+pub struct AsyncTlsStream {
+    _session: Arc<Mutex<rustls::ClientConnection>>,
+    _sock: Arc<TcpStream>,
+    // TLS records read from the socket but not handed to the session yet
+    _pending: Vec<u8>,
+    _pending_pos: usize,
+    // set once poll_shutdown has queued the close_notify alert, a shutdown polled again doesn't queue another one
+    _close_notify_sent: bool,
+}
+
+// TryWriter writes the TLS records of a session to the socket without waiting,
+// the records it can't write stay in the session.
+struct TryWriter<'a>(&'a TcpStream);
+
+impl<'a> Write for TryWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.try_write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncTlsStream {
+    pub async fn connect(
+        sock: Arc<TcpStream>,
+        config: Arc<rustls::ClientConfig>,
+        server_name: &str,
+    ) -> Result<AsyncTlsStream, ReplicationError> {
+        let name = rustls::ServerName::try_from(server_name).map_err(|e| {
+            ReplicationError::new(format!("invalid TLS server name {}: {}", server_name, e))
+        })?;
+        let mut session = rustls::ClientConnection::new(config, name)
+            .map_err(|e| ReplicationError::new(format!("new TLS session failed: {}", e)))?;
+
+        let mut buf = vec![0_u8; _TLS_READ_BUF_SIZE];
+        loop {
+            poll_fn(|cx| Self::_poll_write_tls(&sock, &mut session, cx))
+                .await
+                .map_err(|e| ReplicationError::new(format!("TLS handshake failed: {}", e)))?;
+            if !session.is_handshaking() {
+                break;
+            }
+
+            let n = poll_fn(|cx| _poll_read_tcp(&sock, cx, &mut buf))
+                .await
+                .map_err(|e| ReplicationError::new(format!("TLS handshake failed: {}", e)))?;
+            if n == 0 {
+                return Err(ReplicationError::new(
+                    "TLS handshake failed: the connection was closed".to_string(),
+                ));
+            }
+
+            let mut records = &buf[..n];
+            while !records.is_empty() {
+                session.read_tls(&mut records)?;
+                if let Err(e) = session.process_new_packets() {
+                    // tell the server why, the alert is best effort
+                    let _ = session.write_tls(&mut TryWriter(&sock));
+                    return Err(ReplicationError::new(format!(
+                        "TLS handshake failed: {}",
+                        e
+                    )));
+                }
+            }
+        }
+
+        Ok(AsyncTlsStream {
+            _session: Arc::new(Mutex::new(session)),
+            _sock: sock,
+            _pending: vec![],
+            _pending_pos: 0,
+            _close_notify_sent: false,
+        })
+    }
+
+    pub fn try_clone(&self) -> AsyncTlsStream {
+        AsyncTlsStream {
+            _session: self._session.clone(),
+            _sock: self._sock.clone(),
+            _pending: vec![],
+            _pending_pos: 0,
+            _close_notify_sent: false,
+        }
+    }
+
+    // GetRef returns the TCP socket under the session.
+    pub fn get_ref(&self) -> &TcpStream {
+        &self._sock
+    }
+
+    // Close sends the close_notify alert if the socket takes it right away and shuts the socket down.
+    pub fn close(&self) -> io::Result<()> {
+        if let Ok(mut session) = self._session.lock() {
+            session.send_close_notify();
+            let _ = session.write_tls(&mut TryWriter(&self._sock));
+        }
+
+        socket2::SockRef::from(self._sock.as_ref()).shutdown(net::Shutdown::Both)
+    }
+
+    fn _lock(
+        session: &Mutex<rustls::ClientConnection>,
+    ) -> io::Result<MutexGuard<'_, rustls::ClientConnection>> {
+        session
+            .lock()
+            .map_err(|_| io::Error::other("TLS session lock poisoned"))
+    }
+
+    // writes the TLS records of the session until it has none left
+    fn _poll_write_tls(
+        sock: &TcpStream,
+        session: &mut rustls::ClientConnection,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        while session.wants_write() {
+            ready!(sock.poll_write_ready(cx))?;
+            match session.write_tls(&mut TryWriter(sock)) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Poll::Ready(Err(e)),
+                Ok(_) => {}
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_read(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        loop {
+            let mut session = Self::_lock(&self._session)?;
+            match session.reader().read(buf) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                rs => return Poll::Ready(rs),
+            }
+
+            if self._pending_pos == self._pending.len() {
+                drop(session);
+
+                self._pending.resize(_TLS_READ_BUF_SIZE, 0);
+                let n = match _poll_read_tcp(&self._sock, cx, &mut self._pending) {
+                    Poll::Ready(Ok(n)) => n,
+                    rs => {
+                        self._pending.clear();
+                        self._pending_pos = 0;
+                        return rs;
+                    }
+                };
+                self._pending.truncate(n);
+                self._pending_pos = 0;
+                if n > 0 {
+                    continue;
+                }
+
+                // the peer closed the socket, an empty read_tls tells the session
+                session = Self::_lock(&self._session)?;
+                let _ = session.read_tls(&mut io::empty())?;
+            } else {
+                // records are handed over only when there is no plaintext left, so the plaintext buffer never fills up
+                let n = session.read_tls(&mut &self._pending[self._pending_pos..])?;
+                self._pending_pos += n;
+            }
+
+            session
+                .process_new_packets()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            // the records the session answers with, e.g. alerts, go out with the next write if the socket is busy
+            match session.write_tls(&mut TryWriter(&self._sock)) {
+                Err(e) if e.kind() != io::ErrorKind::WouldBlock => return Poll::Ready(Err(e)),
+                _ => {}
+            }
+        }
+    }
+
+    fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let mut session = Self::_lock(&self._session)?;
+        // the records of the previous writes go first, so the session never buffers more than one write
+        ready!(Self::_poll_write_tls(&self._sock, &mut session, cx))?;
+
+        let n = session.writer().write(buf)?;
+        // the data is taken once it's in the session, the rest of the records are written by the flush
+        if let Poll::Ready(Err(e)) = Self::_poll_write_tls(&self._sock, &mut session, cx) {
+            return Poll::Ready(Err(e));
+        }
+
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut session = Self::_lock(&self._session)?;
+        Self::_poll_write_tls(&self._sock, &mut session, cx)
+    }
+
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut session = Self::_lock(&self._session)?;
+        if !self._close_notify_sent {
+            session.send_close_notify();
+            self._close_notify_sent = true;
+        }
+        ready!(Self::_poll_write_tls(&self._sock, &mut session, cx))?;
+
+        Poll::Ready(socket2::SockRef::from(self._sock.as_ref()).shutdown(net::Shutdown::Write))
+    }
+}
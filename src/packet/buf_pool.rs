@@ -0,0 +1,46 @@
+use std::sync::{Arc, Mutex};
+
+// buffers grown over this size by a big packet are not kept in the pool
+pub const TOO_BIG_BLOCK_SIZE: usize = 1024 * 1024 * 4;
+
+// BufPool keeps the buffers given back by Put, so reading a packet with ReadPacketInto doesn't allocate.
+// Its clones share the buffers.
+#[derive(Debug, Clone, Default)]
+pub struct BufPool {
+    _bufs: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl BufPool {
+    pub fn new() -> BufPool {
+        BufPool {
+            _bufs: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    // Get returns an empty buffer, with the capacity left by its last use if it comes from the pool.
+    pub fn get(&self) -> Vec<u8> {
+        match self._bufs.lock() {
+            Ok(mut bufs) => bufs.pop().unwrap_or_default(),
+            Err(_) => vec![],
+        }
+    }
+
+    pub fn put(&self, mut buf: Vec<u8>) {
+        if buf.capacity() > TOO_BIG_BLOCK_SIZE {
+            return;
+        }
+
+        buf.clear();
+        if let Ok(mut bufs) = self._bufs.lock() {
+            bufs.push(buf);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self._bufs.lock().map(|bufs| bufs.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
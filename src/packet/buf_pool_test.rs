@@ -0,0 +1,30 @@
+#[cfg(test)]
+mod tests {
+    use crate::packet::{BufPool, TOO_BIG_BLOCK_SIZE};
+
+    #[test]
+    fn test_buf_pool() {
+        let pool = BufPool::new();
+        assert!(pool.is_empty());
+        assert_eq!(pool.get().capacity(), 0);
+
+        let mut buf = pool.get();
+        buf.extend([1_u8; 100]);
+        pool.put(buf);
+        assert_eq!(pool.len(), 1);
+
+        // the buffer is given back empty, with its memory
+        let buf = pool.get();
+        assert!(buf.is_empty());
+        assert_eq!(buf.capacity(), 100);
+        assert!(pool.is_empty());
+
+        // the clones share the buffers
+        pool.clone().put(buf);
+        assert_eq!(pool.len(), 1);
+
+        // a buffer grown by a big packet is dropped
+        pool.put(Vec::with_capacity(TOO_BIG_BLOCK_SIZE + 1));
+        assert_eq!(pool.len(), 1);
+    }
+}
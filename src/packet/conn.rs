@@ -1,214 +1,261 @@
 use crate::error::{MysqlError, ReplicationError};
 use crate::mysql;
-use std::io::{BufReader, Read, Write};
-use std::net;
-
-const _CONN_READ_BUFFER_SIZE: usize = 65536;
-
-pub struct Conn {
-    _conn: Option<net::TcpStream>,
-    // we removed the buffer reader because it will cause the SSLRequest to block (tls connection handshake won't be
-    // able to read the "Client Hello" data since it has been buffered into the buffer reader)
-    // @todo
-    // bufPool *BufPool
-    // br      *bufio.Reader
-    // reader  io.Reader
+use crate::packet::{Stream, Transport};
+use byteorder::{ByteOrder, LittleEndian};
+use std::io;
+use std::io::{Read, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// the size of the read buffer installed by UseBufferedReader
+const READ_BUF_SIZE: usize = 64 * 1024;
+
+// Conn reads and writes the packets of the MySQL protocol over a transport, a TCP socket or a TLS session by default.
+#[derive(Debug)]
+pub struct Conn<S = Stream> {
+    _conn: Option<S>,
+    // the read buffer of the transport, it's empty until UseBufferedReader is called,
+    // a buffer installed before the TLS handshake would swallow the "Server Hello" data
+    _rbuf: Vec<u8>,
+    _rpos: usize,
+    _rend: usize,
+    // reading a packet fails with ReadTimeout if it takes longer
+    _read_timeout: Option<Duration>,
     _copy_n_buf: Vec<u8>,
     _header: [u8; 4],
     pub sequence: u8,
     pub compression: u8,
     pub compressed_sequence: u8,
-    _compressed_header: [u8; 7],
-    compressed_reader_active: bool,
-    // @todo
-    // compressedReader io.Reader
+    // the payload of the last compressed packet read, packets are read out of it from _decompressed_pos
+    _decompressed: Vec<u8>,
+    _decompressed_pos: usize,
 }
 
-impl Conn {
-    pub fn new(conn: net::TcpStream) -> Conn {
-        /*
-        c := new(Conn)
-        c.Conn = conn
-
-        c.bufPool = NewBufPool()
-        c.br = bufio.NewReaderSize(c, 65536) // 64kb
-        c.reader = c.br
-
-        c.copyNBuf = make([]byte, 16*1024)
-
-        return c
-             */
-
+impl<S: Transport> Conn<S> {
+    pub fn new(conn: S) -> Conn<S> {
         Conn {
             _conn: Some(conn),
-            _copy_n_buf: Vec::with_capacity(16 * 1024),
+            _rbuf: vec![],
+            _rpos: 0,
+            _rend: 0,
+            _read_timeout: None,
+            _copy_n_buf: vec![0; 16 * 1024],
             _header: [0, 0, 0, 0],
             sequence: 0,
             compression: 0,
             compressed_sequence: 0,
-            _compressed_header: [0, 0, 0, 0, 0, 0, 0],
-            compressed_reader_active: false,
+            _decompressed: vec![],
+            _decompressed_pos: 0,
         }
     }
 
-    pub fn new_tls_conn(conn: net::TcpStream) -> Conn {
-        /*
-        c := new(Conn)
-        c.Conn = conn
+    pub fn new_tls_conn(conn: S) -> Conn<S> {
+        Conn::new(conn)
+    }
 
-        c.bufPool = NewBufPool()
-        c.reader = c
+    // UseBufferedReader reads the transport in blocks of 64KB from now on, instead of a read per packet header
+    // and payload. It must be called after the TLS handshake.
+    pub fn use_buffered_reader(&mut self) {
+        if self._rbuf.is_empty() {
+            self._rbuf = vec![0; READ_BUF_SIZE];
+        }
+    }
 
-        c.copyNBuf = make([]byte, 16*1024)
+    // SetReadTimeout sets the time a packet has to be read in, the deadline is extended for every packet.
+    // A read timing out fails with ReplicationError::ReadTimeout, None or a zero timeout disables it.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<(), ReplicationError> {
+        self._read_timeout = timeout.filter(|t| !t.is_zero());
+        // the timeout of the socket is shared by the clones, a clone can make the reads of the others give up
+        if let Some(conn) = self._conn.as_ref() {
+            conn.set_read_timeout(self._read_timeout)?;
+        }
 
-        return c
-             */
+        Ok(())
+    }
 
-        Conn {
-            _conn: Some(conn),
-            _copy_n_buf: Vec::with_capacity(16 * 1024),
-            _header: [0, 0, 0, 0],
-            sequence: 0,
-            compression: 0,
-            compressed_sequence: 0,
-            _compressed_header: [0, 0, 0, 0, 0, 0, 0],
-            compressed_reader_active: false,
-        }
+    // SetWriteTimeout bounds every write to the transport, None or a zero timeout disables it.
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<(), ReplicationError> {
+        self._conn
+            .as_ref()
+            .ok_or(ReplicationError::from(MysqlError::ErrBadConn))?
+            .set_write_timeout(timeout.filter(|t| !t.is_zero()))?;
+
+        Ok(())
+    }
+
+    pub fn set_recv_buffer_size(&self, size: usize) -> Result<(), ReplicationError> {
+        self._conn
+            .as_ref()
+            .ok_or(ReplicationError::from(MysqlError::ErrBadConn))?
+            .set_recv_buffer_size(size)?;
+
+        Ok(())
     }
 
     pub fn read_packet(&mut self) -> Result<Vec<u8>, ReplicationError> {
-        return self.read_packet_reuse_mem(&mut vec![]);
+        let mut buf = Vec::<u8>::new();
+        self.read_packet_into(&mut buf)?;
+
+        Ok(buf)
     }
 
     pub fn read_packet_reuse_mem(&mut self, dst: &mut [u8]) -> Result<Vec<u8>, ReplicationError> {
-        // Here we use `sync.Pool` to avoid allocate/destroy buffers frequently.
-        let mut buf = Vec::<u8>::new();
+        let mut buf = dst.to_vec();
+        let mut conn = self
+            ._conn
+            .take()
+            .ok_or(ReplicationError::new("conn is none".to_string()))?;
+        let rs = self._read_packet_from(&mut buf, &mut conn);
+        self._conn = Some(conn);
+        rs?;
 
-        let mut reader = BufReader::with_capacity(
-            _CONN_READ_BUFFER_SIZE,
-            self._conn
-                .as_mut()
-                .ok_or(ReplicationError::new("conn is none".to_string()))?,
-        );
-        if self.compression != mysql::MYSQL_COMPRESS_NONE {
-            if !self.compressed_reader_active {
-                let _ = reader
-                    .read_exact(&mut self._compressed_header)
-                    .map_err(|e| {
-                        ReplicationError::new(format!(
-                            "{}. io.ReadFull(compressedHeader) failed. err {}",
-                            MysqlError::ErrBadConn.to_string(),
-                            e.to_string()
-                        ))
-                    })?;
-
-                let compressed_sequence = self._compressed_header[3];
-                let uncompressed_length = (self._compressed_header[4] as u32
-                    | (self._compressed_header[5] as u32) << 8
-                    | (self._compressed_header[6] as u32) << 16)
-                    as usize;
-                if compressed_sequence != self.compressed_sequence {
-                    return Err(ReplicationError::new(format!(
-                        "invalid compressed sequence {} != {}",
-                        compressed_sequence, self.compressed_sequence
-                    )));
-                }
-                if uncompressed_length > 0 {
-                    match self.compression {
-                        mysql::MYSQL_COMPRESS_ZLIB => {}
-                        mysql::MYSQL_COMPRESS_ZSTD => {}
-                        _ => {
-                            return Err(ReplicationError::new(format!(
-                                "invalid compressed type {} != ({}|{})",
-                                self.compression,
-                                mysql::MYSQL_COMPRESS_ZLIB,
-                                mysql::MYSQL_COMPRESS_ZSTD
-                            )))
-                        }
-                    }
-                }
-                self.compressed_reader_active = true;
-            }
-        }
+        Ok(buf)
+    }
 
-        Ok(vec![])
-    }
-    /*
-    func (c *Conn) ReadPacketReuseMem(dst []byte) ([]byte, error) {
-        if c.Compression != MYSQL_COMPRESS_NONE {
-            if !c.compressedReaderActive {
-                if uncompressedLength > 0 {
-                    var err error
-                    switch c.Compression {
-                    case MYSQL_COMPRESS_ZLIB:
-                        c.compressedReader, err = zlib.NewReader(c.reader)
-                    case MYSQL_COMPRESS_ZSTD:
-                        c.compressedReader, err = zstd.NewReader(c.reader)
-                    }
-                    if err != nil {
-                        return nil, err
+    // ReadPacketInto reads the payload of the next packet into dst, which is cleared first.
+    // The payloads of the packets continuing one of MAX_PAYLOAD_LEN bytes are read in place after it,
+    // so with buffers from a BufPool reading a packet doesn't allocate.
+    pub fn read_packet_into(&mut self, dst: &mut Vec<u8>) -> Result<(), ReplicationError> {
+        dst.clear();
+
+        let mut conn = self
+            ._conn
+            .take()
+            .ok_or(ReplicationError::new("conn is none".to_string()))?;
+        let rs = self._read_packet_from(dst, &mut conn);
+        self._conn = Some(conn);
+        rs
+    }
+
+    fn _read_packet_from(
+        &mut self,
+        buf: &mut Vec<u8>,
+        conn: &mut S,
+    ) -> Result<(), ReplicationError> {
+        let mut br = BufferedReader {
+            _conn: conn,
+            _buf: std::mem::take(&mut self._rbuf),
+            _pos: self._rpos,
+            _end: self._rend,
+            _deadline: self._read_timeout.map(|t| Instant::now() + t),
+        };
+
+        let rs = if self.compression == mysql::MYSQL_COMPRESS_NONE {
+            self._read_payloads(buf, &mut br)
+        } else {
+            let mut r = CompressedReader {
+                _conn: &mut br,
+                _compression: self.compression,
+                _sequence: self.compressed_sequence,
+                _buf: std::mem::take(&mut self._decompressed),
+                _pos: self._decompressed_pos,
+            };
+            let rs = self._read_payloads(buf, &mut r);
+
+            self.compressed_sequence = r._sequence;
+            self._decompressed = r._buf;
+            self._decompressed_pos = r._pos;
+            rs
+        };
+
+        self._rbuf = br._buf;
+        self._rpos = br._pos;
+        self._rend = br._end;
+        rs
+    }
+
+    // reads the payloads of a packet and of the packets continuing it to the end of buf
+    fn _read_payloads<R: Read>(
+        &mut self,
+        buf: &mut Vec<u8>,
+        r: &mut R,
+    ) -> Result<(), ReplicationError> {
+        loop {
+            let length = self._read_header(r)?;
+
+            // read_to_end reads into the spare capacity, the payload is neither zeroed nor copied
+            buf.reserve(length);
+            let n = r
+                .by_ref()
+                .take(length as u64)
+                .read_to_end(buf)
+                .map_err(|e| match self._read_timeout {
+                    Some(timeout) if e.kind() == io::ErrorKind::TimedOut => {
+                        ReplicationError::ReadTimeout(timeout)
                     }
-                }
-                c.compressedReaderActive = true
-            }
-        }
+                    _ => ReplicationError::new(format!(
+                        "{}. io.ReadFull(payload) failed. err {}, expected {}",
+                        MysqlError::ErrBadConn,
+                        e,
+                        length
+                    )),
+                })?;
 
-        if c.compressedReader != nil {
-            if err := c.ReadPacketTo(buf, c.compressedReader); err != nil {
-                return nil, errors.Trace(err)
+            if n != length {
+                return Err(ReplicationError::new(format!(
+                    "{}. io.ReadFull(payload) failed. {} bytes read, while {} expected",
+                    MysqlError::ErrBadConn,
+                    n,
+                    length
+                )));
             }
-        } else {
-            if err := c.ReadPacketTo(buf, c.reader); err != nil {
-                return nil, errors.Trace(err)
+
+            if length < mysql::MAX_PAYLOAD_LEN {
+                return Ok(());
             }
         }
+    }
 
-        readBytes := buf.Bytes()
-        readSize := len(readBytes)
-        var result []byte
-        if len(dst) > 0 {
-            result = append(dst, readBytes...)
-            // if read block is big, do not cache buf any more
-            if readSize > utils.TooBigBlockSize {
-                buf = nil
-            }
-        } else {
-            if readSize > utils.TooBigBlockSize {
-                // if read block is big, use read block as result and do not cache buf any more
-                result = readBytes
-                buf = nil
-            } else {
-                result = append(dst, readBytes...)
-            }
+    // reads a packet header and checks its sequence, returns the payload length
+    fn _read_header<R: Read>(&mut self, r: &mut R) -> Result<usize, ReplicationError> {
+        r.read_exact(&mut self._header)
+            .map_err(|e| match self._read_timeout {
+                Some(timeout) if e.kind() == io::ErrorKind::TimedOut => {
+                    ReplicationError::ReadTimeout(timeout)
+                }
+                _ => ReplicationError::new(format!(
+                    "{}. io.ReadFull(header) failed. err: {}",
+                    MysqlError::ErrBadConn,
+                    e
+                )),
+            })?;
+
+        let length = (self._header[0] as u32
+            | (self._header[1] as u32) << 8
+            | (self._header[2] as u32) << 16) as usize;
+        let sequence = self._header[3];
+
+        // like the MySQL clients, the sequence of the packets inside the compressed packets is not checked
+        if sequence != self.sequence && self.compression == mysql::MYSQL_COMPRESS_NONE {
+            return Err(ReplicationError::new(format!(
+                "invalid sequence {} != {}",
+                sequence, self.sequence
+            )));
         }
+        self.sequence = sequence.wrapping_add(1);
 
-        return result, nil
+        Ok(length)
     }
-    */
+
     fn _copy_n<R: Read, W: Write>(
-        &self,
-        std: &mut W,
+        &mut self,
+        dst: &mut W,
         src: &mut R,
         n: usize,
     ) -> Result<usize, ReplicationError> {
         let mut n = n;
         let mut written = 0_usize;
         while n > 0 {
-            let mut bcap = self._copy_n_buf.capacity();
+            let mut bcap = self._copy_n_buf.len();
             if bcap > n {
                 bcap = n
             }
-            let mut buf = Vec::with_capacity(bcap);
-            let _ = src.read_exact(&mut buf)?;
-
-            let wr = std.write(&buf)?;
-            written += wr;
+            let buf = &mut self._copy_n_buf[..bcap];
+            src.read_exact(buf)?;
 
-            if n > buf.len() {
-                break;
-            } else {
-                n -= buf.len();
-            }
+            dst.write_all(buf)?;
+            written += bcap;
+            n -= bcap;
         }
 
         Ok(written)
@@ -219,27 +266,7 @@ impl Conn {
         w: &mut W,
         r: &mut R,
     ) -> Result<(), ReplicationError> {
-        MysqlError::ErrBadConn.to_string();
-        let _ = r.read_exact(&mut self._header).map_err(|e| {
-            ReplicationError::new(format!(
-                "{}. io.ReadFull(header) failed. err: {}",
-                MysqlError::ErrBadConn.to_string(),
-                e.to_string()
-            ))
-        })?;
-
-        let length = (self._header[0] as u32
-            | (self._header[1] as u32) << 8
-            | (self._header[2] as u32) << 16) as usize;
-        let sequence = self._header[3];
-
-        if sequence != self.sequence {
-            return Err(ReplicationError::new(format!(
-                "invalid sequence {} != {}",
-                sequence, self.sequence
-            )));
-        }
-        self.sequence += 1;
+        let length = self._read_header(r)?;
 
         let n = self._copy_n(w, r, length).map_err(|e| {
             ReplicationError::new(format!(
@@ -279,30 +306,17 @@ impl Conn {
             data[3] = self.sequence;
 
             let write_len = 4 + mysql::MAX_PAYLOAD_LEN;
-            let n = self
-                ._conn
-                .as_mut()
-                .ok_or(ReplicationError::new("conn is none".to_string()))?
-                .write(&data[..write_len])
-                .map_err(|e| {
-                    ReplicationError::new(format!(
-                        "{}. Write(payload portion) failed. err {}",
-                        MysqlError::ErrBadConn.to_string(),
-                        e.to_string()
-                    ))
-                })?;
-            if n != write_len {
-                return Err(ReplicationError::new(format!(
-                    "{}. Write(payload portion) failed. only {} bytes written, while {} expected",
-                    MysqlError::ErrBadConn.to_string(),
-                    n,
-                    write_len
-                )));
-            } else {
-                self.sequence += 1;
-                length -= mysql::MAX_PAYLOAD_LEN;
-                data = &mut data[write_len..]
-            }
+            self._write_raw(&data[..write_len]).map_err(|e| {
+                ReplicationError::new(format!(
+                    "{}. Write(payload portion) failed. err {}",
+                    MysqlError::ErrBadConn,
+                    e
+                ))
+            })?;
+
+            self.sequence = self.sequence.wrapping_add(1);
+            length -= mysql::MAX_PAYLOAD_LEN;
+            data = &mut data[mysql::MAX_PAYLOAD_LEN..]
         }
 
         data[0] = length as u8;
@@ -310,177 +324,54 @@ impl Conn {
         data[2] = (length >> 16) as u8;
         data[3] = self.sequence;
 
+        self._write_raw(data).map_err(|e| {
+            ReplicationError::new(format!(
+                "{}. Write failed. err {}",
+                MysqlError::ErrBadConn,
+                e
+            ))
+        })?;
+
+        self.sequence = self.sequence.wrapping_add(1);
+
+        Ok(())
+    }
+
+    // writes the packets in data to the transport, in compressed packets if the compression is on
+    fn _write_raw(&mut self, data: &[u8]) -> Result<(), ReplicationError> {
         match self.compression {
             mysql::MYSQL_COMPRESS_NONE => {
-                let n = self
-                    ._conn
+                self._conn
                     .as_mut()
                     .ok_or(ReplicationError::new("conn is none".to_string()))?
-                    .write(data)
-                    .map_err(|e| {
-                        ReplicationError::new(format!(
-                            "{}. Write failed. err {}",
-                            MysqlError::ErrBadConn.to_string(),
-                            e.to_string()
-                        ))
-                    })?;
-
-                if n != data.len() {
-                    return Err(ReplicationError::new(format!(
-                        "{}. Write failed. only {} bytes written, while {} expected",
-                        MysqlError::ErrBadConn.to_string(),
-                        n,
-                        data.len()
-                    )));
-                }
+                    .write_all(data)?;
+                Ok(())
             }
             mysql::MYSQL_COMPRESS_ZLIB | mysql::MYSQL_COMPRESS_ZSTD => {
-                let n = self._write_compressed(data).map_err(|e| {
-                    ReplicationError::new(format!(
-                        "{}. Write failed. err {}",
-                        MysqlError::ErrBadConn.to_string(),
-                        e.to_string()
-                    ))
-                })?;
-
-                if n != data.len() {
-                    return Err(ReplicationError::new(format!(
-                        "{}. Write failed. only {} bytes written, while {} expected",
-                        MysqlError::ErrBadConn.to_string(),
-                        n,
-                        data.len()
-                    )));
+                // the length of a compressed packet is 3 bytes too
+                for chunk in data.chunks(mysql::MAX_PAYLOAD_LEN) {
+                    self._write_compressed(chunk)?;
                 }
-
-                // @todo
-                // c.compressedReader = nil
-                self.compressed_reader_active = false;
-            }
-            _ => {
-                return Err(ReplicationError::new(format!(
-                    "{}. Write failed. Unsuppored compression algorithm set",
-                    MysqlError::ErrBadConn.to_string(),
-                )));
+                Ok(())
             }
-        }
-
-        self.sequence += 1;
-
-        Ok(())
-    }
-
-    fn _write_compressed(&mut self, data: &mut [u8]) -> Result<usize, ReplicationError> {
-        match self.compression {
-            mysql::MYSQL_COMPRESS_ZLIB => self._write_compressed_zlib(data),
-            mysql::MYSQL_COMPRESS_ZSTD => self._write_compressed_zstd(data),
             _ => Err(ReplicationError::new(format!(
-                "can't found compression type. compression value: {}",
+                "Unsuppored compression algorithm {}",
                 self.compression
             ))),
         }
     }
 
-    fn _write_compressed_zlib(&mut self, mut data: &mut [u8]) -> Result<usize, ReplicationError> {
-        let (mut compressed_length, mut uncompressed_length) = (0_usize, 0_usize);
-        let (mut payload, mut compressed_packet) = (Vec::<u8>::new(), Vec::<u8>::new());
-
-        let min_compress_length = 50_usize;
-        let mut compressed_header = [0, 0, 0, 0, 0, 0, 0];
-
-        let mut n = {
-            let mut w = flate2::write::ZlibEncoder::new(&mut payload, flate2::Compression::new(9));
-            let mut n = 0_usize;
-            if data.len() > min_compress_length {
-                uncompressed_length = data.len();
-                n = w.write(&data)?;
-                drop(w);
-            }
-
-            n
-        };
-
-        if data.len() > min_compress_length {
-            compressed_length = payload.len();
-        } else {
-            compressed_length = data.len()
-        }
-
-        self.compressed_sequence = 0;
-        compressed_header[0] = compressed_length as u8;
-        compressed_header[1] = (compressed_length >> 8) as u8;
-        compressed_header[2] = (compressed_length >> 16) as u8;
-        compressed_header[3] = self.compressed_sequence;
-        compressed_header[4] = (uncompressed_length) as u8;
-        compressed_header[5] = (uncompressed_length >> 8) as u8;
-        compressed_header[6] = (uncompressed_length >> 16) as u8;
-
-        let _ = std::io::Write::write(&mut compressed_packet, &compressed_header)?;
-        self.compressed_sequence += 1;
-
-        if data.len() > min_compress_length {
-            let _ = std::io::Write::write(&mut compressed_packet, &payload)?;
-        } else {
-            n = std::io::Write::write(&mut compressed_packet, &data)?;
-        }
+    fn _write_compressed(&mut self, data: &[u8]) -> Result<(), ReplicationError> {
+        let compressed_packet =
+            new_compressed_packet(self.compression, self.compressed_sequence, data)?;
+        self.compressed_sequence = self.compressed_sequence.wrapping_add(1);
 
-        let _ = self
-            ._conn
+        self._conn
             .as_mut()
             .ok_or(ReplicationError::new("conn is none".to_string()))?
-            .write(&compressed_packet)?;
+            .write_all(&compressed_packet)?;
 
-        Ok(n)
-    }
-
-    fn _write_compressed_zstd(&mut self, mut data: &mut [u8]) -> Result<usize, ReplicationError> {
-        let (mut compressed_length, mut uncompressed_length) = (0_usize, 0_usize);
-        let (mut payload, mut compressed_packet) = (Vec::<u8>::new(), Vec::<u8>::new());
-
-        let min_compress_length = 50_usize;
-        let mut compressed_header = [0, 0, 0, 0, 0, 0, 0];
-        let mut w = zstd::stream::write::Encoder::new(&mut payload, 0).unwrap();
-
-        let mut n = {
-            let mut n = 0_usize;
-            if data.len() > min_compress_length {
-                uncompressed_length = data.len();
-                n = w.write(&data)?;
-                drop(w);
-            }
-            n
-        };
-
-        if data.len() > min_compress_length {
-            compressed_length = payload.len();
-        } else {
-            compressed_length = data.len()
-        }
-
-        self.compressed_sequence = 0;
-        compressed_header[0] = compressed_length as u8;
-        compressed_header[1] = (compressed_length >> 8) as u8;
-        compressed_header[2] = (compressed_length >> 16) as u8;
-        compressed_header[3] = self.compressed_sequence;
-        compressed_header[4] = (uncompressed_length) as u8;
-        compressed_header[5] = (uncompressed_length >> 8) as u8;
-        compressed_header[6] = (uncompressed_length >> 16) as u8;
-
-        let _ = std::io::Write::write(&mut compressed_packet, &compressed_header)?;
-        self.compressed_sequence += 1;
-
-        if data.len() > min_compress_length {
-            let _ = std::io::Write::write(&mut compressed_packet, &payload)?;
-        } else {
-            n = std::io::Write::write(&mut compressed_packet, &data)?;
-        }
-
-        let _ = self
-            ._conn
-            .as_mut()
-            .ok_or(ReplicationError::new("conn is none".to_string()))?
-            .write(&compressed_packet)?;
-
-        Ok(n)
+        Ok(())
     }
 
     // WriteClearAuthPacket: Client clear text authentication packet
@@ -525,27 +416,12 @@ impl Conn {
             .read_packet()
             .map_err(|e| ReplicationError::new(format!("{} ReadPacket failed", e.to_string())))?;
 
-        let pub_key = openssl::pkey::PKey::public_key_from_pem(&data).map_err(|e| {
+        // skip the more data header 0x01
+        let pub_key = openssl::pkey::PKey::public_key_from_pem(&data[1..]).map_err(|e| {
             ReplicationError::new(format!("{}.  public_key_from_pem failed", e.to_string()))
         })?;
 
-        let mut plain = vec![0_u8; password.len() + 1];
-        {
-            plain.copy_from_slice(password.as_bytes());
-        }
-
-        for i in 0..plain.len() {
-            let j = i % cipher.len();
-            plain[i] ^= cipher[j];
-        }
-        let sha1_digest = openssl::sha::sha1(&plain);
-        let rsa = pub_key.rsa()?;
-        let mut encrypted_data = Vec::<u8>::new();
-        let _ = rsa.public_encrypt(
-            &sha1_digest,
-            &mut encrypted_data,
-            openssl::rsa::Padding::PKCS1_OAEP,
-        )?;
+        let encrypted_data = mysql::encrypt_password(password, cipher, &pub_key)?;
 
         let mut data = vec![0_u8; 4 + encrypted_data.len()];
         {
@@ -607,18 +483,251 @@ impl Conn {
 
     pub fn reset_sequence(&mut self) {
         self.sequence = 0;
+        self.compressed_sequence = 0;
+    }
+
+    // TryClone returns a Conn on the same transport, so packets can be written while another thread is reading.
+    pub fn try_clone(&self) -> Result<Conn<S>, ReplicationError> {
+        let conn = self
+            ._conn
+            .as_ref()
+            .ok_or(ReplicationError::from(MysqlError::ErrBadConn))?
+            .try_clone()?;
+
+        let mut c = Conn::new(conn);
+        c.compression = self.compression;
+        Ok(c)
     }
 
     pub fn close(&mut self) -> Result<(), ReplicationError> {
         self.sequence = 0;
-        if let Some(mut conn) = self._conn.take() {
-            let _ = conn.shutdown(net::Shutdown::Both);
+        if let Some(conn) = self._conn.take() {
+            let _ = conn.shutdown();
         }
 
         Ok(())
     }
 }
 
+impl Conn<Stream> {
+    // UpgradeTls switches the connection to TLS after the SSLRequest packet, the sequence goes on.
+    pub fn upgrade_tls(
+        &mut self,
+        config: Arc<rustls::ClientConfig>,
+        server_name: &str,
+    ) -> Result<(), ReplicationError> {
+        if self._rpos < self._rend {
+            return Err(ReplicationError::new(
+                "can't upgrade to TLS with buffered data".to_string(),
+            ));
+        }
+
+        let conn = self
+            ._conn
+            .take()
+            .ok_or(ReplicationError::from(MysqlError::ErrBadConn))?;
+        self._conn = Some(conn.upgrade_tls(config, server_name)?);
+
+        Ok(())
+    }
+
+    pub fn is_tls(&self) -> bool {
+        self._conn.as_ref().is_some_and(|c| c.is_tls())
+    }
+}
+
+// NewCompressedPacket returns the compressed packet of data with its 7 bytes header, data is at most MAX_PAYLOAD_LEN bytes.
+// Small payloads and the ones that don't get smaller are sent uncompressed, with an uncompressed length of 0.
+pub fn new_compressed_packet(
+    compression: u8,
+    sequence: u8,
+    data: &[u8],
+) -> Result<Vec<u8>, ReplicationError> {
+    let min_compress_length = 50_usize;
+
+    let (payload, uncompressed_length) = match data.len() > min_compress_length {
+        true => match compress(compression, data)? {
+            compressed if compressed.len() < data.len() => (compressed, data.len()),
+            _ => (data.to_vec(), 0),
+        },
+        false => (data.to_vec(), 0),
+    };
+
+    let mut compressed_packet = Vec::<u8>::with_capacity(7 + payload.len());
+    compressed_packet.extend([
+        payload.len() as u8,
+        (payload.len() >> 8) as u8,
+        (payload.len() >> 16) as u8,
+        sequence,
+        uncompressed_length as u8,
+        (uncompressed_length >> 8) as u8,
+        (uncompressed_length >> 16) as u8,
+    ]);
+    compressed_packet.extend(payload);
+
+    Ok(compressed_packet)
+}
+
+pub fn compress(compression: u8, data: &[u8]) -> Result<Vec<u8>, ReplicationError> {
+    match compression {
+        mysql::MYSQL_COMPRESS_ZLIB => {
+            let mut w =
+                flate2::write::ZlibEncoder::new(Vec::<u8>::new(), flate2::Compression::default());
+            w.write_all(data)?;
+            Ok(w.finish()?)
+        }
+        mysql::MYSQL_COMPRESS_ZSTD => Ok(zstd::stream::encode_all(data, 0)?),
+        _ => Err(ReplicationError::new(format!(
+            "can't found compression type. compression value: {}",
+            compression
+        ))),
+    }
+}
+
+// Decompress returns the payload of a compressed packet uncompressed,
+// an uncompressed length of 0 means the payload is not compressed.
+pub fn decompress(
+    compression: u8,
+    payload: Vec<u8>,
+    uncompressed_length: usize,
+) -> io::Result<Vec<u8>> {
+    if uncompressed_length == 0 {
+        return Ok(payload);
+    }
+
+    let data = match compression {
+        mysql::MYSQL_COMPRESS_ZLIB => {
+            let mut data = Vec::<u8>::with_capacity(uncompressed_length);
+            flate2::read::ZlibDecoder::new(payload.as_slice()).read_to_end(&mut data)?;
+            data
+        }
+        mysql::MYSQL_COMPRESS_ZSTD => zstd::stream::decode_all(payload.as_slice())?,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid compressed type {}", compression),
+            ))
+        }
+    };
+
+    if data.len() != uncompressed_length {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "invalid compressed packet, {} bytes uncompressed, while {} expected",
+                data.len(),
+                uncompressed_length
+            ),
+        ));
+    }
+
+    Ok(data)
+}
+
+// BufferedReader reads the transport through the read buffer of a Conn, reads as big as the buffer bypass it.
+// Without a buffer it reads the transport directly. The transport reads fail with TimedOut after the deadline.
+// A read waits no longer than the timeout of the socket, it's only changed by SetReadTimeout, so the shorter
+// timeout a clone sets to make this reader give up is not overwritten.
+struct BufferedReader<'a, S> {
+    _conn: &'a mut S,
+    _buf: Vec<u8>,
+    _pos: usize,
+    _end: usize,
+    _deadline: Option<Instant>,
+}
+
+impl<'a, S: Transport> BufferedReader<'a, S> {
+    fn _read_transport(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self
+            ._deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(io::Error::from(io::ErrorKind::TimedOut));
+        }
+
+        match self._conn.read(buf) {
+            // a socket read timing out fails with EAGAIN on unix
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                Err(io::Error::from(io::ErrorKind::TimedOut))
+            }
+            rs => rs,
+        }
+    }
+}
+
+impl<'a, S: Transport> Read for BufferedReader<'a, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self._pos == self._end {
+            if buf.len() >= self._buf.len() {
+                return self._read_transport(buf);
+            }
+
+            let mut rbuf = std::mem::take(&mut self._buf);
+            let rs = self._read_transport(&mut rbuf);
+            self._buf = rbuf;
+            self._end = rs?;
+            self._pos = 0;
+        }
+
+        let n = buf.len().min(self._end - self._pos);
+        buf[..n].copy_from_slice(&self._buf[self._pos..self._pos + n]);
+        self._pos += n;
+        Ok(n)
+    }
+}
+
+// CompressedReader reads the packets out of the compressed packets of the transport.
+struct CompressedReader<'a, S> {
+    _conn: &'a mut S,
+    _compression: u8,
+    _sequence: u8,
+    _buf: Vec<u8>,
+    _pos: usize,
+}
+
+impl<'a, S: Read> CompressedReader<'a, S> {
+    fn _read_compressed_packet(&mut self) -> io::Result<()> {
+        let mut header = [0_u8; 7];
+        self._conn.read_exact(&mut header)?;
+
+        let compressed_length = LittleEndian::read_u24(&header) as usize;
+        let uncompressed_length = LittleEndian::read_u24(&header[4..]) as usize;
+        // the sequence is not checked like the MySQL clients do,
+        // the server may send an error packet before it has read all the packets of a command
+        self._sequence = header[3].wrapping_add(1);
+
+        let mut payload = vec![0_u8; compressed_length];
+        self._conn.read_exact(&mut payload)?;
+
+        let payload = decompress(self._compression, payload, uncompressed_length)?;
+
+        self._buf = payload;
+        self._pos = 0;
+        Ok(())
+    }
+}
+
+impl<'a, S: Read> Read for CompressedReader<'a, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        while self._pos >= self._buf.len() {
+            self._read_compressed_packet()?;
+        }
+
+        let n = buf.len().min(self._buf.len() - self._pos);
+        buf[..n].copy_from_slice(&self._buf[self._pos..self._pos + n]);
+        self._pos += n;
+        Ok(n)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::error::ReplicationError;
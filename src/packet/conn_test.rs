@@ -0,0 +1,354 @@
+#[cfg(test)]
+mod tests {
+    use crate::error::ReplicationError;
+    use crate::mysql;
+    use crate::packet::{BufPool, Conn, Stream};
+    use byteorder::{ByteOrder, LittleEndian};
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    fn packet(payload: &[u8]) -> Vec<u8> {
+        let mut data = vec![0_u8; 4];
+        data.extend(payload);
+        data
+    }
+
+    fn new_pair(compression: u8) -> (Conn<UnixStream>, Conn<UnixStream>) {
+        let (a, b) = UnixStream::pair().unwrap();
+        let (mut a, mut b) = (Conn::new(a), Conn::new(b));
+        a.compression = compression;
+        b.compression = compression;
+        (a, b)
+    }
+
+    fn check_write_read_compressed(compression: u8) -> Result<(), ReplicationError> {
+        let (mut client, mut server) = new_pair(compression);
+
+        // the short packet is sent uncompressed, the long one compressed
+        let short = b"\x03SELECT 1".to_vec();
+        let long = [b"\x03SELECT ".to_vec(), vec![b'1'; 1000]].concat();
+        client.write_packet(&mut packet(&short))?;
+        client.write_packet(&mut packet(&long))?;
+        assert_eq!(client.compressed_sequence, 2);
+
+        assert_eq!(server.read_packet()?, short);
+        assert_eq!(server.read_packet()?, long);
+        assert_eq!(server.compressed_sequence, 2);
+        assert_eq!(server.sequence, 2);
+
+        // a command starts a new sequence
+        client.reset_sequence();
+        assert_eq!(client.compressed_sequence, 0);
+        client.write_packet(&mut packet(&long))?;
+        server.reset_sequence();
+        assert_eq!(server.read_packet()?, long);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_read_zlib() -> Result<(), ReplicationError> {
+        check_write_read_compressed(mysql::MYSQL_COMPRESS_ZLIB)
+    }
+
+    #[test]
+    fn test_write_read_zstd() -> Result<(), ReplicationError> {
+        check_write_read_compressed(mysql::MYSQL_COMPRESS_ZSTD)
+    }
+
+    #[test]
+    fn test_write_read_big_compressed_packet() -> Result<(), ReplicationError> {
+        let (mut client, mut server) = new_pair(mysql::MYSQL_COMPRESS_ZSTD);
+
+        // the packet is split into packets and the packets into compressed packets of at most MAX_PAYLOAD_LEN bytes
+        let payload: Vec<u8> = (0..mysql::MAX_PAYLOAD_LEN + 100)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let expected = payload.clone();
+        let writer = thread::spawn(move || {
+            client.write_packet(&mut packet(&payload)).unwrap();
+            client
+        });
+
+        assert_eq!(server.read_packet()?, expected);
+        let client = writer.join().unwrap();
+        assert_eq!(client.sequence, 2);
+        // the first packet with its header doesn't fit in one compressed packet
+        assert_eq!(client.compressed_sequence, 3);
+        assert_eq!(server.compressed_sequence, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_packets_of_one_compressed_packet() -> Result<(), ReplicationError> {
+        let (mut raw, server) = UnixStream::pair().unwrap();
+        let mut server = Conn::new(server);
+        server.compression = mysql::MYSQL_COMPRESS_ZLIB;
+
+        // two packets in one zlib compressed packet, then one in an uncompressed one
+        let mut packets = vec![];
+        for (sequence, payload) in [(1_u8, vec![b'a'; 100]), (2, vec![b'b'; 10])] {
+            let mut data = vec![0_u8; 4];
+            LittleEndian::write_u24(&mut data, payload.len() as u32);
+            data[3] = sequence;
+            data.extend(payload);
+            packets.extend(data);
+        }
+        let mut w =
+            flate2::write::ZlibEncoder::new(Vec::<u8>::new(), flate2::Compression::default());
+        w.write_all(&packets).unwrap();
+        let compressed = w.finish().unwrap();
+
+        let mut data = vec![0_u8; 7];
+        LittleEndian::write_u24(&mut data, compressed.len() as u32);
+        data[3] = 1;
+        LittleEndian::write_u24(&mut data[4..], packets.len() as u32);
+        data.extend(compressed);
+        data.extend([5, 0, 0, 2, 0, 0, 0, 1, 0, 0, 3, b'c']);
+        raw.write_all(&data).unwrap();
+
+        server.sequence = 1;
+        assert_eq!(server.read_packet()?, vec![b'a'; 100]);
+        assert_eq!(server.read_packet()?, vec![b'b'; 10]);
+        assert_eq!(server.read_packet()?, b"c");
+        assert_eq!(server.compressed_sequence, 3);
+
+        // the connection is closed in the middle of a compressed packet
+        raw.write_all(&[5, 0, 0, 3, 0, 0, 0, 1]).unwrap();
+        drop(raw);
+        assert!(server.read_packet().is_err());
+
+        Ok(())
+    }
+
+    fn raw_packet(sequence: u8, payload: &[u8]) -> Vec<u8> {
+        let mut data = vec![0_u8; 4];
+        LittleEndian::write_u24(&mut data, payload.len() as u32);
+        data[3] = sequence;
+        data.extend(payload);
+        data
+    }
+
+    #[test]
+    fn test_read_packet_into_buffered() -> Result<(), ReplicationError> {
+        let (mut raw, server) = UnixStream::pair().unwrap();
+        let mut server = Conn::new(server);
+        server.use_buffered_reader();
+
+        // all the packets arrive in one read of the buffered reader
+        let mut data = raw_packet(0, &[b'a'; 1000]);
+        data.extend(raw_packet(1, b"bb"));
+        data.extend(raw_packet(2, b""));
+        raw.write_all(&data).unwrap();
+
+        let pool = BufPool::new();
+        let mut buf = pool.get();
+        server.read_packet_into(&mut buf)?;
+        assert_eq!(buf, vec![b'a'; 1000]);
+
+        // the buffer is cleared and its memory reused
+        let ptr = buf.as_ptr();
+        server.read_packet_into(&mut buf)?;
+        assert_eq!(buf, b"bb");
+        assert_eq!(buf.as_ptr(), ptr);
+
+        server.read_packet_into(&mut buf)?;
+        assert!(buf.is_empty());
+        assert_eq!(server.sequence, 3);
+
+        pool.put(buf);
+        assert_eq!(pool.get().capacity(), 1000);
+
+        // a packet cut by the end of the connection
+        raw.write_all(&raw_packet(3, b"cccc")[..6]).unwrap();
+        drop(raw);
+        assert!(server.read_packet().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_big_packet_into() -> Result<(), ReplicationError> {
+        let (mut client, server) = new_pair(mysql::MYSQL_COMPRESS_NONE);
+        let mut server = server;
+        server.use_buffered_reader();
+
+        // the payload of MAX_PAYLOAD_LEN bytes is continued by an empty packet
+        let payloads = [
+            vec![7_u8; mysql::MAX_PAYLOAD_LEN],
+            (0..mysql::MAX_PAYLOAD_LEN + 100)
+                .map(|i| (i % 251) as u8)
+                .collect::<Vec<u8>>(),
+            b"small".to_vec(),
+        ];
+        let expected = payloads.clone();
+        let writer = thread::spawn(move || {
+            for payload in payloads {
+                client.write_packet(&mut packet(&payload)).unwrap();
+            }
+        });
+
+        let mut buf = vec![];
+        for payload in expected {
+            server.read_packet_into(&mut buf)?;
+            assert_eq!(buf, payload);
+        }
+        writer.join().unwrap();
+        assert_eq!(server.sequence, 5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_compressed_buffered() -> Result<(), ReplicationError> {
+        let (mut client, mut server) = new_pair(mysql::MYSQL_COMPRESS_ZLIB);
+        server.use_buffered_reader();
+
+        let long = vec![b'x'; 10000];
+        client.write_packet(&mut packet(b"\x03SELECT 1"))?;
+        client.write_packet(&mut packet(&long))?;
+
+        let mut buf = vec![];
+        server.read_packet_into(&mut buf)?;
+        assert_eq!(buf, b"\x03SELECT 1");
+        server.read_packet_into(&mut buf)?;
+        assert_eq!(buf, long);
+        assert_eq!(server.compressed_sequence, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_packet_reuse_mem() -> Result<(), ReplicationError> {
+        let (mut client, mut server) = new_pair(mysql::MYSQL_COMPRESS_NONE);
+        client.write_packet(&mut packet(b"payload"))?;
+
+        assert_eq!(
+            server.read_packet_reuse_mem(&mut b"prefix-".to_vec())?,
+            b"prefix-payload"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_upgrade_tls_with_buffered_data() -> Result<(), ReplicationError> {
+        let (mut raw, server) = UnixStream::pair().unwrap();
+        let mut server = Conn::new(Stream::Unix(server));
+        server.use_buffered_reader();
+
+        let mut data = raw_packet(0, b"a");
+        data.extend(raw_packet(1, b"b"));
+        raw.write_all(&data).unwrap();
+        assert_eq!(server.read_packet()?, b"a");
+
+        // the second packet is in the read buffer, a TLS session can't start from the socket
+        let config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(rustls::RootCertStore::empty())
+            .with_no_client_auth();
+        let rs = server.upgrade_tls(Arc::new(config), "localhost");
+        assert!(rs
+            .unwrap_err()
+            .to_string()
+            .contains("can't upgrade to TLS with buffered data"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_timeout() -> Result<(), ReplicationError> {
+        let (mut raw, server) = UnixStream::pair().unwrap();
+        let mut server = Conn::new(server);
+        server.use_buffered_reader();
+        server.set_read_timeout(Some(Duration::from_millis(100)))?;
+
+        let start = Instant::now();
+        let err = server.read_packet().unwrap_err();
+        assert!(err.is_timeout(), "{}", err);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        // the deadline is extended for every packet, a quiet server doesn't time out
+        let writer = thread::spawn(move || {
+            for sequence in 0..3 {
+                thread::sleep(Duration::from_millis(60));
+                raw.write_all(&raw_packet(sequence, b"ping")).unwrap();
+            }
+            raw
+        });
+        server.reset_sequence();
+        for _ in 0..3 {
+            assert_eq!(server.read_packet()?, b"ping");
+        }
+        let mut raw = writer.join().unwrap();
+
+        // but a packet trickling in slower than the timeout does
+        let writer = thread::spawn(move || {
+            for b in raw_packet(3, b"slow") {
+                thread::sleep(Duration::from_millis(30));
+                if raw.write_all(&[b]).is_err() {
+                    break;
+                }
+            }
+        });
+        assert!(server.read_packet().unwrap_err().is_timeout());
+        writer.join().unwrap();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_timeout_set_by_clone() -> Result<(), ReplicationError> {
+        let (_raw, server) = UnixStream::pair().unwrap();
+        let mut server = Conn::new(server);
+        server.use_buffered_reader();
+        server.set_read_timeout(Some(Duration::from_secs(10)))?;
+
+        // the socket is shared, a clone shortens the timeout of the reads of the others
+        let mut clone = server.try_clone()?;
+        clone.set_read_timeout(Some(Duration::from_millis(100)))?;
+
+        let start = Instant::now();
+        assert!(server.read_packet().unwrap_err().is_timeout());
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_without_timeout() -> Result<(), ReplicationError> {
+        let (mut raw, server) = UnixStream::pair().unwrap();
+        let mut server = Conn::new(server);
+        server.set_read_timeout(Some(Duration::from_millis(50)))?;
+        // a zero timeout disables it like None
+        server.set_read_timeout(Some(Duration::ZERO))?;
+
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(150));
+            raw.write_all(&raw_packet(0, b"late")).unwrap();
+        });
+        assert_eq!(server.read_packet()?, b"late");
+        writer.join().unwrap();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_recv_buffer_size() -> Result<(), ReplicationError> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let sock = client.try_clone().unwrap();
+        let c = Conn::new(Stream::Tcp(client));
+
+        let size = 256 * 1024;
+        c.set_recv_buffer_size(size)?;
+        // the kernel may round the size up
+        assert!(socket2::SockRef::from(&sock).recv_buffer_size().unwrap() >= size);
+
+        Ok(())
+    }
+}
@@ -1,3 +1,14 @@
+pub mod async_conn;
+mod async_conn_test;
+pub mod async_stream;
+pub mod buf_pool;
+mod buf_pool_test;
 pub mod conn;
+mod conn_test;
+pub mod stream;
 
+pub use async_conn::*;
+pub use async_stream::*;
+pub use buf_pool::*;
 pub use conn::*;
+pub use stream::*;
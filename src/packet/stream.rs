@@ -0,0 +1,325 @@
+use crate::error::ReplicationError;
+use std::io;
+use std::io::{Read, Write};
+use std::net;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+// the size of the socket reads of a TLS stream, a TLS record is at most 16KB plus the overhead
+const _TLS_READ_BUF_SIZE: usize = 18 * 1024;
+
+// Transport is the byte stream the packets of a Conn are read from and written to.
+pub trait Transport: Read + Write {
+    // TryClone returns a handle of the same stream, so one thread can write while another one is reading.
+    fn try_clone(&self) -> io::Result<Self>
+    where
+        Self: Sized;
+
+    // Shutdown closes both directions of the stream, reads blocked on any handle of it return.
+    fn shutdown(&self) -> io::Result<()>;
+
+    // SetReadTimeout bounds every read of the stream, a read timing out fails with WouldBlock or TimedOut.
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    // SetRecvBufferSize sets SO_RCVBUF, the size of the receive buffer of the operating system.
+    fn set_recv_buffer_size(&self, size: usize) -> io::Result<()>;
+}
+
+impl Transport for net::TcpStream {
+    fn try_clone(&self) -> io::Result<net::TcpStream> {
+        net::TcpStream::try_clone(self)
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        net::TcpStream::shutdown(self, net::Shutdown::Both)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        net::TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        net::TcpStream::set_write_timeout(self, timeout)
+    }
+
+    fn set_recv_buffer_size(&self, size: usize) -> io::Result<()> {
+        socket2::SockRef::from(self).set_recv_buffer_size(size)
+    }
+}
+
+#[cfg(unix)]
+impl Transport for UnixStream {
+    fn try_clone(&self) -> io::Result<UnixStream> {
+        UnixStream::try_clone(self)
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        UnixStream::shutdown(self, net::Shutdown::Both)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_write_timeout(self, timeout)
+    }
+
+    fn set_recv_buffer_size(&self, size: usize) -> io::Result<()> {
+        socket2::SockRef::from(self).set_recv_buffer_size(size)
+    }
+}
+
+// Stream is the transport of a MySQL connection, a TCP socket, a unix domain socket or the TLS session
+// upgraded from a TCP socket after the SSLRequest packet.
+#[derive(Debug)]
+pub enum Stream {
+    Tcp(net::TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+    Tls(TlsStream),
+}
+
+impl Stream {
+    // UpgradeTls runs the TLS handshake over the TCP socket, server_name is the name the certificate is verified against.
+    pub fn upgrade_tls(
+        self,
+        config: Arc<rustls::ClientConfig>,
+        server_name: &str,
+    ) -> Result<Stream, ReplicationError> {
+        match self {
+            Stream::Tcp(sock) => Ok(Stream::Tls(TlsStream::connect(sock, config, server_name)?)),
+            #[cfg(unix)]
+            Stream::Unix(_) => Err(ReplicationError::new(
+                "TLS is not supported over a unix socket".to_string(),
+            )),
+            Stream::Tls(_) => Err(ReplicationError::new(
+                "the connection is already using TLS".to_string(),
+            )),
+        }
+    }
+
+    pub fn is_tls(&self) -> bool {
+        matches!(self, Stream::Tls(_))
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.read(buf),
+            Stream::Tls(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.write(buf),
+            Stream::Tls(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.flush(),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.flush(),
+            Stream::Tls(s) => s.flush(),
+        }
+    }
+}
+
+impl Transport for Stream {
+    fn try_clone(&self) -> io::Result<Stream> {
+        match self {
+            Stream::Tcp(s) => Ok(Stream::Tcp(Transport::try_clone(s)?)),
+            #[cfg(unix)]
+            Stream::Unix(s) => Ok(Stream::Unix(Transport::try_clone(s)?)),
+            Stream::Tls(s) => Ok(Stream::Tls(s.try_clone()?)),
+        }
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => Transport::shutdown(s),
+            #[cfg(unix)]
+            Stream::Unix(s) => Transport::shutdown(s),
+            Stream::Tls(s) => s.shutdown(),
+        }
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => Transport::set_read_timeout(s, timeout),
+            #[cfg(unix)]
+            Stream::Unix(s) => Transport::set_read_timeout(s, timeout),
+            Stream::Tls(s) => s.get_ref().set_read_timeout(timeout),
+        }
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => Transport::set_write_timeout(s, timeout),
+            #[cfg(unix)]
+            Stream::Unix(s) => Transport::set_write_timeout(s, timeout),
+            Stream::Tls(s) => s.get_ref().set_write_timeout(timeout),
+        }
+    }
+
+    fn set_recv_buffer_size(&self, size: usize) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => Transport::set_recv_buffer_size(s, size),
+            #[cfg(unix)]
+            Stream::Unix(s) => Transport::set_recv_buffer_size(s, size),
+            Stream::Tls(s) => Transport::set_recv_buffer_size(s.get_ref(), size),
+        }
+    }
+}
+
+// TlsStream is a client TLS session over a TCP socket.
+// Unlike rustls::StreamOwned its clones share the session, the socket is read without holding the session lock,
+// so one clone can write (e.g. semi-sync ACKs) while another one is blocked reading. Only one clone should read.
+#[derive(Debug)]
+pub struct TlsStream {
+    _session: Arc<Mutex<rustls::ClientConnection>>,
+    _sock: net::TcpStream,
+    // TLS records read from the socket but not handed to the session yet
+    _pending: Vec<u8>,
+}
+
+impl TlsStream {
+    pub fn connect(
+        mut sock: net::TcpStream,
+        config: Arc<rustls::ClientConfig>,
+        server_name: &str,
+    ) -> Result<TlsStream, ReplicationError> {
+        let name = rustls::ServerName::try_from(server_name).map_err(|e| {
+            ReplicationError::new(format!("invalid TLS server name {}: {}", server_name, e))
+        })?;
+        let mut session = rustls::ClientConnection::new(config, name)
+            .map_err(|e| ReplicationError::new(format!("new TLS session failed: {}", e)))?;
+
+        while session.is_handshaking() {
+            session
+                .complete_io(&mut sock)
+                .map_err(|e| ReplicationError::new(format!("TLS handshake failed: {}", e)))?;
+        }
+
+        Ok(TlsStream {
+            _session: Arc::new(Mutex::new(session)),
+            _sock: sock,
+            _pending: vec![],
+        })
+    }
+
+    pub fn try_clone(&self) -> io::Result<TlsStream> {
+        Ok(TlsStream {
+            _session: self._session.clone(),
+            _sock: self._sock.try_clone()?,
+            _pending: vec![],
+        })
+    }
+
+    // GetRef returns the TCP socket under the session, for the socket options.
+    pub fn get_ref(&self) -> &net::TcpStream {
+        &self._sock
+    }
+
+    // Shutdown sends the close_notify alert and shuts the socket down.
+    pub fn shutdown(&self) -> io::Result<()> {
+        if let Ok(mut session) = self._session.lock() {
+            session.send_close_notify();
+            let _ = Self::_write_tls(&self._sock, &mut session);
+        }
+
+        self._sock.shutdown(net::Shutdown::Both)
+    }
+
+    fn _lock(
+        session: &Mutex<rustls::ClientConnection>,
+    ) -> io::Result<MutexGuard<'_, rustls::ClientConnection>> {
+        session
+            .lock()
+            .map_err(|_| io::Error::other("TLS session lock poisoned"))
+    }
+
+    // the socket writes of all clones are serialized by the session lock
+    fn _write_tls(
+        mut sock: &net::TcpStream,
+        session: &mut rustls::ClientConnection,
+    ) -> io::Result<()> {
+        while session.wants_write() {
+            session.write_tls(&mut sock)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let mut session = Self::_lock(&self._session)?;
+            match session.reader().read(buf) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                rs => return rs,
+            }
+
+            if self._pending.is_empty() {
+                drop(session);
+
+                self._pending.resize(_TLS_READ_BUF_SIZE, 0);
+                let n = match (&self._sock).read(&mut self._pending) {
+                    Ok(n) => n,
+                    Err(e) => {
+                        self._pending.clear();
+                        return Err(e);
+                    }
+                };
+                self._pending.truncate(n);
+                if n > 0 {
+                    continue;
+                }
+
+                // the peer closed the socket, an empty read_tls tells the session
+                session = Self::_lock(&self._session)?;
+                let _ = session.read_tls(&mut io::empty())?;
+            } else {
+                // records are handed over only when there is no plaintext left, so the plaintext buffer never fills up
+                let n = session.read_tls(&mut self._pending.as_slice())?;
+                self._pending.drain(..n);
+            }
+
+            session
+                .process_new_packets()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            Self::_write_tls(&self._sock, &mut session)?;
+        }
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut session = Self::_lock(&self._session)?;
+        let n = session.writer().write(buf)?;
+        Self::_write_tls(&self._sock, &mut session)?;
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut session = Self::_lock(&self._session)?;
+        session.writer().flush()?;
+        Self::_write_tls(&self._sock, &mut session)
+    }
+}
@@ -1,5 +1,7 @@
+use crate::error::EventError;
 use crate::error::ReplicationError;
-use crate::replication::{Event, EventEnum, EventHeader};
+use crate::mysql::Position;
+use crate::replication::{Checkpoint, Event, EventEnum, EventHeader, EventRef};
 use std::io::Write;
 
 #[derive(Debug, Clone, Default)]
@@ -9,6 +11,18 @@ pub struct BinlogEvent {
 
     pub header: Option<EventHeader>,
     pub event: Option<EventEnum>,
+
+    // the master waits for a semi-sync ACK of this event, pass it to BinlogSyncer::reply_semi_sync_ack
+    // after the event has been durably handled
+    pub semi_sync_ack_pos: Option<Position>,
+
+    // the checkpoint right after the transaction ended by this event, pass it to BinlogSyncer::save_checkpoint
+    // after the transaction has been durably handled
+    pub checkpoint: Option<Checkpoint>,
+
+    // the event is filtered out by the replication filter of the parser, the rows of a filtered rows event
+    // are not decoded. BinlogSyncer only sends a filtered event if it waits for a semi-sync ACK or carries a checkpoint.
+    pub filtered: bool,
 }
 
 impl BinlogEvent {
@@ -19,3 +33,43 @@ impl BinlogEvent {
         Ok(())
     }
 }
+
+// BinlogEventRef is a BinlogEvent borrowing the buffer it was parsed from, see BinlogParser::parse_ref.
+// A consumer can look at the header, the schema or the table of the event and drop it without allocating.
+#[derive(Debug, Clone)]
+pub struct BinlogEventRef<'a> {
+    // raw binlog data which contains all data, including binlog header and event body, and including crc32 checksum if exists
+    pub raw_data: &'a [u8],
+
+    pub header: EventHeader,
+    pub event: EventRef<'a>,
+
+    // the event is filtered out by the replication filter of the parser
+    pub filtered: bool,
+}
+
+impl<'a> BinlogEventRef<'a> {
+    // ToOwned returns the BinlogEvent BinlogParser::parse would return for the same data,
+    // except the rows of a rows event are always decoded, see RowsEventRef::to_owned.
+    pub fn to_owned(&self) -> Result<BinlogEvent, ReplicationError> {
+        let event = match &self.event {
+            EventRef::RowsEvent(e) => EventEnum::RowsEvent(e.to_owned().map_err(|err| {
+                ReplicationError::EventError(EventError {
+                    header: self.header.clone(),
+                    err: err.to_string(),
+                    data: e.data.to_vec(),
+                })
+            })?),
+            e => e.to_owned()?,
+        };
+
+        Ok(BinlogEvent {
+            raw_data: self.raw_data.to_vec(),
+            header: Some(self.header.clone()),
+            event: Some(event),
+            semi_sync_ack_pos: None,
+            checkpoint: None,
+            filtered: self.filtered,
+        })
+    }
+}
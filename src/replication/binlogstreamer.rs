@@ -1,6 +1,10 @@
 use crate::error::ReplicationError;
 use crate::replication::BinlogEvent;
 use chrono::NaiveDateTime;
+use futures_core::stream::FusedStream;
+use futures_core::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
 use tokio::select;
 use tokio_context::context;
 
@@ -97,19 +101,22 @@ impl BinlogStreamer {
         }
     }
 
+    // the event channel fails once it's closed by Close and empty, the streamer holds one of its senders
     fn _receive_binlog_event(
         &mut self,
         binlog_event_rs: Result<BinlogEvent, async_channel::RecvError>,
     ) -> Result<BinlogEvent, ReplicationError> {
         match binlog_event_rs {
             Ok(v) => Ok(v),
-            Err(e) => {
-                self.err = Err(ReplicationError::new(e.to_string()));
-                Err(ReplicationError::new(e.to_string()))
-            }
+            Err(_) => Err(self._sync_closed()),
         }
     }
 
+    fn _sync_closed(&mut self) -> ReplicationError {
+        self.err = Err(ReplicationError::new(ERR_SYNC_CLOSED.to_string()));
+        ReplicationError::new(ERR_SYNC_CLOSED.to_string())
+    }
+
     fn _receive_error(
         &mut self,
         error_rs: Result<ReplicationError, async_channel::RecvError>,
@@ -122,6 +129,12 @@ impl BinlogStreamer {
         e
     }
 
+    // the channels are closed, like after an error the streamer must be synced again
+    fn _end_stream(&mut self) -> Poll<Option<Result<BinlogEvent, ReplicationError>>> {
+        self.err = Err(ReplicationError::new(ERR_NEED_SYNC_AGIN.to_string()));
+        Poll::Ready(None)
+    }
+
     pub fn get_binlog_event_tx(&self) -> async_channel::Sender<BinlogEvent> {
         self.binlog_event_sender.clone()
     }
@@ -142,28 +155,24 @@ impl BinlogStreamer {
         Ok(events)
     }
 
-    async fn _close(&mut self) -> Result<(), ReplicationError> {
-        let _ = self._close_with_error(Ok(())).await?;
-        Ok(())
+    // Close tells the consumers the sync was closed, GetEvent returns ERR_SYNC_CLOSED after the events left.
+    // It's called by BinlogSyncer::close, it never blocks. The event channel is closed for all the clones,
+    // so unlike an error sent to the bounded error channel it can't be lost, and it wakes the waiting consumers.
+    pub fn close(&self) {
+        self.binlog_event_sender.close();
     }
 
-    async fn _close_with_error(
-        &mut self,
-        err: Result<(), ReplicationError>,
-    ) -> Result<(), ReplicationError> {
+    // CloseWithError is called by the dump task of BinlogSyncer, it never blocks.
+    pub fn close_with_error(&self, err: Result<(), ReplicationError>) {
         let new_err = match err {
             Ok(_) => ReplicationError::new(ERR_NEED_SYNC_AGIN.to_string()),
             Err(e) => {
-                log::error!("{}", e.to_string());
+                log::error!("close sync with err: {}", e);
                 e
             }
         };
 
-        if let Err(e) = self.err_sender.send(new_err).await {
-            return Err(ReplicationError::new(e.to_string()));
-        }
-
-        Ok(())
+        let _ = self.err_sender.try_send(new_err);
     }
 
     // AddEventToStreamer adds a binlog event to the streamer. You can use it when you want to add an event to the streamer manually.
@@ -197,3 +206,37 @@ impl BinlogStreamer {
         }
     }
 }
+
+// BinlogStreamer is also a stream of the events, it yields the sync error like GetEvent does and ends after it.
+// GetEvent keeps returning ERR_NEED_SYNC_AGIN instead until the sync is started again.
+impl Stream for BinlogStreamer {
+    type Item = Result<BinlogEvent, ReplicationError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.err.is_err() {
+            return Poll::Ready(None);
+        }
+
+        // the events sent before the error come first
+        match Pin::new(&mut self.binlog_event_recv).poll_next(cx) {
+            Poll::Ready(Some(be)) => return Poll::Ready(Some(Ok(be))),
+            Poll::Ready(None) => return Poll::Ready(Some(Err(self._sync_closed()))),
+            Poll::Pending => {}
+        }
+
+        match Pin::new(&mut self.err_recv).poll_next(cx) {
+            Poll::Ready(Some(e)) => {
+                self.err = Err(ReplicationError::new(e.to_string()));
+                Poll::Ready(Some(Err(e)))
+            }
+            Poll::Ready(None) => self._end_stream(),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl FusedStream for BinlogStreamer {
+    fn is_terminated(&self) -> bool {
+        self.err.is_err()
+    }
+}
@@ -0,0 +1,90 @@
+#[cfg(test)]
+mod tests {
+    use crate::error::ReplicationError;
+    use crate::replication::{BinlogEvent, BinlogStreamer, ERR_NEED_SYNC_AGIN, ERR_SYNC_CLOSED};
+    use futures_core::stream::FusedStream;
+    use futures_core::Stream;
+    use std::pin::Pin;
+    use std::time::Duration;
+
+    async fn next(s: &mut BinlogStreamer) -> Option<Result<BinlogEvent, ReplicationError>> {
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            std::future::poll_fn(|cx| Pin::new(&mut *s).poll_next(cx)),
+        )
+        .await
+        .expect("timed out waiting for the stream")
+    }
+
+    fn event(raw_data: &[u8]) -> BinlogEvent {
+        BinlogEvent {
+            raw_data: raw_data.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_events_then_error() -> Result<(), ReplicationError> {
+        let mut s = BinlogStreamer::new();
+        let tx = s.get_binlog_event_tx();
+
+        // the event arrives while the stream is waiting
+        let sender = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            tx.send(event(b"1")).await.unwrap();
+        });
+        assert_eq!(next(&mut s).await.unwrap()?.raw_data, b"1");
+        sender.await.unwrap();
+
+        s.add_event_to_streamer(event(b"2")).await?;
+        s.close_with_error(Err(ReplicationError::new("io error".to_string())));
+
+        assert_eq!(next(&mut s).await.unwrap()?.raw_data, b"2");
+        let err = next(&mut s).await.unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "io error");
+
+        // the stream ends after the error, GetEvent tells the streamer must be synced again
+        assert!(s.is_terminated());
+        assert!(next(&mut s).await.is_none());
+        assert!(next(&mut s).await.is_none());
+        let (ctx, _handle) = tokio_context::context::Context::new();
+        let err = s.get_event(ctx).await.unwrap_err();
+        assert_eq!(err.to_string(), ERR_NEED_SYNC_AGIN);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stream_closed_sync() {
+        let mut s = BinlogStreamer::new();
+        s.close_with_error(Ok(()));
+
+        assert!(!s.is_terminated());
+        let err = next(&mut s).await.unwrap().unwrap_err();
+        assert_eq!(err.to_string(), ERR_NEED_SYNC_AGIN);
+        assert!(next(&mut s).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_close() -> Result<(), ReplicationError> {
+        let mut s = BinlogStreamer::new();
+        let mut other = s.clone_with_no_error();
+        s.add_event_to_streamer(event(b"1")).await?;
+        s.close();
+
+        // the events left come first
+        let (ctx, _handle) = tokio_context::context::Context::new();
+        assert_eq!(other.get_event(ctx).await?.unwrap().raw_data, b"1");
+        let (ctx, _handle) = tokio_context::context::Context::new();
+        let err = other.get_event(ctx).await.unwrap_err();
+        assert_eq!(err.to_string(), ERR_SYNC_CLOSED);
+
+        // every clone sees the close
+        let err = next(&mut s).await.unwrap().unwrap_err();
+        assert_eq!(err.to_string(), ERR_SYNC_CLOSED);
+        assert!(s.is_terminated());
+        assert!(next(&mut s).await.is_none());
+
+        Ok(())
+    }
+}
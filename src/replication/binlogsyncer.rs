@@ -1,13 +1,28 @@
-use crate::error::ReplicationError;
+use crate::client;
+use crate::client::AsyncConn;
+use crate::error::{MyError, MysqlError, ReplicationError};
 use crate::loggerop;
-use crate::mysql::{GtidSetEnum, Position};
+use crate::mysql;
+use crate::mysql::{GTIDSet, GtidSetEnum, Position};
+use crate::packet;
 use crate::replication::parser::BinlogParser;
-use crate::replication::{common, GTIDEvent};
+use crate::replication::{
+    common, BinlogEvent, BinlogStreamer, Checkpoint, CheckpointStore, EventEnum, GTIDEvent,
+    HeartbeatEvent, HeartbeatEventV2, MariadbGTIDEvent, QueryEvent, ReplicationFilter,
+    RewriteRules, XIDEvent, BINLOG_DUMP_NON_BLOCK, ERR_SYNC_CLOSED, SEMI_SYNC_INDICATOR,
+};
+use byteorder::{ByteOrder, LittleEndian};
+use chrono::NaiveDateTime;
+use futures_core::future::BoxFuture;
+use std::collections::HashMap;
 use std::fmt::Formatter;
-use std::net;
-use tokio::sync;
+use std::sync::{Arc, Mutex};
+use tokio_context::context::Handle;
 
 const _ERR_SYNC_RUNNING: &str = "Sync is running, must Close first";
+// retry sync waits 1s, 2s, 4s ... between reconnect attempts, never longer than 30s.
+const _RETRY_SYNC_BASE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+const _RETRY_SYNC_MAX_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
 
 impl std::fmt::Debug for BinlogSyncerConfig {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -23,9 +38,11 @@ impl std::fmt::Debug for BinlogSyncerConfig {
             .field("semi_sync_enabled", &self.semi_sync_enabled)
             .field("raw_mode_enabled", &self.raw_mode_enabled)
             .field("tls_config", &self.tls_config)
+            .field("compression", &self.compression)
             .field("parse_time", &self.parse_time)
             .field("timestamp_string_location", &self.timestamp_string_location)
             .field("use_decimal", &self.use_decimal)
+            .field("lazy_rows_decoding", &self.lazy_rows_decoding)
             .field("heartbeat_period", &self.heartbeat_period)
             .field("read_timeout", &self.read_timeout)
             .field("max_reconnect_attempts", &self.max_reconnect_attempts)
@@ -34,18 +51,30 @@ impl std::fmt::Debug for BinlogSyncerConfig {
             .field("dump_command_flag", &self.dump_command_flag)
             .field(
                 "option",
-                &"Box<dyn Fn(&net::TcpStream) -> Result<(), ReplicationError>>",
+                &"Option<Box<dyn Fn(&mut client::AsyncConn) -> BoxFuture<Result<(), ReplicationError>>>>",
             )
-            .field("dialer", &self.dialer)
+            .field("dialer", &"Option<Arc<client::Dialer>>")
             .field(
                 "rows_event_decode_func",
                 &"Box<dyn Fn(&mut RowsEvent, &[u8]) -> Result<(), ReplicationError>>",
             )
             .field("discard_gtid_set", &self.discard_gtid_set)
+            .field("checkpoint_store", &"Option<Arc<dyn CheckpointStore>>")
+            .field("filter", &self.filter)
+            .field("rewrite_rules", &self.rewrite_rules)
             .finish()
     }
 }
 
+// ConnOptionFunc is BinlogSyncerConfig.option, it runs on the connection before COM_REGISTER_SLAVE.
+// The connection is async, so it returns a boxed future borrowing it:
+//
+//     option: Some(Box::new(|c| {
+//         Box::pin(async move { c.execute("SET @slave_gtid_ignore_duplicates=1").await.map(|_| ()) })
+//     })),
+pub type ConnOptionFunc =
+    dyn for<'a> Fn(&'a mut AsyncConn) -> BoxFuture<'a, Result<(), ReplicationError>> + Send + Sync;
+
 // BinlogSyncerConfig is the configuration for BinlogSyncer.
 pub struct BinlogSyncerConfig {
     // ServerID is the unique ID in cluster.
@@ -53,7 +82,7 @@ pub struct BinlogSyncerConfig {
     // Flavor is "mysql" or "mariadb", if not set, use "mysql" default.
     pub flavor: String,
 
-    // Host is for MySQL server host.
+    // Host is for MySQL server host, or the path of a unix socket like /var/run/mysqld/mysqld.sock.
     pub host: String,
     // Port is for MySQL server port.
     pub port: u16,
@@ -71,8 +100,12 @@ pub struct BinlogSyncerConfig {
     pub semi_sync_enabled: bool,
     // RawModeEnabled is for not parsing binlog event.
     pub raw_mode_enabled: bool,
-    // If not nil, use the provided tls.Config to connect to the database using TLS/SSL.
-    pub tls_config: rustls::ClientConfig,
+    // If not None, use the provided TLS config to connect to the database using TLS/SSL,
+    // see mysql::new_client_tls_config. The certificate is verified against Host.
+    pub tls_config: Option<rustls::ClientConfig>,
+    // Compression is the compression of the client protocol, mysql::MYSQL_COMPRESS_ZLIB or mysql::MYSQL_COMPRESS_ZSTD,
+    // it's used only if the server supports it. Default mysql::MYSQL_COMPRESS_NONE.
+    pub compression: u8,
 
     // Use replication.Time structure for timestamp and datetime.
     // We will use Local location for timestamp and UTC location for datatime.
@@ -92,6 +125,8 @@ pub struct BinlogSyncerConfig {
     pub timestamp_string_location: Option<chrono_tz::Tz>,
     // Use decimal.Decimal structure for decimals.
     pub use_decimal: bool,
+    // LazyRowsDecoding keeps the rows of the rows events undecoded, they are decoded one at a time by RowsEvent::rows_iter.
+    pub lazy_rows_decoding: bool,
     // RecvBufferSize sets the size in bytes of the operating system's receive buffer associated with the connection.
     pub recv_buffer_size: usize,
     // master heartbeat period
@@ -119,34 +154,74 @@ pub struct BinlogSyncerConfig {
 
     //Option function is used to set outside of BinlogSyncerConfig， between mysql connection and COM_REGISTER_SLAVE
     //For MariaDB: slave_gtid_ignore_duplicates、skip_replication、slave_until_gtid
-    pub option: Box<dyn Fn(&net::TcpStream) -> Result<(), ReplicationError>>,
+    pub option: Option<Box<ConnOptionFunc>>,
 
-    // Set Dialer
-    pub dialer: Option<net::TcpStream>,
+    // Set Dialer, it's blocking and runs on the blocking threads of tokio.
+    pub dialer: Option<Arc<client::Dialer>>,
 
     // Dialer client.Dialer
     pub rows_event_decode_func: Option<common::RowsEventDecodeFunc>,
     pub discard_gtid_set: bool,
+
+    // CheckpointStore keeps the checkpoint StartSyncFromCheckpoint resumes from. If set, the events ending
+    // a transaction carry the checkpoint right after it, see SaveCheckpoint.
+    pub checkpoint_store: Option<Arc<dyn CheckpointStore>>,
+
+    // Filter is the replication filter of the parser, the filtered events are not sent to the streamer
+    // unless they wait for a semi-sync ACK or carry a checkpoint. The position and the GTID set still move past them.
+    pub filter: ReplicationFilter,
+    // RewriteRules renames the databases and tables of the events passing the filter.
+    pub rewrite_rules: RewriteRules,
 }
 
 // BinlogSyncer syncs binlog event from server.
 pub struct BinlogSyncer {
-    _m: sync::RwLock<()>,
-    _cfg: BinlogSyncerConfig,
-    _c: Option<net::TcpStream>,
-    _parser: BinlogParser,
-    _next_pos: Option<Position>,
+    _cfg: Arc<BinlogSyncerConfig>,
+    _s: Arc<Mutex<SyncerState>>,
+    // the dump tasks run in contexts spawned from it, Close cancels them all
+    _handle: Option<Handle>,
+    _wg: Option<tokio::task::JoinHandle<()>>,
+    // a handle of the streamer of the running sync, Close drains it
+    _streamer: Option<BinlogStreamer>,
+    // the packets of the dump stream are read into its buffers, they are kept across the syncs
+    _buf_pool: packet::BufPool,
+}
+
+// SyncerState is shared between BinlogSyncer and the task running the dump stream.
+#[derive(Default)]
+struct SyncerState {
+    _next_pos: Position,
     _prev_gset: Option<GtidSetEnum>,
     _curr_gset: Option<GtidSetEnum>,
     // instead of GTIDSet.Clone, use this to speed up calculate prevGset
     _prev_mysql_gtid_event: Option<GTIDEvent>,
+    // the position right after the last fully committed transaction, retry sync resumes from here
+    _committed_pos: Position,
+    _in_transaction: bool,
+    // a BEGIN query was read, only COMMIT or XID ends the transaction then, not the statements in it
+    _in_begin: bool,
+    // a second handle of the replication connection, semi-sync ACKs are written with it
+    _ack_conn: Option<Arc<tokio::sync::Mutex<packet::AsyncConn>>>,
+    // the stream of it, Close shuts it down
+    _conn: Option<packet::AsyncStream>,
     _running: bool,
-    _ctx: tokio_context::context::Context,
-    _cancel: tokio_context::context::Handle,
+    // set by Close, the syncer never syncs again then
+    _closed: bool,
     _last_connection_id: u32,
     _retry_count: usize,
 }
 
+// BinlogDumper owns the replication connection and the parser, it is moved into the dump task.
+struct BinlogDumper {
+    _cfg: Arc<BinlogSyncerConfig>,
+    _s: Arc<Mutex<SyncerState>>,
+    _c: Option<AsyncConn>,
+    _parser: BinlogParser,
+    // SemiSyncEnabled is turned off if the master does not support semi synchronous replication
+    _semi_sync_enabled: bool,
+    _buf_pool: packet::BufPool,
+}
+
 impl BinlogSyncer {
     // NewBinlogSyncer creates the BinlogSyncer with cfg.
     pub fn new(mut cfg: BinlogSyncerConfig) -> Result<BinlogSyncer, ReplicationError> {
@@ -156,17 +231,7 @@ impl BinlogSyncer {
             log::error!("{}", &err_msg);
             return Err(ReplicationError::new(err_msg));
         }
-
-        // @todo
-        // if cfg.dialer.is_none() {
-        //     cfg.dialer = Some()
-        // }
-        /*
-        if cfg.Dialer == nil {
-            dialer := &net.Dialer{}
-            cfg.Dialer = dialer.DialContext
-        }
-        */
+        cfg.filter.validate()?;
 
         // Clear the Password to avoid outputing it in log.
         let pass = cfg.password.clone();
@@ -174,768 +239,1370 @@ impl BinlogSyncer {
         log::info!("create BinlogSyncer with config {:?}", &cfg);
         cfg.password = pass;
 
-        let mut parser = BinlogParser::new();
-        parser.set_flavor(cfg.flavor.clone());
-        parser.set_raw_mode(cfg.raw_mode_enabled);
-        parser.set_parse_time(cfg.parse_time);
-        parser.set_timestamp_string_location(cfg.timestamp_string_location.clone());
-        parser.set_use_decimal(cfg.use_decimal);
-        parser.set_verify_checksum(cfg.verify_checksum);
-        parser.set_rows_event_decode_func(cfg.rows_event_decode_func.clone());
-
-        let (ctx, cancel) = tokio_context::context::Context::new();
+        let (_, handle) = tokio_context::context::Context::new();
         Ok(BinlogSyncer {
-            _m: sync::RwLock::new(()),
-            _cfg: cfg,
-            _c: Default::default(),
-            _parser: parser,
-            _next_pos: None,
-            _prev_gset: None,
-            _curr_gset: None,
-            _prev_mysql_gtid_event: None,
-            _running: false,
-            _ctx: ctx,
-            _cancel: cancel,
-            _last_connection_id: 0,
-            _retry_count: 0,
+            _cfg: Arc::new(cfg),
+            _s: Arc::new(Mutex::new(SyncerState::default())),
+            _handle: Some(handle),
+            _wg: None,
+            _streamer: None,
+            _buf_pool: packet::BufPool::new(),
         })
     }
 
-    /*
-    // Close closes the BinlogSyncer.
-    func (b *BinlogSyncer) Close() {
-        b.m.Lock()
-        defer b.m.Unlock()
-
-        b.close()
-    }
-    */
+    // Close stops the sync. The context of the dump task is cancelled, which stops it at once wherever it
+    // waits, reading the connection, retrying the sync or sending to a full streamer, so no read deadline is
+    // needed. Its connection is killed from a new one and shut down, Close waits for the task to end and
+    // drains the events it sent but nobody received yet.
+    // No event is delivered after Close returns, GetEvent returns ERR_SYNC_CLOSED then.
+    pub async fn close(&mut self) -> Vec<BinlogEvent> {
+        let (conn, last_connection_id) = {
+            let mut state = self._s.lock().unwrap();
+            if state._closed {
+                return vec![];
+            }
+            log::info!("syncer is closing...");
+
+            state._closed = true;
+            state._ack_conn = None;
+            (state._conn.take(), state._last_connection_id)
+        };
+        if let Some(handle) = self._handle.take() {
+            handle.cancel();
+        }
 
-    // async fn _close(&mut self) {
-    //     if self._is_closed().await {
-    //         return;
-    //     }
+        if let Some(c) = conn {
+            // kill last connection id
+            if last_connection_id > 0 {
+                // Use a new connection to kill the binlog syncer
+                // because calling KILL from the same connection
+                // doesn't actually disconnect it.
+                let d =
+                    BinlogDumper::new(self._cfg.clone(), self._s.clone(), self._buf_pool.clone());
+                if let Ok(mut c) = d._new_connection().await {
+                    d._kill_connection(&mut c, last_connection_id).await;
+                    let _ = c.close().await;
+                }
+            }
 
-    //     log::info!("syncer is closing...");
+            let _ = c.close();
+        }
 
-    //     self._running = false;
-    //     self._cancel.cancel();
+        if let Some(wg) = self._wg.take() {
+            let _ = wg.await;
+        }
 
-    //     // if let Some(c) = &self._c {
-    //     //     c.set
-    //     // }
-    // }
-    /*
-    func (b *BinlogSyncer) close() {
-        if b.isClosed() {
-            return
+        let mut events = vec![];
+        if let Some(mut s) = self._streamer.take() {
+            events = s.dump_events().await.unwrap_or_default();
+            s.close();
         }
 
-        b.cfg.Logger.Info("syncer is closing...")
+        log::info!("syncer is closed");
+        events
+    }
 
-        b.running = false
-        b.cancel()
+    fn _is_closed(&self) -> bool {
+        self._s.lock().unwrap()._closed
+    }
 
-        if b.c != nil {
-            err := b.c.SetReadDeadline(time.Now().Add(100 * time.Millisecond))
-            if err != nil {
-                b.cfg.Logger.Warnf(`could not set read deadline: %s`, err)
+    fn _start_dump_stream(
+        &mut self,
+        mut d: BinlogDumper,
+    ) -> Result<BinlogStreamer, ReplicationError> {
+        let ctx = match &mut self._handle {
+            Some(handle) => handle.spawn_ctx(),
+            None => return Err(ReplicationError::new(ERR_SYNC_CLOSED.to_string())),
+        };
+        self._s.lock().unwrap()._running = true;
+
+        let s = BinlogStreamer::new();
+        let ss = s.clone_with_no_error();
+        let es = s.clone_with_no_error();
+        self._streamer = Some(s.clone_with_no_error());
+
+        let dump = tokio::spawn(async move { d._on_stream(ctx, &ss).await });
+        let state = self._s.clone();
+        self._wg = Some(tokio::spawn(async move {
+            if let Err(e) = dump.await {
+                if e.is_panic() {
+                    es.close_with_error(Err(ReplicationError::new(format!("Err: {:?}", e))));
+                }
             }
+            state.lock().unwrap()._running = false;
+        }));
+
+        Ok(s)
+    }
+
+    // StartSync starts syncing from the `pos` position.
+    pub async fn start_sync(&mut self, pos: Position) -> Result<BinlogStreamer, ReplicationError> {
+        log::info!("begin to sync binlog from position {}", pos);
+
+        if self._is_closed() {
+            return Err(ReplicationError::new(ERR_SYNC_CLOSED.to_string()));
         }
 
-        // kill last connection id
-        if b.lastConnectionID > 0 {
-            // Use a new connection to kill the binlog syncer
-            // because calling KILL from the same connection
-            // doesn't actually disconnect it.
-            c, err := b.newConnection(context.Background())
-            if err == nil {
-                b.killConnection(c, b.lastConnectionID)
-                c.Close()
+        {
+            let mut state = self._s.lock().unwrap();
+            if state._running {
+                return Err(ReplicationError::new(_ERR_SYNC_RUNNING.to_string()));
             }
+
+            state._prev_mysql_gtid_event = None;
+            state._prev_gset = None;
+            state._curr_gset = None;
         }
 
-        b.wg.Wait()
+        let mut d = BinlogDumper::new(self._cfg.clone(), self._s.clone(), self._buf_pool.clone());
+        d._prepare_sync_pos(pos).await?;
 
-        if b.c != nil {
-            b.c.Close()
+        self._start_dump_stream(d)
+    }
+
+    // StartSyncFromTime starts syncing from the first transaction at or after `t`, `t` is in UTC like
+    // BinlogStreamer::get_event_with_start_time. The binlogs of SHOW BINARY LOGS are binary searched by the
+    // time of their first event, then the found binlog is scanned for the transaction.
+    pub async fn start_sync_from_time(
+        &mut self,
+        t: NaiveDateTime,
+    ) -> Result<BinlogStreamer, ReplicationError> {
+        log::info!("begin to find the binlog position of time {}", t);
+
+        if self._is_closed() {
+            return Err(ReplicationError::new(ERR_SYNC_CLOSED.to_string()));
+        }
+        // the scan would kill the connection of the running sync
+        if self._s.lock().unwrap()._running {
+            return Err(ReplicationError::new(_ERR_SYNC_RUNNING.to_string()));
         }
 
-        b.cfg.Logger.Info("syncer is closed")
+        let pos = BinlogDumper::_find_position_by_time(&self._cfg, t.and_utc().timestamp()).await?;
+        log::info!("the first transaction at or after {} is at {}", t, pos);
+
+        self.start_sync(pos).await
     }
-    */
-    // async fn _is_closed(&self) -> bool {
-    //     select! {
-    //         _ = self._ctx.done() => {
-    //             return true;
-    //         }
-    //         _ = tokio::time::sleep(tokio::time::Duration::from_micros(10)) => {
-    //             return false;
-    //         }
-    //     }
-    // }
-    /*
-    func (b *BinlogSyncer) registerSlave() error {
-        if b.c != nil {
-            b.c.Close()
-        }
 
-        var err error
-        b.c, err = b.newConnection(b.ctx)
-        if err != nil {
-            return errors.Trace(err)
+    // StartSyncGTID starts syncing from the `gset` GTIDSet.
+    pub async fn start_sync_gtid(
+        &mut self,
+        gset: GtidSetEnum,
+    ) -> Result<BinlogStreamer, ReplicationError> {
+        log::info!("begin to sync binlog from GTID set {}", gset);
+
+        if self._is_closed() {
+            return Err(ReplicationError::new(ERR_SYNC_CLOSED.to_string()));
         }
 
-        if b.cfg.Option != nil {
-            if err = b.cfg.Option(b.c); err != nil {
-                return errors.Trace(err)
+        {
+            let mut state = self._s.lock().unwrap();
+            if state._running {
+                return Err(ReplicationError::new(_ERR_SYNC_RUNNING.to_string()));
             }
-        }
 
-        if len(b.cfg.Charset) != 0 {
-            if err = b.c.SetCharset(b.cfg.Charset); err != nil {
-                return errors.Trace(err)
-            }
+            state._prev_mysql_gtid_event = None;
+            state._prev_gset = Some(gset.clone());
         }
 
-        //set read timeout
-        if b.cfg.ReadTimeout > 0 {
-            _ = b.c.SetReadDeadline(time.Now().Add(b.cfg.ReadTimeout))
-        }
+        let mut d = BinlogDumper::new(self._cfg.clone(), self._s.clone(), self._buf_pool.clone());
+        d._prepare_sync_gtid(&gset).await?;
 
-        if b.cfg.RecvBufferSize > 0 {
-            if tcp, ok := b.c.Conn.Conn.(*net.TCPConn); ok {
-                _ = tcp.SetReadBuffer(b.cfg.RecvBufferSize)
-            }
-        }
+        self._start_dump_stream(d)
+    }
 
-        // kill last connection id
-        if b.lastConnectionID > 0 {
-            b.killConnection(b.c, b.lastConnectionID)
-        }
+    // ReplySemiSyncACK tells the master the event has been received, call it once the event is durably handled.
+    // Events without semi_sync_ack_pos are ignored.
+    pub async fn reply_semi_sync_ack(&self, e: &BinlogEvent) -> Result<(), ReplicationError> {
+        let p = match &e.semi_sync_ack_pos {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+
+        let ack_conn = self._s.lock().unwrap()._ack_conn.clone();
+        let ack_conn = ack_conn.ok_or(ReplicationError::new(
+            "semi-sync ACK connection is none".to_string(),
+        ))?;
+        let mut c = ack_conn.lock().await;
+        c.reset_sequence();
+
+        let mut data = vec![0_u8; 4 + 1 + 8 + p.name.len()];
+        let mut pos = 4;
+        // semi sync indicator
+        data[pos] = SEMI_SYNC_INDICATOR;
+        pos += 1;
 
-        // save last last connection id for kill
-        b.lastConnectionID = b.c.GetConnectionID()
+        LittleEndian::write_u64(&mut data[pos..], p.pos as u64);
+        pos += 8;
 
-        //for mysql 5.6+, binlog has a crc32 checksum
-        //before mysql 5.6, this will not work, don't matter.:-)
-        if r, err := b.c.Execute("SHOW GLOBAL VARIABLES LIKE 'BINLOG_CHECKSUM'"); err != nil {
-            return errors.Trace(err)
-        } else {
-            s, _ := r.GetString(0, 1)
-            if s != "" {
-                // maybe CRC32 or NONE
+        data[pos..].copy_from_slice(p.name.as_bytes());
 
-                // mysqlbinlog.cc use NONE, see its below comments:
-                // Make a notice to the server that this client
-                // is checksum-aware. It does not need the first fake Rotate
-                // necessary checksummed.
-                // That preference is specified below.
+        c.write_packet(&mut data).await?;
 
-                if _, err = b.c.Execute(`SET @master_binlog_checksum='NONE'`); err != nil {
-                    return errors.Trace(err)
-                }
+        Ok(())
+    }
 
-                // if _, err = b.c.Execute(`SET @master_binlog_checksum=@@global.binlog_checksum`); err != nil {
-                // 	return errors.Trace(err)
-                // }
+    // StartSyncFromCheckpoint starts the sync from the checkpoint of cfg.checkpoint_store, or from initial if
+    // nothing was saved yet. A checkpoint with a GTID set starts a GTID based sync.
+    pub async fn start_sync_from_checkpoint(
+        &mut self,
+        initial: Checkpoint,
+    ) -> Result<BinlogStreamer, ReplicationError> {
+        let store = self
+            ._cfg
+            .checkpoint_store
+            .clone()
+            .ok_or(ReplicationError::new(
+                "checkpoint store is none".to_string(),
+            ))?;
+
+        let checkpoint = match store.load()? {
+            Some(checkpoint) => checkpoint,
+            None => {
+                log::info!("no checkpoint saved, start from the initial one");
+                initial
             }
-        }
+        };
 
-        if b.cfg.Flavor == MariaDBFlavor {
-            // Refer https://github.com/alibaba/canal/wiki/BinlogChange(MariaDB5&10)
-            // Tell the server that we understand GTIDs by setting our slave capability
-            // to MARIA_SLAVE_CAPABILITY_GTID = 4 (MariaDB >= 10.0.1).
-            if _, err := b.c.Execute("SET @mariadb_slave_capability=4"); err != nil {
-                return errors.Errorf("failed to set @mariadb_slave_capability=4: %v", err)
-            }
+        match checkpoint.gset {
+            Some(gset) => self.start_sync_gtid(gset).await,
+            None => self.start_sync(checkpoint.position).await,
         }
+    }
 
-        if b.cfg.HeartbeatPeriod > 0 {
-            _, err = b.c.Execute(fmt.Sprintf("SET @master_heartbeat_period=%d;", b.cfg.HeartbeatPeriod))
-            if err != nil {
-                b.cfg.Logger.Errorf("failed to set @master_heartbeat_period=%d, err: %v", b.cfg.HeartbeatPeriod, err)
-                return errors.Trace(err)
-            }
+    // SaveCheckpoint saves the checkpoint of the event to cfg.checkpoint_store, call it once the transaction
+    // ended by the event has been durably handled. Events without checkpoint are ignored.
+    pub fn save_checkpoint(&self, e: &BinlogEvent) -> Result<(), ReplicationError> {
+        match (&self._cfg.checkpoint_store, &e.checkpoint) {
+            (Some(store), Some(checkpoint)) => store.save(checkpoint),
+            _ => Ok(()),
         }
+    }
+
+    // LastConnectionID returns last connectionID.
+    pub fn last_connection_id(&self) -> u32 {
+        self._s.lock().unwrap()._last_connection_id
+    }
+
+    // GetNextPosition returns the next position of the syncer, it may be in the middle of a transaction.
+    pub fn get_next_position(&self) -> Position {
+        self._s.lock().unwrap()._next_pos.clone()
+    }
 
-        if err = b.writeRegisterSlaveCommand(); err != nil {
-            return errors.Trace(err)
+    // Position returns the position right after the last committed transaction the syncer has read,
+    // a sync started from it neither misses nor repeats a transaction.
+    pub fn position(&self) -> Position {
+        self._s.lock().unwrap()._committed_pos.clone()
+    }
+
+    // GtidSet returns the GTID set of the transactions committed so far,
+    // none if the sync was not started with StartSyncGTID.
+    pub fn gtid_set(&self) -> Option<GtidSetEnum> {
+        self._s.lock().unwrap()._prev_gset.clone()
+    }
+}
+
+impl BinlogDumper {
+    fn new(
+        cfg: Arc<BinlogSyncerConfig>,
+        s: Arc<Mutex<SyncerState>>,
+        buf_pool: packet::BufPool,
+    ) -> BinlogDumper {
+        let mut parser = BinlogParser::new();
+        parser.set_flavor(cfg.flavor.clone());
+        parser.set_raw_mode(cfg.raw_mode_enabled);
+        parser.set_parse_time(cfg.parse_time);
+        parser.set_timestamp_string_location(cfg.timestamp_string_location);
+        parser.set_use_decimal(cfg.use_decimal);
+        parser.set_lazy_rows_decoding(cfg.lazy_rows_decoding);
+        parser.set_verify_checksum(cfg.verify_checksum);
+        parser.set_rows_event_decode_func(cfg.rows_event_decode_func.clone());
+        parser.set_filter(cfg.filter.clone());
+        parser.set_rewrite_rules(cfg.rewrite_rules.clone());
+
+        let semi_sync_enabled = cfg.semi_sync_enabled;
+        BinlogDumper {
+            _cfg: cfg,
+            _s: s,
+            _c: None,
+            _parser: parser,
+            _semi_sync_enabled: semi_sync_enabled,
+            _buf_pool: buf_pool,
         }
+    }
+
+    fn _conn(&mut self) -> Result<&mut AsyncConn, ReplicationError> {
+        self._c.as_mut().ok_or(ReplicationError::new(
+            "binlog connection is none".to_string(),
+        ))
+    }
 
-        if _, err = b.c.ReadOKPacket(); err != nil {
-            return errors.Trace(err)
+    async fn _close_conn(&mut self) {
+        if let Some(mut c) = self._c.take() {
+            let _ = c.close().await;
         }
+        self._s.lock().unwrap()._conn = None;
+    }
 
-        serverUUID, err := uuid.NewUUID()
-        if err != nil {
-            b.cfg.Logger.Errorf("failed to get new uud %v", err)
-            return errors.Trace(err)
+    async fn _register_slave(&mut self) -> Result<(), ReplicationError> {
+        if let Some(mut c) = self._c.take() {
+            let _ = c.close().await;
         }
-        if _, err = b.c.Execute(fmt.Sprintf("SET @slave_uuid = '%s', @replica_uuid = '%s'", serverUUID, serverUUID)); err != nil {
-            b.cfg.Logger.Errorf("failed to set @slave_uuid = '%s', err: %v", serverUUID, err)
-            return errors.Trace(err)
+
+        let mut c = self._new_connection().await?;
+
+        let last_connection_id = self._s.lock().unwrap()._last_connection_id;
+        if last_connection_id > 0 {
+            self._kill_connection(&mut c, last_connection_id).await;
         }
 
-        return nil
-    }
+        if let Some(option) = &self._cfg.option {
+            option(&mut c).await?;
+        }
 
-    func (b *BinlogSyncer) enableSemiSync() error {
-        if !b.cfg.SemiSyncEnabled {
-            return nil
+        if !self._cfg.charset.is_empty() {
+            c.set_charset(&self._cfg.charset).await?;
         }
 
-        if r, err := b.c.Execute("SHOW VARIABLES LIKE 'rpl_semi_sync_master_enabled';"); err != nil {
-            return errors.Trace(err)
-        } else {
-            s, _ := r.GetString(0, 1)
-            if s != "ON" {
-                b.cfg.Logger.Errorf("master does not support semi synchronous replication, use no semi-sync")
-                b.cfg.SemiSyncEnabled = false
-                return nil
+        // save last last connection id for kill
+        {
+            let mut state = self._s.lock().unwrap();
+            state._last_connection_id = c.get_connection_id();
+            state._conn = c
+                .try_clone_packet_conn()
+                .ok()
+                .map(|c| c.get_ref().try_clone());
+            if state._closed {
+                // Close ran while reconnecting, it didn't see this connection
+                return Err(ReplicationError::new(ERR_SYNC_CLOSED.to_string()));
             }
         }
+        self._c = Some(c);
 
-        _, err := b.c.Execute(`SET @rpl_semi_sync_slave = 1;`)
-        if err != nil {
-            return errors.Trace(err)
+        //for mysql 5.6+, binlog has a crc32 checksum
+        //before mysql 5.6, this will not work, don't matter.:-)
+        let r = self
+            ._conn()?
+            .execute("SHOW GLOBAL VARIABLES LIKE 'BINLOG_CHECKSUM'")
+            .await?;
+        let s = match &r.result_set {
+            Some(rs) => rs.get_string(0, 1).unwrap_or_default(),
+            None => String::new(),
+        };
+        if !s.is_empty() {
+            // maybe CRC32 or NONE
+
+            // mysqlbinlog.cc use NONE, see its below comments:
+            // Make a notice to the server that this client
+            // is checksum-aware. It does not need the first fake Rotate
+            // necessary checksummed.
+            // That preference is specified below.
+            let _ = self
+                ._conn()?
+                .execute("SET @master_binlog_checksum='NONE'")
+                .await?;
         }
 
-        return nil
-    }
+        if !self._cfg.heartbeat_period.is_zero() {
+            // a master sends a heartbeat when it has no event to send in the period,
+            // so with a read timeout longer than the period only a dead master times out
+            if !self._cfg.read_timeout.is_zero()
+                && self._cfg.read_timeout <= self._cfg.heartbeat_period
+            {
+                log::warn!(
+                    "read timeout {:?} is not longer than heartbeat period {:?}, an idle master will time out",
+                    self._cfg.read_timeout,
+                    self._cfg.heartbeat_period
+                );
+            }
 
-    func (b *BinlogSyncer) prepare() error {
-        if b.isClosed() {
-            return errors.Trace(ErrSyncClosed)
+            let heartbeat_period = self._cfg.heartbeat_period.as_nanos();
+            if let Err(e) = self
+                ._conn()?
+                .execute(&format!(
+                    "SET @master_heartbeat_period={};",
+                    heartbeat_period
+                ))
+                .await
+            {
+                log::error!(
+                    "failed to set @master_heartbeat_period={}, err: {}",
+                    heartbeat_period,
+                    e
+                );
+                return Err(e);
+            }
         }
 
-        if err := b.registerSlave(); err != nil {
-            return errors.Trace(err)
+        if self._cfg.flavor == mysql::MARIA_DB_FLAVOR {
+            // Refer https://github.com/alibaba/canal/wiki/BinlogChange(MariaDB5&10)
+            // Tell the server that we understand GTIDs by setting our slave capability
+            // to MARIA_SLAVE_CAPABILITY_GTID = 4 (MariaDB >= 10.0.1).
+            if let Err(e) = self
+                ._conn()?
+                .execute("SET @mariadb_slave_capability=4")
+                .await
+            {
+                return Err(ReplicationError::new(format!(
+                    "failed to set @mariadb_slave_capability=4: {}",
+                    e
+                )));
+            }
         }
 
-        if err := b.enableSemiSync(); err != nil {
-            return errors.Trace(err)
+        self._write_register_slave_command().await?;
+
+        let _ = self._conn()?.read_ok_packet().await?;
+
+        let mut node_id = [0_u8; 6];
+        rand::Rng::fill(&mut rand::thread_rng(), &mut node_id);
+        let server_uuid = uuid::Uuid::now_v1(&node_id);
+        if let Err(e) = self
+            ._conn()?
+            .execute(&format!(
+                "SET @slave_uuid = '{}', @replica_uuid = '{}'",
+                server_uuid, server_uuid
+            ))
+            .await
+        {
+            log::error!("failed to set @slave_uuid = '{}', err: {}", server_uuid, e);
+            return Err(e);
         }
 
-        return nil
+        Ok(())
     }
 
-    func (b *BinlogSyncer) startDumpStream() *BinlogStreamer {
-        b.running = true
-
-        s := NewBinlogStreamer()
-
-        b.wg.Add(1)
-        go b.onStream(s)
-        return s
+    async fn _kill_connection(&self, c: &mut AsyncConn, id: u32) {
+        let cmd = format!("KILL {}", id);
+        if let Err(e) = c.execute(&cmd).await {
+            log::error!("kill connection {} error {}", id, e);
+            // Unknown thread id
+            if MyError::error_code(&e.to_string()) != mysql::ER_NO_SUCH_THREAD as isize {
+                log::error!("{}", e);
+            }
+        }
+        log::info!("kill last connection id {}", id);
     }
 
-    // GetNextPosition returns the next position of the syncer
-    func (b *BinlogSyncer) GetNextPosition() Position {
-        return b.nextPos
-    }
+    async fn _prepare(&mut self) -> Result<(), ReplicationError> {
+        self._register_slave().await?;
 
-    // StartSync starts syncing from the `pos` position.
-    func (b *BinlogSyncer) StartSync(pos Position) (*BinlogStreamer, error) {
-        b.cfg.Logger.Infof("begin to sync binlog from position %s", pos)
+        self._enable_semi_sync().await?;
 
-        b.m.Lock()
-        defer b.m.Unlock()
+        Ok(())
+    }
 
-        if b.running {
-            return nil, errors.Trace(errSyncRunning)
+    async fn _enable_semi_sync(&mut self) -> Result<(), ReplicationError> {
+        self._s.lock().unwrap()._ack_conn = None;
+        if !self._semi_sync_enabled {
+            return Ok(());
         }
 
-        if err := b.prepareSyncPos(pos); err != nil {
-            return nil, errors.Trace(err)
+        let r = self
+            ._conn()?
+            .execute("SHOW VARIABLES LIKE 'rpl_semi_sync_master_enabled';")
+            .await?;
+        let s = match &r.result_set {
+            Some(rs) => rs.get_string(0, 1).unwrap_or_default(),
+            None => String::new(),
+        };
+        if s != "ON" {
+            log::error!("master does not support semi synchronous replication, use no semi-sync");
+            self._semi_sync_enabled = false;
+            return Ok(());
         }
 
-        return b.startDumpStream(), nil
-    }
-
-    // StartSyncGTID starts syncing from the `gset` GTIDSet.
-    func (b *BinlogSyncer) StartSyncGTID(gset GTIDSet) (*BinlogStreamer, error) {
-        b.cfg.Logger.Infof("begin to sync binlog from GTID set %s", gset)
+        let _ = self
+            ._conn()?
+            .execute("SET @rpl_semi_sync_slave = 1;")
+            .await?;
 
-        b.prevMySQLGTIDEvent = nil
-        b.prevGset = gset
+        let ack_conn = self._conn()?.try_clone_packet_conn()?;
+        self._s.lock().unwrap()._ack_conn = Some(Arc::new(tokio::sync::Mutex::new(ack_conn)));
 
-        b.m.Lock()
-        defer b.m.Unlock()
+        Ok(())
+    }
 
-        if b.running {
-            return nil, errors.Trace(errSyncRunning)
+    async fn _prepare_sync_pos(&mut self, mut pos: Position) -> Result<(), ReplicationError> {
+        // always start from position 4
+        if pos.pos < 4 {
+            pos.pos = 4;
         }
 
-        // establishing network connection here and will start getting binlog events from "gset + 1", thus until first
-        // MariadbGTIDEvent/GTIDEvent event is received - we effectively do not have a "current GTID"
-        b.currGset = nil
+        self._prepare().await?;
 
-        if err := b.prepare(); err != nil {
-            return nil, errors.Trace(err)
-        }
+        self._write_binlog_dump_command(&pos).await?;
 
-        var err error
-        switch b.cfg.Flavor {
-        case MariaDBFlavor:
-            err = b.writeBinlogDumpMariadbGTIDCommand(gset)
-        default:
-            // default use MySQL
-            err = b.writeBinlogDumpMysqlGTIDCommand(gset)
-        }
+        Ok(())
+    }
 
-        if err != nil {
-            return nil, err
+    async fn _retry_sync(&mut self) -> Result<(), ReplicationError> {
+        self._parser.reset();
+
+        let (prev_gset, committed_pos) = {
+            let mut state = self._s.lock().unwrap();
+            // the pending GTID belongs to a transaction which was not committed yet, it will be sent again
+            state._prev_mysql_gtid_event = None;
+            state._in_transaction = false;
+            state._in_begin = false;
+            (state._prev_gset.clone(), state._committed_pos.clone())
+        };
+
+        match prev_gset {
+            Some(gset) => {
+                let mut msg = format!("begin to re-sync from {}", gset);
+                if let Some(curr_gset) = &self._s.lock().unwrap()._curr_gset {
+                    msg = format!("{} (last read GTID={})", msg, curr_gset);
+                }
+                log::info!("{}", msg);
+
+                self._prepare_sync_gtid(&gset).await
+            }
+            None => {
+                log::info!("begin to re-sync from {}", committed_pos);
+                self._prepare_sync_pos(committed_pos).await
+            }
         }
+    }
 
-        return b.startDumpStream(), nil
+    // retry_sync_interval doubles the wait time for every failed attempt.
+    fn _retry_sync_interval(retry_count: usize) -> std::time::Duration {
+        let shift = retry_count.saturating_sub(1).min(16) as u32;
+        std::cmp::min(
+            _RETRY_SYNC_BASE_INTERVAL * 2_u32.pow(shift),
+            _RETRY_SYNC_MAX_INTERVAL,
+        )
     }
 
-    func (b *BinlogSyncer) writeBinlogDumpCommand(p Position) error {
-        b.c.ResetSequence()
+    // on_connection_error reconnects and resumes the dump after a broken connection,
+    // returns false if the stream was closed instead.
+    async fn _on_connection_error(&mut self, s: &BinlogStreamer, err: ReplicationError) -> bool {
+        {
+            let state = self._s.lock().unwrap();
+            if state._closed {
+                // the connection was broken by Close
+                return false;
+            }
+            if state._committed_pos.name.is_empty() && state._prev_gset.is_none() {
+                // we can't get the correct position, close.
+                drop(state);
+                s.close_with_error(Err(err));
+                return false;
+            }
+        }
 
-        data := make([]byte, 4+1+4+2+4+len(p.Name))
+        if self._cfg.disable_retry_sync {
+            log::warn!("retry sync is disabled");
+            s.close_with_error(Err(err));
+            return false;
+        }
 
-        pos := 4
-        data[pos] = COM_BINLOG_DUMP
-        pos++
+        loop {
+            let retry_count = {
+                let mut state = self._s.lock().unwrap();
+                state._retry_count += 1;
+                state._retry_count
+            };
+            // Close cancels the wait with the dump task
+            tokio::time::sleep(BinlogDumper::_retry_sync_interval(retry_count)).await;
+            if self._s.lock().unwrap()._closed {
+                return false;
+            }
 
-        binary.LittleEndian.PutUint32(data[pos:], p.Pos)
-        pos += 4
+            match self._retry_sync().await {
+                Ok(_) => return true,
+                Err(e) => {
+                    if self._cfg.max_reconnect_attempts > 0
+                        && retry_count >= self._cfg.max_reconnect_attempts
+                    {
+                        log::error!(
+                            "retry sync err: {}, exceeded max retries ({})",
+                            e,
+                            self._cfg.max_reconnect_attempts
+                        );
+                        s.close_with_error(Err(e));
+                        return false;
+                    }
 
-        binary.LittleEndian.PutUint16(data[pos:], b.cfg.DumpCommandFlag)
-        pos += 2
+                    log::error!(
+                        "retry sync err: {}, wait {:?} and retry again",
+                        e,
+                        BinlogDumper::_retry_sync_interval(retry_count + 1)
+                    );
+                }
+            }
+        }
+    }
 
-        binary.LittleEndian.PutUint32(data[pos:], b.cfg.ServerID)
-        pos += 4
+    async fn _prepare_sync_gtid(&mut self, gset: &GtidSetEnum) -> Result<(), ReplicationError> {
+        // re establishing network connection here and will start getting binlog events from "gset + 1", thus until first
+        // MariadbGTIDEvent/GTIDEvent event is received - we effectively do not have a "current GTID"
+        self._s.lock().unwrap()._curr_gset = None;
 
-        copy(data[pos:], p.Name)
+        self._prepare().await?;
 
-        return b.c.WritePacket(data)
+        match self._cfg.flavor.as_str() {
+            mysql::MARIA_DB_FLAVOR => self._write_binlog_dump_mariadb_gtid_command(gset).await,
+            // default use MySQL
+            _ => self._write_binlog_dump_mysql_gtid_command(gset).await,
+        }
     }
 
-    func (b *BinlogSyncer) writeBinlogDumpMysqlGTIDCommand(gset GTIDSet) error {
-        p := Position{Name: "", Pos: 4}
-        gtidData := gset.Encode()
+    async fn _write_binlog_dump_mysql_gtid_command(
+        &mut self,
+        gset: &GtidSetEnum,
+    ) -> Result<(), ReplicationError> {
+        let p = Position {
+            name: String::new(),
+            pos: 4,
+        };
+        let gtid_data = gset.encode()?;
+        let server_id = self._cfg.server_id;
 
-        b.c.ResetSequence()
+        let c = self._conn()?;
+        c.reset_sequence();
 
-        data := make([]byte, 4+1+2+4+4+len(p.Name)+8+4+len(gtidData))
-        pos := 4
-        data[pos] = COM_BINLOG_DUMP_GTID
-        pos++
+        let mut data = vec![0_u8; 4 + 1 + 2 + 4 + 4 + p.name.len() + 8 + 4 + gtid_data.len()];
+        let mut pos = 4;
+        data[pos] = mysql::COM_BINLOG_DUMP_GTID;
+        pos += 1;
 
-        binary.LittleEndian.PutUint16(data[pos:], 0)
-        pos += 2
+        LittleEndian::write_u16(&mut data[pos..], 0);
+        pos += 2;
 
-        binary.LittleEndian.PutUint32(data[pos:], b.cfg.ServerID)
-        pos += 4
+        LittleEndian::write_u32(&mut data[pos..], server_id);
+        pos += 4;
 
-        binary.LittleEndian.PutUint32(data[pos:], uint32(len(p.Name)))
-        pos += 4
+        LittleEndian::write_u32(&mut data[pos..], p.name.len() as u32);
+        pos += 4;
 
-        n := copy(data[pos:], p.Name)
-        pos += n
+        data[pos..pos + p.name.len()].copy_from_slice(p.name.as_bytes());
+        pos += p.name.len();
 
-        binary.LittleEndian.PutUint64(data[pos:], uint64(p.Pos))
-        pos += 8
+        LittleEndian::write_u64(&mut data[pos..], p.pos as u64);
+        pos += 8;
 
-        binary.LittleEndian.PutUint32(data[pos:], uint32(len(gtidData)))
-        pos += 4
-        n = copy(data[pos:], gtidData)
-        pos += n
+        LittleEndian::write_u32(&mut data[pos..], gtid_data.len() as u32);
+        pos += 4;
 
-        data = data[0:pos]
+        data[pos..].copy_from_slice(&gtid_data);
 
-        return b.c.WritePacket(data)
+        c.write_packet(&mut data).await
     }
 
-    func (b *BinlogSyncer) writeBinlogDumpMariadbGTIDCommand(gset GTIDSet) error {
+    async fn _write_binlog_dump_mariadb_gtid_command(
+        &mut self,
+        gset: &GtidSetEnum,
+    ) -> Result<(), ReplicationError> {
         // Copy from vitess
 
-        startPos := gset.String()
+        let start_pos = gset.to_string();
 
         // Set the slave_connect_state variable before issuing COM_BINLOG_DUMP to
         // provide the start position in GTID form.
-        query := fmt.Sprintf("SET @slave_connect_state='%s'", startPos)
+        let query = format!("SET @slave_connect_state='{}'", start_pos);
 
-        if _, err := b.c.Execute(query); err != nil {
-            return errors.Errorf("failed to set @slave_connect_state='%s': %v", startPos, err)
+        if let Err(e) = self._conn()?.execute(&query).await {
+            return Err(ReplicationError::new(format!(
+                "failed to set @slave_connect_state='{}': {}",
+                start_pos, e
+            )));
         }
 
         // Real slaves set this upon connecting if their gtid_strict_mode option was
         // enabled. We always use gtid_strict_mode because we need it to make our
         // internal GTID comparisons safe.
-        if _, err := b.c.Execute("SET @slave_gtid_strict_mode=1"); err != nil {
-            return errors.Errorf("failed to set @slave_gtid_strict_mode=1: %v", err)
+        if let Err(e) = self._conn()?.execute("SET @slave_gtid_strict_mode=1").await {
+            return Err(ReplicationError::new(format!(
+                "failed to set @slave_gtid_strict_mode=1: {}",
+                e
+            )));
         }
 
         // Since we use @slave_connect_state, the file and position here are ignored.
-        return b.writeBinlogDumpCommand(Position{Name: "", Pos: 0})
+        self._write_binlog_dump_command(&Position {
+            name: String::new(),
+            pos: 0,
+        })
+        .await
+    }
+
+    async fn _write_binlog_dump_command(&mut self, p: &Position) -> Result<(), ReplicationError> {
+        self._write_binlog_dump_command_with_flag(p, self._cfg.dump_command_flag)
+            .await
+    }
+
+    async fn _write_binlog_dump_command_with_flag(
+        &mut self,
+        p: &Position,
+        dump_command_flag: u16,
+    ) -> Result<(), ReplicationError> {
+        let server_id = self._cfg.server_id;
+
+        let c = self._conn()?;
+        c.reset_sequence();
+
+        let mut data = vec![0_u8; 4 + 1 + 4 + 2 + 4 + p.name.len()];
+
+        let mut pos = 4;
+        data[pos] = mysql::COM_BINLOG_DUMP;
+        pos += 1;
+
+        LittleEndian::write_u32(&mut data[pos..], p.pos);
+        pos += 4;
+
+        LittleEndian::write_u16(&mut data[pos..], dump_command_flag);
+        pos += 2;
+
+        LittleEndian::write_u32(&mut data[pos..], server_id);
+        pos += 4;
+
+        data[pos..].copy_from_slice(p.name.as_bytes());
+
+        c.write_packet(&mut data).await
     }
 
     // localHostname returns the hostname that register slave would register as.
-    func (b *BinlogSyncer) localHostname() string {
-        if len(b.cfg.Localhost) == 0 {
-            h, _ := os.Hostname()
-            return h
+    fn _local_hostname(&self) -> String {
+        if self._cfg.localhost.is_empty() {
+            if let Ok(h) = std::fs::read_to_string("/proc/sys/kernel/hostname") {
+                return h.trim().to_string();
+            }
+            return std::env::var("HOSTNAME").unwrap_or_default();
         }
-        return b.cfg.Localhost
+
+        self._cfg.localhost.clone()
     }
 
-    func (b *BinlogSyncer) writeRegisterSlaveCommand() error {
-        b.c.ResetSequence()
+    async fn _write_register_slave_command(&mut self) -> Result<(), ReplicationError> {
+        let hostname = self._local_hostname();
+        let cfg = self._cfg.clone();
 
-        hostname := b.localHostname()
+        let c = self._conn()?;
+        c.reset_sequence();
 
         // This should be the name of slave host not the host we are connecting to.
-        data := make([]byte, 4+1+4+1+len(hostname)+1+len(b.cfg.User)+1+len(b.cfg.Password)+2+4+4)
-        pos := 4
-
-        data[pos] = COM_REGISTER_SLAVE
-        pos++
-
-        binary.LittleEndian.PutUint32(data[pos:], b.cfg.ServerID)
-        pos += 4
+        let mut data = vec![
+            0_u8;
+            4 + 1
+                + 4
+                + 1
+                + hostname.len()
+                + 1
+                + cfg.user.len()
+                + 1
+                + cfg.password.len()
+                + 2
+                + 4
+                + 4
+        ];
+        let mut pos = 4;
+
+        data[pos] = mysql::COM_REGISTER_SLAVE;
+        pos += 1;
+
+        LittleEndian::write_u32(&mut data[pos..], cfg.server_id);
+        pos += 4;
 
         // This should be the name of slave hostname not the host we are connecting to.
-        data[pos] = uint8(len(hostname))
-        pos++
-        n := copy(data[pos:], hostname)
-        pos += n
+        data[pos] = hostname.len() as u8;
+        pos += 1;
+        data[pos..pos + hostname.len()].copy_from_slice(hostname.as_bytes());
+        pos += hostname.len();
 
-        data[pos] = uint8(len(b.cfg.User))
-        pos++
-        n = copy(data[pos:], b.cfg.User)
-        pos += n
+        data[pos] = cfg.user.len() as u8;
+        pos += 1;
+        data[pos..pos + cfg.user.len()].copy_from_slice(cfg.user.as_bytes());
+        pos += cfg.user.len();
 
-        data[pos] = uint8(len(b.cfg.Password))
-        pos++
-        n = copy(data[pos:], b.cfg.Password)
-        pos += n
+        data[pos] = cfg.password.len() as u8;
+        pos += 1;
+        data[pos..pos + cfg.password.len()].copy_from_slice(cfg.password.as_bytes());
+        pos += cfg.password.len();
 
-        binary.LittleEndian.PutUint16(data[pos:], b.cfg.Port)
-        pos += 2
+        LittleEndian::write_u16(&mut data[pos..], cfg.port);
+        pos += 2;
 
         //replication rank, not used
-        binary.LittleEndian.PutUint32(data[pos:], 0)
-        pos += 4
+        LittleEndian::write_u32(&mut data[pos..], 0);
+        pos += 4;
 
         // master ID, 0 is OK
-        binary.LittleEndian.PutUint32(data[pos:], 0)
+        LittleEndian::write_u32(&mut data[pos..], 0);
 
-        return b.c.WritePacket(data)
+        c.write_packet(&mut data).await
     }
 
-    func (b *BinlogSyncer) replySemiSyncACK(p Position) error {
-        b.c.ResetSequence()
-
-        data := make([]byte, 4+1+8+len(p.Name))
-        pos := 4
-        // semi sync indicator
-        data[pos] = SemiSyncIndicator
-        pos++
+    // new_probe returns a dumper with its own state and a registered connection, it finds a position by time.
+    // The connections of the probes are closed by them, they aren't the last connection the sync kills.
+    async fn _new_probe(cfg: &Arc<BinlogSyncerConfig>) -> Result<BinlogDumper, ReplicationError> {
+        let mut d = BinlogDumper::new(
+            cfg.clone(),
+            Arc::new(Mutex::new(SyncerState::default())),
+            packet::BufPool::new(),
+        );
+        d._register_slave().await?;
+        Ok(d)
+    }
 
-        binary.LittleEndian.PutUint64(data[pos:], uint64(p.Pos))
-        pos += 8
+    // find_position_by_time returns the position of the first transaction at or after the unix time t.
+    // A probe stopped in the middle of a dump can't run another command, so every binlog probed needs a
+    // connection, but the first one lists the binlogs too and the scan goes on with the dump it needs.
+    async fn _find_position_by_time(
+        cfg: &Arc<BinlogSyncerConfig>,
+        t: i64,
+    ) -> Result<Position, ReplicationError> {
+        let mut first = BinlogDumper::_new_probe(cfg).await?;
+        let logs = first._binary_logs().await?;
+        if logs.is_empty() {
+            return Err(ReplicationError::new(
+                "no binary log found, is binary logging enabled?".to_string(),
+            ));
+        }
 
-        copy(data[pos:], p.Name)
+        // the first time of a binlog is before the times of all the events in it,
+        // find the last binlog starting at or before t
+        let (mut lo, mut hi) = (0, logs.len());
+        // the dump of logs[lo - 1], stopped after its format description event
+        let mut last = None::<BinlogDumper>;
+        let mut idle = Some(first);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            let mut d = match idle.take() {
+                Some(d) => d,
+                None => BinlogDumper::_new_probe(cfg).await?,
+            };
+            if d._first_event_time(&logs[mid]).await? as i64 <= t {
+                lo = mid + 1;
+                if let Some(mut prev) = last.replace(d) {
+                    prev._close_conn().await;
+                }
+            } else {
+                hi = mid;
+                d._close_conn().await;
+            }
+        }
+        let mut d = match last {
+            Some(d) => d,
+            None => {
+                // all the binlogs start after t
+                if let Some(mut d) = idle {
+                    d._close_conn().await;
+                }
+                return Ok(Position {
+                    name: logs[0].clone(),
+                    pos: 4,
+                });
+            }
+        };
 
-        err := b.c.WritePacket(data)
-        if err != nil {
-            return errors.Trace(err)
+        let rs = d._scan_time(&logs[lo - 1], t).await;
+        d._close_conn().await;
+        let (name, pos, found) = rs?;
+        if found {
+            return Ok(Position { name, pos });
         }
 
-        return nil
+        // the scan stopped at the end of the binlogs, or the transaction starts the next binlog
+        match logs.iter().position(|l| *l == name) {
+            Some(i) if i + 1 < logs.len() => Ok(Position {
+                name: logs[i + 1].clone(),
+                pos: 4,
+            }),
+            _ => Ok(Position { name, pos }),
+        }
     }
 
-    func (b *BinlogSyncer) retrySync() error {
-        b.m.Lock()
-        defer b.m.Unlock()
-
-        b.parser.Reset()
-        b.prevMySQLGTIDEvent = nil
-
-        if b.prevGset != nil {
-            msg := fmt.Sprintf("begin to re-sync from %s", b.prevGset.String())
-            if b.currGset != nil {
-                msg = fmt.Sprintf("%v (last read GTID=%v)", msg, b.currGset)
-            }
-            b.cfg.Logger.Infof(msg)
+    async fn _binary_logs(&mut self) -> Result<Vec<String>, ReplicationError> {
+        let r = self._conn()?.execute("SHOW BINARY LOGS").await?;
 
-            if err := b.prepareSyncGTID(b.prevGset); err != nil {
-                return errors.Trace(err)
-            }
-        } else {
-            b.cfg.Logger.Infof("begin to re-sync from %s", b.nextPos)
-            if err := b.prepareSyncPos(b.nextPos); err != nil {
-                return errors.Trace(err)
+        let mut logs = vec![];
+        if let Some(rs) = &r.result_set {
+            for i in 0..rs.row_number() {
+                logs.push(rs.get_string(i, 0)?);
             }
         }
 
-        return nil
+        Ok(logs)
     }
 
-    func (b *BinlogSyncer) prepareSyncPos(pos Position) error {
-        // always start from position 4
-        if pos.Pos < 4 {
-            pos.Pos = 4
-        }
+    // dump_binlog starts dumping the binlog `name` from position 4 on the registered connection.
+    async fn _dump_binlog(&mut self, name: &str) -> Result<(), ReplicationError> {
+        self._parser.reset();
+        self._write_binlog_dump_command_with_flag(
+            &Position {
+                name: name.to_string(),
+                pos: 4,
+            },
+            BINLOG_DUMP_NON_BLOCK,
+        )
+        .await
+    }
 
-        if err := b.prepare(); err != nil {
-            return errors.Trace(err)
-        }
+    // read_binlog calls on_event for every dumped event until it returns false
+    // or the dump reaches the end of the binlogs.
+    async fn _read_binlog(
+        &mut self,
+        on_event: impl FnMut(&BinlogEvent) -> bool,
+    ) -> Result<(), ReplicationError> {
+        let mut data = self._buf_pool.get();
+        let rs = self._read_binlog_into(&mut data, on_event).await;
+        self._buf_pool.put(data);
+        rs
+    }
 
-        if err := b.writeBinlogDumpCommand(pos); err != nil {
-            return errors.Trace(err)
+    async fn _read_binlog_into(
+        &mut self,
+        data: &mut Vec<u8>,
+        mut on_event: impl FnMut(&BinlogEvent) -> bool,
+    ) -> Result<(), ReplicationError> {
+        loop {
+            self._conn()?.read_packet_into(data).await?;
+            if data.is_empty() {
+                return Err(ReplicationError::from(MysqlError::ErrMalformPacket));
+            }
+            match data[0] {
+                mysql::OK_HEADER => {
+                    let e = self._parser.parse(&data[1..])?;
+                    if !on_event(&e) {
+                        return Ok(());
+                    }
+                }
+                mysql::ERR_HEADER => return Err(self._conn()?.handle_error_packet(data)),
+                // no more binlog event
+                mysql::EOF_HEADER => return Ok(()),
+                _ => {
+                    return Err(ReplicationError::new(format!(
+                        "invalid stream header {}",
+                        data[0]
+                    )))
+                }
+            }
         }
-
-        return nil
     }
 
-    func (b *BinlogSyncer) prepareSyncGTID(gset GTIDSet) error {
-        var err error
+    // first_event_time dumps the binlog `name` and returns the time of the format description event starting it.
+    async fn _first_event_time(&mut self, name: &str) -> Result<u32, ReplicationError> {
+        self._dump_binlog(name).await?;
 
-        // re establishing network connection here and will start getting binlog events from "gset + 1", thus until first
-        // MariadbGTIDEvent/GTIDEvent event is received - we effectively do not have a "current GTID"
-        b.currGset = nil
+        let mut timestamp = None;
+        self._read_binlog(|e| match &e.event {
+            Some(EventEnum::FormatDescriptionEvent(_)) => {
+                timestamp = e.header.as_ref().map(|h| h.timestamp);
+                false
+            }
+            _ => true,
+        })
+        .await?;
 
-        if err = b.prepare(); err != nil {
-            return errors.Trace(err)
-        }
+        timestamp.ok_or(ReplicationError::new(format!(
+            "no format description event in binlog {}",
+            name
+        )))
+    }
 
-        switch b.cfg.Flavor {
-        case MariaDBFlavor:
-            err = b.writeBinlogDumpMariadbGTIDCommand(gset)
-        default:
-            // default use MySQL
-            err = b.writeBinlogDumpMysqlGTIDCommand(gset)
-        }
+    // scan_time goes on with the dump of the binlog `name` stopped by first_event_time, for the first transaction
+    // at or after t. Returns the binlog and the position of the transaction, or of the end of the binlogs if it's
+    // not found. Only the format description event was read, position 4 is before the same events as its end.
+    async fn _scan_time(
+        &mut self,
+        name: &str,
+        t: i64,
+    ) -> Result<(String, u32, bool), ReplicationError> {
+        let mut name = name.to_string();
+        let mut pos = 4_u32;
+        let mut found = false;
+        let mut in_transaction = false;
+        let mut in_begin = false;
+
+        self._read_binlog(|e| {
+            let h = match &e.header {
+                Some(h) => h,
+                None => return true,
+            };
+            let start = !in_transaction;
+            match &e.event {
+                Some(EventEnum::RotateEvent(event)) => {
+                    name = String::from_utf8_lossy(&event.next_log_name).to_string();
+                    pos = event.position as u32;
+                    return true;
+                }
+                Some(EventEnum::GTIDEvent(_)) => {
+                    in_transaction = true;
+                    in_begin = false;
+                }
+                Some(EventEnum::MariadbGTIDEvent(event)) => {
+                    in_transaction = true;
+                    in_begin = !event.is_standalone();
+                }
+                Some(EventEnum::QueryEvent(event)) if event.query == b"BEGIN" => {
+                    in_transaction = true;
+                    in_begin = true;
+                }
+                Some(EventEnum::QueryEvent(event))
+                    if in_begin && event.query != b"COMMIT" && event.query != b"ROLLBACK" => {}
+                Some(EventEnum::XIDEvent(_)) | Some(EventEnum::QueryEvent(_)) => {
+                    in_transaction = false;
+                    in_begin = false;
+                }
+                Some(EventEnum::RowsEvent(_)) | Some(EventEnum::TableMapEvent(_)) => {}
+                // format description, previous GTIDs and the like don't start a transaction
+                _ => {
+                    if h.log_pos > 0 {
+                        pos = h.log_pos;
+                    }
+                    return true;
+                }
+            }
 
-        if err != nil {
-            return err
+            if start && h.timestamp as i64 >= t {
+                pos = h.log_pos.saturating_sub(h.event_size);
+                found = true;
+                return false;
+            }
+            if h.log_pos > 0 {
+                pos = h.log_pos;
+            }
+            true
+        })
+        .await?;
+
+        Ok((name, pos, found))
+    }
+
+    // on_stream runs the dump until it ends or ctx is cancelled by Close.
+    async fn _on_stream(&mut self, mut ctx: tokio_context::context::Context, s: &BinlogStreamer) {
+        // the events are copied out of the packet by the parser, so all the packets are read into one buffer
+        let mut data = self._buf_pool.get();
+        tokio::select! {
+            _ = ctx.done() => {}
+            _ = self._read_stream(s, &mut data) => {}
         }
-        return nil
+        self._buf_pool.put(data);
     }
 
-    func (b *BinlogSyncer) onStream(s *BinlogStreamer) {
-        defer func() {
-            if e := recover(); e != nil {
-                s.closeWithError(fmt.Errorf("Err: %v\n Stack: %s", e, Pstack()))
+    async fn _read_stream(&mut self, s: &BinlogStreamer, data: &mut Vec<u8>) {
+        let tx = s.get_binlog_event_tx();
+
+        loop {
+            // don't hold on to the memory of a big event
+            if data.capacity() > packet::TOO_BIG_BLOCK_SIZE {
+                *data = self._buf_pool.get();
             }
-            b.wg.Done()
-        }()
 
-        for {
-            data, err := b.c.ReadPacket()
-            select {
-            case <-b.ctx.Done():
-                s.close()
-                return
-            default:
+            let rs = match self._conn() {
+                Ok(c) => c.read_packet_into(data).await,
+                Err(e) => Err(e),
+            };
+            if self._s.lock().unwrap()._closed {
+                // no event is delivered after Close
+                return;
             }
 
-            if err != nil {
-                b.cfg.Logger.Error(err)
+            if let Err(e) = rs {
+                log::error!("{}", e);
                 // we meet connection error, should re-connect again with
-                // last nextPos or nextGTID we got.
-                if len(b.nextPos.Name) == 0 && b.prevGset == nil {
-                    // we can't get the correct position, close.
-                    s.closeWithError(err)
-                    return
-                }
-
-                if b.cfg.DisableRetrySync {
-                    b.cfg.Logger.Warn("retry sync is disabled")
-                    s.closeWithError(err)
-                    return
-                }
-
-                for {
-                    select {
-                    case <-b.ctx.Done():
-                        s.close()
-                        return
-                    case <-time.After(time.Second):
-                        b.retryCount++
-                        if err = b.retrySync(); err != nil {
-                            if b.cfg.MaxReconnectAttempts > 0 && b.retryCount >= b.cfg.MaxReconnectAttempts {
-                                b.cfg.Logger.Errorf("retry sync err: %v, exceeded max retries (%d)", err, b.cfg.MaxReconnectAttempts)
-                                s.closeWithError(err)
-                                return
-                            }
-
-                            b.cfg.Logger.Errorf("retry sync err: %v, wait 1s and retry again", err)
-                            continue
-                        }
-                    }
-
-                    break
+                // last committed position or GTID set we got.
+                if !self._on_connection_error(s, e).await {
+                    return;
                 }
-
                 // we connect the server and begin to re-sync again.
-                continue
-            }
-
-            //set read timeout
-            if b.cfg.ReadTimeout > 0 {
-                _ = b.c.SetReadDeadline(time.Now().Add(b.cfg.ReadTimeout))
+                continue;
             }
 
             // Reset retry count on successful packet receieve
-            b.retryCount = 0
+            self._s.lock().unwrap()._retry_count = 0;
+
+            if data.is_empty() {
+                s.close_with_error(Err(ReplicationError::from(MysqlError::ErrMalformPacket)));
+                return;
+            }
 
-            switch data[0] {
-            case OK_HEADER:
-                if err = b.parseEvent(s, data); err != nil {
-                    s.closeWithError(err)
-                    return
+            match data[0] {
+                mysql::OK_HEADER => {
+                    if let Err(e) = self._parse_event(&tx, data).await {
+                        s.close_with_error(Err(e));
+                        return;
+                    }
+                }
+                mysql::ERR_HEADER => {
+                    let e = match self._conn() {
+                        Ok(c) => c.handle_error_packet(data),
+                        Err(e) => e,
+                    };
+                    s.close_with_error(Err(e));
+                    return;
+                }
+                mysql::EOF_HEADER => {
+                    // refer to https://dev.mysql.com/doc/internals/en/com-binlog-dump.html#binlog-dump-non-block
+                    // when COM_BINLOG_DUMP command use BINLOG_DUMP_NON_BLOCK flag,
+                    // if there is no more event to send an EOF_Packet instead of blocking the connection
+                    log::info!("receive EOF packet, no more binlog event now.");
+                    continue;
+                }
+                _ => {
+                    log::error!("invalid stream header {}", data[0]);
+                    continue;
                 }
-            case ERR_HEADER:
-                err = b.c.HandleErrorPacket(data)
-                s.closeWithError(err)
-                return
-            case EOF_HEADER:
-                // refer to https://dev.mysql.com/doc/internals/en/com-binlog-dump.html#binlog-dump-non-block
-                // when COM_BINLOG_DUMP command use BINLOG_DUMP_NON_BLOCK flag,
-                // if there is no more event to send an EOF_Packet instead of blocking the connection
-                b.cfg.Logger.Info("receive EOF packet, no more binlog event now.")
-                continue
-            default:
-                b.cfg.Logger.Errorf("invalid stream header %c", data[0])
-                continue
             }
         }
     }
 
-    func (b *BinlogSyncer) parseEvent(s *BinlogStreamer, data []byte) error {
+    async fn _parse_event(
+        &mut self,
+        tx: &async_channel::Sender<BinlogEvent>,
+        data: &[u8],
+    ) -> Result<(), ReplicationError> {
         //skip OK byte, 0x00
-        data = data[1:]
+        let mut data = &data[1..];
 
-        needACK := false
-        if b.cfg.SemiSyncEnabled && (data[0] == SemiSyncIndicator) {
-            needACK = data[1] == 0x01
+        let mut need_ack = false;
+        if self._semi_sync_enabled && data.first() == Some(&SEMI_SYNC_INDICATOR) {
+            // the semi-sync header is the indicator and the ACK flag
+            if data.len() < 2 {
+                return Err(ReplicationError::from(MysqlError::ErrMalformPacket));
+            }
+            need_ack = data[1] == 0x01;
             //skip semi sync header
-            data = data[2:]
+            data = &data[2..];
         }
 
-        e, err := b.parser.Parse(data)
-        if err != nil {
-            return errors.Trace(err)
-        }
+        let mut e = self._parser.parse(data)?;
 
-        if e.Header.LogPos > 0 {
-            // Some events like FormatDescriptionEvent return 0, ignore.
-            b.nextPos.Pos = e.Header.LogPos
-        }
-
-        getCurrentGtidSet := func() GTIDSet {
-            if b.currGset == nil {
-                return nil
+        {
+            let mut state = self._s.lock().unwrap();
+            let mut committed = false;
+            if let Some(h) = &e.header {
+                if h.log_pos > 0 {
+                    // Some events like FormatDescriptionEvent return 0, ignore.
+                    state._next_pos.pos = h.log_pos;
+                }
             }
-            return b.currGset.Clone()
-        }
 
-        switch event := e.Event.(type) {
-        case *RotateEvent:
-            b.nextPos.Name = string(event.NextLogName)
-            b.nextPos.Pos = uint32(event.Position)
-            b.cfg.Logger.Infof("rotate to %s", b.nextPos)
-        case *GTIDEvent:
-            if b.prevGset == nil {
-                break
-            }
-            if b.currGset == nil {
-                b.currGset = b.prevGset.Clone()
-            }
-            u, _ := uuid.FromBytes(event.SID)
-            b.currGset.(*MysqlGTIDSet).AddGTID(u, event.GNO)
-            if b.prevMySQLGTIDEvent != nil {
-                u, _ = uuid.FromBytes(b.prevMySQLGTIDEvent.SID)
-                b.prevGset.(*MysqlGTIDSet).AddGTID(u, b.prevMySQLGTIDEvent.GNO)
-            }
-            b.prevMySQLGTIDEvent = event
-        case *MariadbGTIDEvent:
-            if b.prevGset == nil {
-                break
-            }
-            if b.currGset == nil {
-                b.currGset = b.prevGset.Clone()
-            }
-            prev := b.currGset.Clone()
-            err = b.currGset.(*MariadbGTIDSet).AddSet(&event.GTID)
-            if err != nil {
-                return errors.Trace(err)
+            match &mut e.event {
+                Some(EventEnum::RotateEvent(event)) => {
+                    state._next_pos.name =
+                        String::from_utf8_lossy(&event.next_log_name).to_string();
+                    state._next_pos.pos = event.position as u32;
+                    state._in_transaction = false;
+                    state._in_begin = false;
+                    log::info!("rotate to {}", &state._next_pos);
+                }
+                Some(EventEnum::HeartbeatEvent(HeartbeatEvent { log_name, log_pos }))
+                | Some(EventEnum::HeartbeatEventV2(HeartbeatEventV2 { log_name, log_pos })) => {
+                    // an idle master still tells its position, the events before it were all sent
+                    if !log_name.is_empty() {
+                        state._next_pos.name = String::from_utf8_lossy(log_name).to_string();
+                    }
+                    // a position over 4GB doesn't fit in a Position, it's kept from the header then
+                    if let Ok(pos) = u32::try_from(*log_pos) {
+                        if pos > 0 {
+                            state._next_pos.pos = pos;
+                        }
+                    }
+                }
+                Some(EventEnum::GTIDEvent(event)) => {
+                    state._in_transaction = true;
+                    state._in_begin = false;
+                    BinlogDumper::_on_mysql_gtid_event(&mut state, event)?;
+                }
+                Some(EventEnum::MariadbGTIDEvent(event)) => {
+                    state._in_transaction = true;
+                    // the GTID event takes the place of BEGIN, a group ends with a COMMIT or XID unless it's standalone
+                    state._in_begin = !event.is_standalone();
+                    BinlogDumper::_on_mariadb_gtid_event(&mut state, event)?;
+                }
+                Some(EventEnum::QueryEvent(event)) if event.query == b"BEGIN" => {
+                    state._in_transaction = true;
+                    state._in_begin = true;
+                }
+                Some(EventEnum::QueryEvent(event))
+                    if state._in_begin
+                        && event.query != b"COMMIT"
+                        && event.query != b"ROLLBACK" =>
+                {
+                    // a statement of the transaction
+                }
+                Some(EventEnum::XIDEvent(XIDEvent { gset, .. }))
+                | Some(EventEnum::QueryEvent(QueryEvent { gset, .. })) => {
+                    // XID, COMMIT or a DDL query ends the transaction
+                    state._in_transaction = false;
+                    state._in_begin = false;
+                    committed = true;
+                    BinlogDumper::_on_transaction_committed(&mut state)?;
+                    if !self._cfg.discard_gtid_set {
+                        *gset = state._curr_gset.clone();
+                    }
+                }
+                _ => {}
             }
-            // right after reconnect we will see same gtid as we saw before, thus currGset will not get changed
-            if !b.currGset.Equal(prev) {
-                b.prevGset = prev
+
+            if !state._in_transaction {
+                state._committed_pos = state._next_pos.clone();
             }
-        case *XIDEvent:
-            if !b.cfg.DiscardGTIDSet {
-                event.GSet = getCurrentGtidSet()
+
+            if committed && self._cfg.checkpoint_store.is_some() {
+                e.checkpoint = Some(Checkpoint {
+                    position: state._committed_pos.clone(),
+                    gset: state._prev_gset.clone(),
+                });
             }
-        case *QueryEvent:
-            if !b.cfg.DiscardGTIDSet {
-                event.GSet = getCurrentGtidSet()
+
+            if need_ack {
+                e.semi_sync_ack_pos = Some(state._next_pos.clone());
             }
         }
 
-        needStop := false
-        select {
-        case s.ch <- e:
-        case <-b.ctx.Done():
-            needStop = true
+        // the consumer still has to reply the ACK of a filtered event, and to save the checkpoint of a filtered
+        // DDL, or the checkpoint doesn't move while only the filtered out databases change
+        if e.filtered && !need_ack && e.checkpoint.is_none() {
+            return Ok(());
         }
 
-        if needACK {
-            err := b.replySemiSyncACK(b.nextPos)
-            if err != nil {
-                return errors.Trace(err)
-            }
+        tx.send(e)
+            .await
+            .map_err(|e| ReplicationError::new(e.to_string()))?;
+
+        Ok(())
+    }
+
+    fn _on_mysql_gtid_event(
+        state: &mut SyncerState,
+        event: &GTIDEvent,
+    ) -> Result<(), ReplicationError> {
+        if state._prev_gset.is_none() || event.gno <= 0 {
+            return Ok(());
         }
 
-        if needStop {
-            return errors.New("sync is been closing...")
+        if state._curr_gset.is_none() {
+            state._curr_gset = state._prev_gset.clone();
+        }
+        if let Some(curr_gset) = state._curr_gset.as_mut() {
+            BinlogDumper::_add_mysql_gtid(curr_gset, event)?;
         }
 
-        return nil
-    }
+        // the previous transaction was not closed by a XID or query event, it is complete anyway
+        if let Some(prev) = state._prev_mysql_gtid_event.take() {
+            if let Some(prev_gset) = state._prev_gset.as_mut() {
+                BinlogDumper::_add_mysql_gtid(prev_gset, &prev)?;
+            }
+        }
+        state._prev_mysql_gtid_event = Some(event.clone());
 
-    // LastConnectionID returns last connectionID.
-    func (b *BinlogSyncer) LastConnectionID() uint32 {
-        return b.lastConnectionID
+        Ok(())
     }
 
-    func (b *BinlogSyncer) newConnection(ctx context.Context) (*client.Conn, error) {
-        var addr string
-        if b.cfg.Port != 0 {
-            addr = net.JoinHostPort(b.cfg.Host, strconv.Itoa(int(b.cfg.Port)))
-        } else {
-            addr = b.cfg.Host
+    fn _on_mariadb_gtid_event(
+        state: &mut SyncerState,
+        event: &MariadbGTIDEvent,
+    ) -> Result<(), ReplicationError> {
+        if state._prev_gset.is_none() {
+            return Ok(());
         }
 
-        timeoutCtx, cancel := context.WithTimeout(ctx, time.Second*10)
-        defer cancel()
+        if state._curr_gset.is_none() {
+            state._curr_gset = state._prev_gset.clone();
+        }
+        if let Some(GtidSetEnum::MariadbGTIDSet(curr_gset)) = state._curr_gset.as_mut() {
+            let prev = curr_gset.clone();
+            curr_gset.add_set(&event.gtid)?;
+            // right after reconnect we will see same gtid as we saw before, thus currGset will not get changed
+            if !curr_gset.equal(&prev) {
+                state._prev_gset = Some(GtidSetEnum::MariadbGTIDSet(prev));
+            }
+        }
 
-        return client.ConnectWithDialer(timeoutCtx, "", addr, b.cfg.User, b.cfg.Password,
-            "", b.cfg.Dialer, func(c *client.Conn) {
-                c.SetTLSConfig(b.cfg.TLSConfig)
-                c.SetAttributes(map[string]string{"_client_role": "binary_log_listener"})
-            })
+        Ok(())
     }
 
-    func (b *BinlogSyncer) killConnection(conn *client.Conn, id uint32) {
-        cmd := fmt.Sprintf("KILL %d", id)
-        if _, err := conn.Execute(cmd); err != nil {
-            b.cfg.Logger.Errorf("kill connection %d error %v", id, err)
-            // Unknown thread id
-            if code := ErrorCode(err.Error()); code != ER_NO_SUCH_THREAD {
-                b.cfg.Logger.Error(errors.Trace(err))
+    // on_transaction_committed moves prev_gset forward, a retry will not send the committed transaction again.
+    fn _on_transaction_committed(state: &mut SyncerState) -> Result<(), ReplicationError> {
+        let prev = state._prev_mysql_gtid_event.take();
+        match state._prev_gset.as_mut() {
+            // prev_gset only lacks the GTID of the committed transaction, adding it is cheaper than cloning curr_gset
+            Some(prev_gset @ GtidSetEnum::MysqlGTIDSet(_)) => {
+                if let Some(prev) = prev {
+                    BinlogDumper::_add_mysql_gtid(prev_gset, &prev)?;
+                }
+            }
+            Some(prev_gset) => {
+                if let Some(curr_gset) = &state._curr_gset {
+                    *prev_gset = curr_gset.clone();
+                }
             }
+            None => {}
         }
-        b.cfg.Logger.Infof("kill last connection id %d", id)
+
+        Ok(())
+    }
+
+    fn _add_mysql_gtid(gset: &mut GtidSetEnum, event: &GTIDEvent) -> Result<(), ReplicationError> {
+        if let GtidSetEnum::MysqlGTIDSet(s) = gset {
+            let u = uuid::Uuid::from_slice(&event.sid)?;
+            s.add_gtid(&u, event.gno);
+        }
+
+        Ok(())
     }
 
-     */
+    async fn _new_connection(&self) -> Result<AsyncConn, ReplicationError> {
+        // a host with a / is the path of a unix socket, the port is ignored
+        let addr = if self._cfg.port != 0 && !self._cfg.host.contains('/') {
+            if self._cfg.host.contains(':') {
+                format!("[{}]:{}", self._cfg.host, self._cfg.port)
+            } else {
+                format!("{}:{}", self._cfg.host, self._cfg.port)
+            }
+        } else {
+            self._cfg.host.clone()
+        };
+
+        let tls_config = self._cfg.tls_config.clone();
+        let compression = self._cfg.compression;
+        let recv_buffer_size = self._cfg.recv_buffer_size;
+        let read_timeout = self._cfg.read_timeout;
+        let options: Vec<Box<client::AsyncConnOption>> =
+            vec![Box::new(move |c: &mut AsyncConn| {
+                if let Some(config) = &tls_config {
+                    c.set_tls_config(config.clone());
+                }
+                // set before the handshake, so the buffer is in place when the dump starts
+                if recv_buffer_size > 0 {
+                    if let Err(e) = c.set_recv_buffer_size(recv_buffer_size) {
+                        log::warn!("failed to set recv buffer size {}: {}", recv_buffer_size, e);
+                    }
+                }
+                if let Err(e) = c.set_read_timeout(Some(read_timeout)) {
+                    log::warn!("failed to set read timeout {:?}: {}", read_timeout, e);
+                }
+                match compression {
+                    mysql::MYSQL_COMPRESS_ZLIB => c.set_capability(mysql::CLIENT_COMPRESS),
+                    mysql::MYSQL_COMPRESS_ZSTD => {
+                        c.set_capability(mysql::CLIENT_ZSTD_COMPRESSION_ALGORITHM)
+                    }
+                    _ => {}
+                }
+                c.set_attributes(HashMap::from([(
+                    "_client_role".to_string(),
+                    "binary_log_listener".to_string(),
+                )]))
+            })];
+
+        match &self._cfg.dialer {
+            Some(dialer) => {
+                client::connect_async_with_dialer(
+                    &addr,
+                    &self._cfg.user,
+                    &self._cfg.password,
+                    "",
+                    dialer.clone(),
+                    &options,
+                )
+                .await
+            }
+            None => {
+                client::connect_async(&addr, &self._cfg.user, &self._cfg.password, "", &options)
+                    .await
+            }
+        }
+    }
 }
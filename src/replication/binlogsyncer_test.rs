@@ -0,0 +1,1530 @@
+#[cfg(test)]
+mod tests {
+    use crate::error::{MysqlError, ReplicationError};
+    use crate::mysql;
+    use crate::mysql::Position;
+    use crate::replication::{
+        BinlogEvent, BinlogStreamer, BinlogSyncer, BinlogSyncerConfig, Checkpoint, CheckpointStore,
+        EventEnum, EventType, MemoryCheckpointStore, ReplicationFilter, RewriteRules,
+        BINLOG_DUMP_NON_BLOCK, BINLOG_FILE_HEADER, ERR_SYNC_CLOSED, EVENT_HEADER_SIZE,
+        LOG_EVENT_ARTIFICIAL_F, SEMI_SYNC_INDICATOR,
+    };
+    use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+    use futures_core::Stream;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::os::unix::net::UnixListener;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    const BINLOG_NAME: &str = "mysql-bin.000001";
+    const SERVER_ID: u32 = 1;
+    const CONNECTION_ID: u32 = 42;
+    const USER: &str = "repl";
+    const PASSWORD: &str = "secret";
+
+    // FakeMaster speaks just enough of the MySQL protocol to serve one binlog file to a replica.
+    struct FakeMaster {
+        port: u16,
+        commands: Arc<Mutex<Vec<Vec<u8>>>>,
+    }
+
+    #[derive(Default)]
+    struct FakeMasterOptions {
+        // the first dump is cut off after sending this many events of the binlog file
+        drop_after: Option<usize>,
+        // refuse every connection after the first dump was cut off
+        deny_reconnect: bool,
+        // rpl_semi_sync_master_enabled is ON and XID events ask for a semi-sync ACK
+        semi_sync: bool,
+        // the first dump ends with a HeartbeatLogEventV2 at this position, then the connection is cut off
+        heartbeat_pos: Option<u64>,
+        // the binlogs after BINLOG_NAME, SHOW BINARY LOGS lists them
+        binlog_files: Vec<(String, std::path::PathBuf)>,
+        // the dumps end with this raw packet after the events of the binlog file
+        last_packet: Option<Vec<u8>>,
+    }
+
+    struct FakeMasterState {
+        binlog_file: std::path::PathBuf,
+        options: FakeMasterOptions,
+        commands: Arc<Mutex<Vec<Vec<u8>>>>,
+        connection_id: AtomicU32,
+        dumps: AtomicUsize,
+    }
+
+    impl FakeMaster {
+        fn start(binlog_file: std::path::PathBuf) -> FakeMaster {
+            FakeMaster::start_with_options(binlog_file, FakeMasterOptions::default())
+        }
+
+        fn start_with_options(
+            binlog_file: std::path::PathBuf,
+            options: FakeMasterOptions,
+        ) -> FakeMaster {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let state = FakeMaster::new_state(binlog_file, options);
+            let commands = state.commands.clone();
+
+            thread::spawn(move || {
+                for conn in listener.incoming() {
+                    let conn = conn.unwrap();
+                    let state = state.clone();
+                    thread::spawn(move || serve(conn, &state));
+                }
+            });
+
+            FakeMaster { port, commands }
+        }
+
+        // start_unix serves the binlog file on the unix socket at `socket`, the port of the master is 0.
+        fn start_unix(binlog_file: std::path::PathBuf, socket: &std::path::Path) -> FakeMaster {
+            let listener = UnixListener::bind(socket).unwrap();
+            let state = FakeMaster::new_state(binlog_file, FakeMasterOptions::default());
+            let commands = state.commands.clone();
+
+            thread::spawn(move || {
+                for conn in listener.incoming() {
+                    let conn = conn.unwrap();
+                    let state = state.clone();
+                    thread::spawn(move || serve(conn, &state));
+                }
+            });
+
+            FakeMaster { port: 0, commands }
+        }
+
+        fn new_state(
+            binlog_file: std::path::PathBuf,
+            options: FakeMasterOptions,
+        ) -> Arc<FakeMasterState> {
+            Arc::new(FakeMasterState {
+                binlog_file,
+                options,
+                commands: Arc::new(Mutex::new(Vec::<Vec<u8>>::new())),
+                connection_id: AtomicU32::new(CONNECTION_ID),
+                dumps: AtomicUsize::new(0),
+            })
+        }
+
+        fn commands(&self, command: u8) -> Vec<Vec<u8>> {
+            self.commands
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|data| data[0] == command)
+                .map(|data| data[1..].to_vec())
+                .collect()
+        }
+    }
+
+    fn write_packet(conn: &mut impl Write, sequence: u8, payload: &[u8]) {
+        let mut data = vec![0_u8; 4];
+        LittleEndian::write_u24(&mut data, payload.len() as u32);
+        data[3] = sequence;
+        data.extend(payload);
+        conn.write_all(&data).unwrap();
+    }
+
+    fn read_packet(conn: &mut impl Read) -> Option<Vec<u8>> {
+        let mut header = [0_u8; 4];
+        conn.read_exact(&mut header).ok()?;
+        let mut data = vec![0_u8; LittleEndian::read_u24(&header) as usize];
+        conn.read_exact(&mut data).ok()?;
+        Some(data)
+    }
+
+    fn write_ok(conn: &mut impl Write, sequence: u8) {
+        write_packet(conn, sequence, &[mysql::OK_HEADER, 0, 0, 0x02, 0, 0, 0]);
+    }
+
+    fn write_eof(conn: &mut impl Write, sequence: u8) {
+        write_packet(conn, sequence, &[mysql::EOF_HEADER, 0, 0, 0x02, 0]);
+    }
+
+    fn column_definition(name: &str) -> Vec<u8> {
+        let mut data = vec![];
+        for s in ["def", "", "", "", name, name] {
+            data.extend(mysql::put_length_encoded_string(s.as_bytes()));
+        }
+        data.push(0x0c);
+        data.write_u16::<LittleEndian>(mysql::DEFAULT_COLLATION_ID as u16)
+            .unwrap();
+        data.write_u32::<LittleEndian>(1024).unwrap();
+        data.push(mysql::MYSQL_TYPE_VAR_STRING);
+        data.write_u16::<LittleEndian>(0).unwrap();
+        data.push(0);
+        data.extend([0_u8; 2]);
+        data
+    }
+
+    fn write_result_set(conn: &mut impl Write, names: &[&str], rows: &[Vec<&str>]) {
+        let mut sequence = 1_u8;
+        write_packet(conn, sequence, &[names.len() as u8]);
+        for name in names {
+            sequence += 1;
+            write_packet(conn, sequence, &column_definition(name));
+        }
+        sequence += 1;
+        write_eof(conn, sequence);
+        for row in rows {
+            sequence += 1;
+            let mut data = vec![];
+            for value in row {
+                data.extend(mysql::put_length_encoded_string(value.as_bytes()));
+            }
+            write_packet(conn, sequence, &data);
+        }
+        sequence += 1;
+        write_eof(conn, sequence);
+    }
+
+    fn handshake(conn: &mut (impl Read + Write), connection_id: u32, deny: bool) -> bool {
+        let salt = b"0123456789abcdefghij";
+        let capability = mysql::CLIENT_LONG_PASSWORD
+            | mysql::CLIENT_PROTOCOL_41
+            | mysql::CLIENT_SECURE_CONNECTION
+            | mysql::CLIENT_TRANSACTIONS
+            | mysql::CLIENT_PLUGIN_AUTH;
+
+        let mut data = vec![mysql::MIN_PPOTOCOL_VERSION];
+        data.extend(b"5.7.30-log\0");
+        data.write_u32::<LittleEndian>(connection_id).unwrap();
+        data.extend(&salt[..8]);
+        data.push(0);
+        data.write_u16::<LittleEndian>(capability as u16).unwrap();
+        data.push(mysql::DEFAULT_COLLATION_ID);
+        data.write_u16::<LittleEndian>(mysql::SERVER_STATUS_AUTOCOMMIT)
+            .unwrap();
+        data.write_u16::<LittleEndian>((capability >> 16) as u16)
+            .unwrap();
+        data.push(21);
+        data.extend([0_u8; 10]);
+        data.extend(&salt[8..]);
+        data.push(0);
+        data.extend(mysql::AUTH_NATIVE_PASSWORD.as_bytes());
+        data.push(0);
+        write_packet(conn, 0, &data);
+
+        // HandshakeResponse41: capability, max packet size, charset, filler, user
+        let data = read_packet(conn).unwrap();
+        let mut pos = 4 + 4 + 1 + 23;
+        let user_end = pos + data[pos..].iter().position(|&b| b == 0).unwrap();
+        let user = String::from_utf8_lossy(&data[pos..user_end]).to_string();
+        pos = user_end + 1;
+        let auth_len = data[pos] as usize;
+        let auth = &data[pos + 1..pos + 1 + auth_len];
+
+        if deny || user != USER || auth != mysql::calc_password(salt, PASSWORD.as_bytes()) {
+            write_packet(conn, 2, b"\xff\x15\x04#28000Access denied for user 'repl'");
+            return false;
+        }
+
+        write_ok(conn, 2);
+        true
+    }
+
+    fn serve(mut conn: impl Read + Write, state: &FakeMasterState) {
+        let connection_id = state.connection_id.fetch_add(1, Ordering::SeqCst);
+        let deny = state.options.deny_reconnect && state.dumps.load(Ordering::SeqCst) > 0;
+        if !handshake(&mut conn, connection_id, deny) {
+            return;
+        }
+
+        while let Some(data) = read_packet(&mut conn) {
+            state.commands.lock().unwrap().push(data.clone());
+
+            match data[0] {
+                mysql::COM_QUERY => {
+                    let query = String::from_utf8_lossy(&data[1..]).to_string();
+                    if query == "SHOW GLOBAL VARIABLES LIKE 'BINLOG_CHECKSUM'" {
+                        write_result_set(
+                            &mut conn,
+                            &["Variable_name", "Value"],
+                            &[vec!["binlog_checksum", "CRC32"]],
+                        );
+                    } else if query == "SHOW BINARY LOGS" {
+                        let mut names = vec![BINLOG_NAME];
+                        names.extend(state.options.binlog_files.iter().map(|(n, _)| n.as_str()));
+                        let rows: Vec<Vec<&str>> =
+                            names.into_iter().map(|n| vec![n, "0"]).collect();
+                        write_result_set(&mut conn, &["Log_name", "File_size"], &rows);
+                    } else if query == "SHOW VARIABLES LIKE 'rpl_semi_sync_master_enabled';" {
+                        let value = if state.options.semi_sync { "ON" } else { "OFF" };
+                        write_result_set(
+                            &mut conn,
+                            &["Variable_name", "Value"],
+                            &[vec!["rpl_semi_sync_master_enabled", value]],
+                        );
+                    } else {
+                        write_ok(&mut conn, 1);
+                    }
+                }
+                mysql::COM_REGISTER_SLAVE => write_ok(&mut conn, 1),
+                mysql::COM_BINLOG_DUMP | mysql::COM_BINLOG_DUMP_GTID => {
+                    let (limit, heartbeat_pos) = match state.dumps.fetch_add(1, Ordering::SeqCst) {
+                        0 => (state.options.drop_after, state.options.heartbeat_pos),
+                        _ => (None, None),
+                    };
+                    if !dump(&mut conn, state, &data, limit, heartbeat_pos) {
+                        // cut off the connection like a network failure
+                        return;
+                    }
+                    // keep the connection open until the replica goes away, semi-sync ACKs may still come
+                    while let Some(data) = read_packet(&mut conn) {
+                        state.commands.lock().unwrap().push(data);
+                    }
+                    return;
+                }
+                _ => write_ok(&mut conn, 1),
+            }
+        }
+    }
+
+    // dump returns false if it stopped after `limit` events of the binlog file or after the heartbeat.
+    fn dump(
+        conn: &mut (impl Read + Write),
+        state: &FakeMasterState,
+        data: &[u8],
+        limit: Option<usize>,
+        heartbeat_pos: Option<u64>,
+    ) -> bool {
+        let (pos, flags, name) = if data[0] == mysql::COM_BINLOG_DUMP_GTID {
+            // flags, server id, name length, name, position, gtid data length, gtid data
+            let name_len = LittleEndian::read_u32(&data[7..]) as usize;
+            let name = String::from_utf8_lossy(&data[11..11 + name_len]).to_string();
+            let pos = LittleEndian::read_u64(&data[11 + name_len..]) as usize;
+            (pos, LittleEndian::read_u16(&data[1..]), name)
+        } else {
+            let pos = LittleEndian::read_u32(&data[1..]) as usize;
+            let name = String::from_utf8_lossy(&data[11..]).to_string();
+            (pos, LittleEndian::read_u16(&data[5..]), name)
+        };
+        let path = state
+            .options
+            .binlog_files
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map_or(&state.binlog_file, |(_, p)| p);
+        let file = std::fs::read(path).unwrap();
+        let semi_sync = state.options.semi_sync;
+        let events = split_events(&file);
+
+        // a fake rotate event always comes first, then the format description event
+        let mut body = vec![];
+        body.write_u64::<LittleEndian>(pos as u64).unwrap();
+        body.extend(name.as_bytes());
+        let mut sequence = 1_u8;
+        let mut rotate = build_event(EventType::RotateEvent, 0, &body);
+        LittleEndian::write_u16(&mut rotate[17..], LOG_EVENT_ARTIFICIAL_F);
+        write_event(conn, &mut sequence, &rotate, semi_sync);
+
+        if pos > 4 {
+            let mut fde = events[0].1.clone();
+            LittleEndian::write_u32(&mut fde[13..], 0);
+            write_event(conn, &mut sequence, &fde, semi_sync);
+        }
+
+        let mut sent = 0;
+        for (offset, event) in &events {
+            if *offset >= pos {
+                if Some(sent) == limit {
+                    return false;
+                }
+                write_event(conn, &mut sequence, event, semi_sync);
+                sent += 1;
+            }
+        }
+
+        if let Some(packet) = &state.options.last_packet {
+            write_packet(conn, sequence, packet);
+            sequence = sequence.wrapping_add(1);
+        }
+
+        if let Some(heartbeat_pos) = heartbeat_pos {
+            // log file name and log position fields, then the end mark
+            let mut body = vec![0x01, BINLOG_NAME.len() as u8];
+            body.extend(BINLOG_NAME.as_bytes());
+            body.extend([0x02, 0x09, 0xfe]);
+            body.write_u64::<LittleEndian>(heartbeat_pos).unwrap();
+            body.push(0x00);
+            let heartbeat = build_event(EventType::HeartbeatLogEventV2, 0, &body);
+            write_event(conn, &mut sequence, &heartbeat, semi_sync);
+            return false;
+        }
+
+        if flags & BINLOG_DUMP_NON_BLOCK > 0 {
+            write_eof(conn, sequence);
+        }
+        true
+    }
+
+    fn write_event(conn: &mut impl Write, sequence: &mut u8, event: &[u8], semi_sync: bool) {
+        let mut data = vec![mysql::OK_HEADER];
+        if semi_sync {
+            let need_ack = event[4] == EventType::XidEvent as u8;
+            data.extend([SEMI_SYNC_INDICATOR, need_ack as u8]);
+        }
+        data.extend(event);
+        write_packet(conn, *sequence, &data);
+        *sequence = sequence.wrapping_add(1);
+    }
+
+    fn split_events(file: &[u8]) -> Vec<(usize, Vec<u8>)> {
+        let mut events = vec![];
+        let mut offset = BINLOG_FILE_HEADER.len();
+        while offset < file.len() {
+            let size = LittleEndian::read_u32(&file[offset + 9..]) as usize;
+            events.push((offset, file[offset..offset + size].to_vec()));
+            offset += size;
+        }
+        events
+    }
+
+    fn build_event(event_type: EventType, log_pos: u32, body: &[u8]) -> Vec<u8> {
+        let mut data = vec![];
+        data.write_u32::<LittleEndian>(1700000000).unwrap();
+        data.push(event_type as u8);
+        data.write_u32::<LittleEndian>(SERVER_ID).unwrap();
+        data.write_u32::<LittleEndian>((EVENT_HEADER_SIZE + body.len()) as u32)
+            .unwrap();
+        data.write_u32::<LittleEndian>(log_pos).unwrap();
+        data.write_u16::<LittleEndian>(0).unwrap();
+        data.extend(body);
+        data
+    }
+
+    fn query_body(schema: &str, query: &str) -> Vec<u8> {
+        let mut body = vec![];
+        body.write_u32::<LittleEndian>(CONNECTION_ID).unwrap();
+        body.write_u32::<LittleEndian>(0).unwrap();
+        body.push(schema.len() as u8);
+        body.write_u16::<LittleEndian>(0).unwrap();
+        body.write_u16::<LittleEndian>(0).unwrap();
+        body.extend(schema.as_bytes());
+        body.push(0);
+        body.extend(query.as_bytes());
+        body
+    }
+
+    fn format_description_body() -> Vec<u8> {
+        let mut body = vec![];
+        body.write_u16::<LittleEndian>(4).unwrap();
+        let mut server_version = b"5.7.30-log".to_vec();
+        server_version.resize(50, 0);
+        body.extend(server_version);
+        body.write_u32::<LittleEndian>(0).unwrap();
+        body.push(EVENT_HEADER_SIZE as u8);
+        body.extend([
+            0x38, 0xd, 0x0, 0x8, 0x0, 0x12, 0x0, 0x4, 0x4, 0x4, 0x4, 0x12, 0x0, 0x0, 0x5c, 0x0,
+            0x4, 0x1a, 0x8, 0x0, 0x0, 0x0, 0x8, 0x8, 0x8, 0x2, 0x0, 0x0, 0x0, 0xa, 0xa, 0xa, 0x19,
+            0x19, 0x0, 0x12, 0x34, 0x0, 0xa, 0x28, 0x0,
+        ]);
+        // checksum algorithm OFF, followed by the (unused) checksum
+        body.extend([0_u8; 5]);
+        body
+    }
+
+    // write_binlog_file writes FDE, BEGIN, INSERT, XID and returns the path and the event offsets.
+    fn write_binlog_file(tag: &str) -> (std::path::PathBuf, Vec<u32>) {
+        let mut bodies = vec![(EventType::FormatDescriptionEvent, format_description_body())];
+        bodies.extend(transaction_bodies(7));
+        write_binlog_events(tag, bodies)
+    }
+
+    // write_gtid_binlog_file writes FDE and a GTID, BEGIN, INSERT, XID transaction for every gno.
+    fn write_gtid_binlog_file(
+        tag: &str,
+        sid: &uuid::Uuid,
+        gnos: &[i64],
+    ) -> (std::path::PathBuf, Vec<u32>) {
+        let mut bodies = vec![(EventType::FormatDescriptionEvent, format_description_body())];
+        for gno in gnos {
+            let mut gtid = vec![1_u8];
+            gtid.extend(sid.as_bytes());
+            gtid.write_i64::<LittleEndian>(*gno).unwrap();
+            bodies.push((EventType::GtidEvent, gtid));
+            bodies.extend(transaction_bodies(*gno as u64));
+        }
+        write_binlog_events(tag, bodies)
+    }
+
+    // write_timed_binlog_file writes FDE and a BEGIN, INSERT, XID transaction for every timestamp,
+    // the FDE has the first timestamp.
+    fn write_timed_binlog_file(tag: &str, timestamps: &[u32]) -> (std::path::PathBuf, Vec<u32>) {
+        let mut bodies = vec![(EventType::FormatDescriptionEvent, format_description_body())];
+        let mut event_timestamps = vec![timestamps[0]];
+        for (i, timestamp) in timestamps.iter().enumerate() {
+            bodies.extend(transaction_bodies(i as u64 + 1));
+            event_timestamps.extend([*timestamp; 3]);
+        }
+
+        let (path, offsets) = write_binlog_events(tag, bodies);
+        let mut file = std::fs::read(&path).unwrap();
+        for (offset, timestamp) in offsets.iter().zip(event_timestamps) {
+            LittleEndian::write_u32(&mut file[*offset as usize..], timestamp);
+        }
+        std::fs::write(&path, &file).unwrap();
+        (path, offsets)
+    }
+
+    fn transaction_bodies(xid: u64) -> Vec<(EventType, Vec<u8>)> {
+        let mut body = vec![];
+        body.write_u64::<LittleEndian>(xid).unwrap();
+        vec![
+            (EventType::QueryEvent, query_body("test", "BEGIN")),
+            (
+                EventType::QueryEvent,
+                query_body("test", "INSERT INTO t VALUES (1)"),
+            ),
+            (EventType::XidEvent, body),
+        ]
+    }
+
+    fn write_binlog_events(
+        tag: &str,
+        bodies: Vec<(EventType, Vec<u8>)>,
+    ) -> (std::path::PathBuf, Vec<u32>) {
+        let mut file = BINLOG_FILE_HEADER.to_vec();
+        let mut offsets = vec![];
+        for (event_type, body) in bodies {
+            offsets.push(file.len() as u32);
+            let log_pos = (file.len() + EVENT_HEADER_SIZE + body.len()) as u32;
+            file.extend(build_event(event_type, log_pos, &body));
+        }
+
+        let path = std::env::temp_dir().join(format!(
+            "binlogsyncer_test_{}_{}.000001",
+            std::process::id(),
+            tag
+        ));
+        std::fs::write(&path, &file).unwrap();
+        (path, offsets)
+    }
+
+    fn new_config(port: u16) -> BinlogSyncerConfig {
+        BinlogSyncerConfig {
+            server_id: 100,
+            flavor: mysql::MYSQL_FLAVOR.to_string(),
+            host: "127.0.0.1".to_string(),
+            port,
+            user: USER.to_string(),
+            password: PASSWORD.to_string(),
+            localhost: "replica-1".to_string(),
+            charset: String::new(),
+            semi_sync_enabled: false,
+            raw_mode_enabled: false,
+            tls_config: None,
+            compression: mysql::MYSQL_COMPRESS_NONE,
+            parse_time: false,
+            timestamp_string_location: None,
+            use_decimal: false,
+            lazy_rows_decoding: false,
+            recv_buffer_size: 0,
+            heartbeat_period: Default::default(),
+            read_timeout: Default::default(),
+            max_reconnect_attempts: 0,
+            disable_retry_sync: false,
+            verify_checksum: false,
+            dump_command_flag: BINLOG_DUMP_NON_BLOCK,
+            option: None,
+            dialer: None,
+            rows_event_decode_func: None,
+            discard_gtid_set: false,
+            checkpoint_store: None,
+            filter: ReplicationFilter::new(),
+            rewrite_rules: RewriteRules::new(),
+        }
+    }
+
+    // the events are read through the Stream impl, it returns the events sent before a sync error first,
+    // GetEvent may return the error while events are left
+    async fn get_events(s: &mut BinlogStreamer, n: usize) -> Vec<BinlogEvent> {
+        let mut events = vec![];
+        while events.len() < n {
+            let ev = tokio::time::timeout(
+                std::time::Duration::from_secs(5),
+                std::future::poll_fn(|cx| Pin::new(&mut *s).poll_next(cx)),
+            )
+            .await
+            .expect("timed out waiting for binlog event")
+            .unwrap()
+            .unwrap();
+            events.push(ev);
+        }
+        events
+    }
+
+    fn queries(master: &FakeMaster) -> Vec<String> {
+        master
+            .commands(mysql::COM_QUERY)
+            .iter()
+            .map(|q| String::from_utf8_lossy(q).to_string())
+            .collect()
+    }
+
+    fn query(ev: &BinlogEvent) -> String {
+        match &ev.event {
+            Some(EventEnum::QueryEvent(e)) => String::from_utf8_lossy(&e.query).to_string(),
+            _ => panic!("not a query event: {:?}", ev.header),
+        }
+    }
+
+    #[test]
+    fn test_new_binlog_syncer_with_zero_server_id() {
+        let mut cfg = new_config(3306);
+        cfg.server_id = 0;
+        assert!(BinlogSyncer::new(cfg).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_start_sync() -> Result<(), ReplicationError> {
+        let (path, offsets) = write_binlog_file("start_sync");
+        let master = FakeMaster::start(path.clone());
+
+        let mut b = BinlogSyncer::new(new_config(master.port))?;
+        let mut s = b
+            .start_sync(Position {
+                name: BINLOG_NAME.to_string(),
+                pos: 4,
+            })
+            .await?;
+
+        let events = get_events(&mut s, 5).await;
+        match &events[0].event {
+            Some(EventEnum::RotateEvent(e)) => {
+                assert_eq!(e.next_log_name, BINLOG_NAME.as_bytes());
+                assert_eq!(e.position, 4);
+            }
+            _ => panic!("first event must be a fake rotate event"),
+        }
+        assert_eq!(
+            events[1].header.as_ref().unwrap().event_type,
+            EventType::FormatDescriptionEvent
+        );
+        assert_eq!(query(&events[2]), "BEGIN");
+        assert_eq!(query(&events[3]), "INSERT INTO t VALUES (1)");
+        match &events[4].event {
+            Some(EventEnum::XIDEvent(e)) => assert_eq!(e.xid, 7),
+            _ => panic!("last event must be a xid event"),
+        }
+        assert_eq!(b.last_connection_id(), CONNECTION_ID);
+
+        // COM_REGISTER_SLAVE: server id, hostname, user, password, port
+        let register = master.commands(mysql::COM_REGISTER_SLAVE);
+        assert_eq!(register.len(), 1);
+        assert_eq!(LittleEndian::read_u32(&register[0]), 100);
+        assert_eq!(register[0][4] as usize, "replica-1".len());
+        assert_eq!(&register[0][5..14], b"replica-1");
+
+        // COM_BINLOG_DUMP: position, flags, server id, file name
+        let dump = master.commands(mysql::COM_BINLOG_DUMP);
+        assert_eq!(dump.len(), 1);
+        assert_eq!(LittleEndian::read_u32(&dump[0]), 4);
+        assert_eq!(LittleEndian::read_u16(&dump[0][4..]), BINLOG_DUMP_NON_BLOCK);
+        assert_eq!(LittleEndian::read_u32(&dump[0][6..]), 100);
+        assert_eq!(&dump[0][10..], BINLOG_NAME.as_bytes());
+
+        let queries = queries(&master);
+        assert!(queries.contains(&"SET @master_binlog_checksum='NONE'".to_string()));
+        assert!(!queries.contains(&"SET @mariadb_slave_capability=4".to_string()));
+
+        // only one sync can run at a time
+        assert!(b
+            .start_sync(Position {
+                name: BINLOG_NAME.to_string(),
+                pos: offsets[2],
+            })
+            .await
+            .is_err());
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_close() -> Result<(), ReplicationError> {
+        let (path, _) = write_binlog_file("close");
+        let master = FakeMaster::start(path.clone());
+
+        let mut b = BinlogSyncer::new(new_config(master.port))?;
+        let mut s = b
+            .start_sync(Position {
+                name: BINLOG_NAME.to_string(),
+                pos: 4,
+            })
+            .await?;
+
+        let events = get_events(&mut s, 2).await;
+        assert_eq!(
+            events[1].header.as_ref().unwrap().event_type,
+            EventType::FormatDescriptionEvent
+        );
+        // let the dump thread send the rest and block reading after the EOF packet
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+        // the events not received yet are returned by close
+        let events = tokio::time::timeout(std::time::Duration::from_secs(5), b.close())
+            .await
+            .expect("timed out closing the syncer");
+        assert_eq!(events.len(), 3);
+        assert_eq!(query(&events[0]), "BEGIN");
+        match &events[2].event {
+            Some(EventEnum::XIDEvent(e)) => assert_eq!(e.xid, 7),
+            _ => panic!("last event must be a xid event"),
+        }
+
+        // nothing is delivered after close
+        let (ctx, _handle) = tokio_context::context::Context::new();
+        let err = s.get_event(ctx).await.unwrap_err();
+        assert_eq!(err.to_string(), ERR_SYNC_CLOSED);
+
+        // the dump connection was killed from another one
+        assert!(queries(&master).contains(&format!("KILL {}", CONNECTION_ID)));
+
+        // a closed syncer can't sync again
+        assert!(b
+            .start_sync(Position {
+                name: BINLOG_NAME.to_string(),
+                pos: 4,
+            })
+            .await
+            .is_err());
+        assert!(b.close().await.is_empty());
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_sync_from_middle_of_file() -> Result<(), ReplicationError> {
+        let (path, offsets) = write_binlog_file("middle_of_file");
+        let master = FakeMaster::start(path.clone());
+
+        let mut b = BinlogSyncer::new(new_config(master.port))?;
+        let mut s = b
+            .start_sync(Position {
+                name: BINLOG_NAME.to_string(),
+                pos: offsets[2],
+            })
+            .await?;
+
+        let events = get_events(&mut s, 4).await;
+        assert_eq!(
+            events[1].header.as_ref().unwrap().event_type,
+            EventType::FormatDescriptionEvent
+        );
+        assert_eq!(query(&events[2]), "INSERT INTO t VALUES (1)");
+        assert_eq!(
+            events[3].header.as_ref().unwrap().log_pos,
+            std::fs::metadata(&path)?.len() as u32
+        );
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_sync_over_unix_socket() -> Result<(), ReplicationError> {
+        let (path, _) = write_binlog_file("unix_socket");
+        let socket = path.with_extension("sock");
+        let _ = std::fs::remove_file(&socket);
+        let master = FakeMaster::start_unix(path.clone(), &socket);
+
+        let mut cfg = new_config(3306);
+        cfg.host = socket.to_str().unwrap().to_string();
+        let mut b = BinlogSyncer::new(cfg)?;
+        let mut s = b
+            .start_sync(Position {
+                name: BINLOG_NAME.to_string(),
+                pos: 4,
+            })
+            .await?;
+
+        let events = get_events(&mut s, 5).await;
+        assert_eq!(query(&events[3]), "INSERT INTO t VALUES (1)");
+        assert_eq!(master.commands(mysql::COM_BINLOG_DUMP).len(), 1);
+
+        let _ = std::fs::remove_file(socket);
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_sync_with_wrong_password() -> Result<(), ReplicationError> {
+        let (path, _) = write_binlog_file("wrong_password");
+        let master = FakeMaster::start(path.clone());
+
+        let mut cfg = new_config(master.port);
+        cfg.password = "wrong".to_string();
+        let mut b = BinlogSyncer::new(cfg)?;
+        let rs = b
+            .start_sync(Position {
+                name: BINLOG_NAME.to_string(),
+                pos: 4,
+            })
+            .await;
+        assert!(rs.is_err());
+        assert!(rs.err().unwrap().to_string().contains("Access denied"));
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_sync_from_time() -> Result<(), ReplicationError> {
+        let (path1, _) = write_timed_binlog_file("from_time_1", &[1000, 1100]);
+        let (path2, offsets2) = write_timed_binlog_file("from_time_2", &[2000, 2100]);
+        let (path3, offsets3) = write_timed_binlog_file("from_time_3", &[3000, 3100]);
+        let end3 = std::fs::metadata(&path3)?.len() as u32;
+        let master = FakeMaster::start_with_options(
+            path1.clone(),
+            FakeMasterOptions {
+                binlog_files: vec![
+                    ("mysql-bin.000002".to_string(), path2.clone()),
+                    ("mysql-bin.000003".to_string(), path3.clone()),
+                ],
+                ..Default::default()
+            },
+        );
+
+        let cases = [
+            // before all the binlogs
+            (500, BINLOG_NAME, 4),
+            // the second transaction of the second binlog
+            (2050, "mysql-bin.000002", offsets2[4]),
+            (2100, "mysql-bin.000002", offsets2[4]),
+            // the first transaction of a binlog is at the time of its FDE
+            (3000, "mysql-bin.000003", offsets3[1]),
+            // after the last transaction of a binlog, the next binlog starts
+            (2500, "mysql-bin.000003", 4),
+            // after all the binlogs, the sync waits at the end
+            (5000, "mysql-bin.000003", end3),
+        ];
+        for (t, name, pos) in cases {
+            let mut b = BinlogSyncer::new(new_config(master.port))?;
+            let t = chrono::NaiveDateTime::from_timestamp_opt(t, 0).unwrap();
+            let _s = b.start_sync_from_time(t).await?;
+            b.close().await;
+
+            let dump = master.commands(mysql::COM_BINLOG_DUMP);
+            let last = dump.last().unwrap();
+            assert_eq!(
+                (
+                    String::from_utf8_lossy(&last[10..]).to_string(),
+                    LittleEndian::read_u32(last)
+                ),
+                (name.to_string(), pos),
+                "start time {}",
+                t
+            );
+        }
+
+        // the stream starts with the found transaction
+        let registers = master.commands(mysql::COM_REGISTER_SLAVE).len();
+        let kills = queries(&master)
+            .iter()
+            .filter(|q| q.starts_with("KILL"))
+            .count();
+        let mut b = BinlogSyncer::new(new_config(master.port))?;
+        let mut s = b
+            .start_sync_from_time(chrono::NaiveDateTime::from_timestamp_opt(2050, 0).unwrap())
+            .await?;
+        let events = get_events(&mut s, 3).await;
+        assert_eq!(query(&events[2]), "BEGIN");
+        assert_eq!(events[2].header.as_ref().unwrap().timestamp, 2100);
+
+        // the second binlog is probed on the connection listing the binlogs, the third on another one,
+        // the scan goes on with the dump of the second one. The probes close their connections, so the sync
+        // doesn't kill them, its last connection id is its own.
+        assert_eq!(
+            master.commands(mysql::COM_REGISTER_SLAVE).len() - registers,
+            3
+        );
+        assert_eq!(
+            queries(&master)
+                .iter()
+                .filter(|q| q.starts_with("KILL"))
+                .count(),
+            kills
+        );
+        let dump = master.commands(mysql::COM_BINLOG_DUMP);
+        assert_eq!(
+            String::from_utf8_lossy(&dump.last().unwrap()[10..]),
+            "mysql-bin.000002"
+        );
+        b.close().await;
+        assert_eq!(
+            queries(&master).last().unwrap(),
+            &format!("KILL {}", b.last_connection_id())
+        );
+
+        for path in [path1, path2, path3] {
+            let _ = std::fs::remove_file(path);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_sync_gtid() -> Result<(), ReplicationError> {
+        let (path, _) = write_binlog_file("start_sync_gtid");
+        let master = FakeMaster::start(path.clone());
+
+        let gset = mysql::parse_gtid_set(
+            mysql::MYSQL_FLAVOR,
+            "de278ad0-2106-11e4-9f8e-6edd0ca20947:1-2",
+        )?;
+        let mut b = BinlogSyncer::new(new_config(master.port))?;
+        let mut s = b.start_sync_gtid(gset.clone()).await?;
+
+        let events = get_events(&mut s, 5).await;
+        assert_eq!(query(&events[3]), "INSERT INTO t VALUES (1)");
+
+        // COM_BINLOG_DUMP_GTID: flags, server id, name length, name, position, gtid data length, gtid data
+        let dump = master.commands(mysql::COM_BINLOG_DUMP_GTID);
+        assert_eq!(dump.len(), 1);
+        assert_eq!(LittleEndian::read_u16(&dump[0]), 0);
+        assert_eq!(LittleEndian::read_u32(&dump[0][2..]), 100);
+        assert_eq!(LittleEndian::read_u32(&dump[0][6..]), 0);
+        assert_eq!(LittleEndian::read_u64(&dump[0][10..]), 4);
+        let gtid_data = gset.encode()?;
+        assert_eq!(
+            LittleEndian::read_u32(&dump[0][18..]) as usize,
+            gtid_data.len()
+        );
+        assert_eq!(&dump[0][22..], &gtid_data[..]);
+        assert!(master.commands(mysql::COM_BINLOG_DUMP).is_empty());
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_sync_gtid_mariadb() -> Result<(), ReplicationError> {
+        let (path, _) = write_binlog_file("start_sync_gtid_mariadb");
+        let master = FakeMaster::start(path.clone());
+
+        let mut cfg = new_config(master.port);
+        cfg.flavor = mysql::MARIA_DB_FLAVOR.to_string();
+        cfg.option = Some(Box::new(|c| {
+            Box::pin(async move {
+                c.execute("SET @slave_gtid_ignore_duplicates=1")
+                    .await
+                    .map(|_| ())
+            })
+        }));
+        let mut b = BinlogSyncer::new(cfg)?;
+        let mut s = b
+            .start_sync_gtid(mysql::parse_gtid_set(
+                mysql::MARIA_DB_FLAVOR,
+                "0-1-100,1-2-200",
+            )?)
+            .await?;
+
+        let events = get_events(&mut s, 5).await;
+        assert_eq!(query(&events[2]), "BEGIN");
+
+        let queries = queries(&master);
+        let capability = queries
+            .iter()
+            .position(|q| q == "SET @mariadb_slave_capability=4")
+            .unwrap();
+        let connect_state = queries
+            .iter()
+            .position(|q| q.starts_with("SET @slave_connect_state='"))
+            .unwrap();
+        assert!(capability < connect_state);
+        assert!(queries[connect_state].contains("0-1-100"));
+        assert!(queries[connect_state].contains("1-2-200"));
+        assert!(queries.contains(&"SET @slave_gtid_strict_mode=1".to_string()));
+        // the option runs before COM_REGISTER_SLAVE
+        assert_eq!(queries[0], "SET @slave_gtid_ignore_duplicates=1");
+
+        // MariaDB ignores the file and position when @slave_connect_state is set
+        let dump = master.commands(mysql::COM_BINLOG_DUMP);
+        assert_eq!(dump.len(), 1);
+        assert_eq!(LittleEndian::read_u32(&dump[0]), 0);
+        assert!(dump[0][10..].is_empty());
+        assert!(master.commands(mysql::COM_BINLOG_DUMP_GTID).is_empty());
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retry_sync_from_committed_position() -> Result<(), ReplicationError> {
+        let (path, offsets) = write_binlog_file("retry_sync_position");
+        // FDE and BEGIN are sent, then the connection breaks in the middle of the transaction
+        let master = FakeMaster::start_with_options(
+            path.clone(),
+            FakeMasterOptions {
+                drop_after: Some(2),
+                ..Default::default()
+            },
+        );
+
+        let mut b = BinlogSyncer::new(new_config(master.port))?;
+        let mut s = b
+            .start_sync(Position {
+                name: BINLOG_NAME.to_string(),
+                pos: 4,
+            })
+            .await?;
+
+        let events = get_events(&mut s, 3 + 5).await;
+        assert_eq!(query(&events[2]), "BEGIN");
+        // resumed with a fake rotate and FDE, then the whole transaction again
+        match &events[3].event {
+            Some(EventEnum::RotateEvent(e)) => assert_eq!(e.position, offsets[1] as u64),
+            _ => panic!("resumed stream must start with a fake rotate event"),
+        }
+        assert_eq!(query(&events[5]), "BEGIN");
+        assert_eq!(query(&events[6]), "INSERT INTO t VALUES (1)");
+        match &events[7].event {
+            Some(EventEnum::XIDEvent(e)) => assert_eq!(e.xid, 7),
+            _ => panic!("last event must be a xid event"),
+        }
+
+        let dump = master.commands(mysql::COM_BINLOG_DUMP);
+        assert_eq!(dump.len(), 2);
+        assert_eq!(LittleEndian::read_u32(&dump[1]), offsets[1]);
+        assert_eq!(&dump[1][10..], BINLOG_NAME.as_bytes());
+
+        // the broken connection is killed by the new one
+        assert!(queries(&master).contains(&format!("KILL {}", CONNECTION_ID)));
+        assert_eq!(b.last_connection_id(), CONNECTION_ID + 1);
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_advances_position() -> Result<(), ReplicationError> {
+        let (path, _) = write_binlog_file("heartbeat");
+        // the master skipped events the replica doesn't get, the heartbeat tells where it is
+        let master = FakeMaster::start_with_options(
+            path.clone(),
+            FakeMasterOptions {
+                heartbeat_pos: Some(10000),
+                ..Default::default()
+            },
+        );
+
+        let mut b = BinlogSyncer::new(new_config(master.port))?;
+        let mut s = b
+            .start_sync(Position {
+                name: BINLOG_NAME.to_string(),
+                pos: 4,
+            })
+            .await?;
+
+        let events = get_events(&mut s, 6 + 2).await;
+        match &events[5].event {
+            Some(EventEnum::HeartbeatEventV2(e)) => {
+                assert_eq!(e.log_name, BINLOG_NAME.as_bytes());
+                assert_eq!(e.log_pos, 10000);
+            }
+            _ => panic!("the dump must end with a heartbeat: {:?}", events[5].header),
+        }
+        // resumed from the position of the heartbeat
+        match &events[6].event {
+            Some(EventEnum::RotateEvent(e)) => assert_eq!(e.position, 10000),
+            _ => panic!("resumed stream must start with a fake rotate event"),
+        }
+
+        let dump = master.commands(mysql::COM_BINLOG_DUMP);
+        assert_eq!(dump.len(), 2);
+        assert_eq!(LittleEndian::read_u32(&dump[1]), 10000);
+        assert_eq!(&dump[1][10..], BINLOG_NAME.as_bytes());
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retry_sync_from_committed_gtid_set() -> Result<(), ReplicationError> {
+        let sid = uuid::Uuid::parse_str("de278ad0-2106-11e4-9f8e-6edd0ca20947").unwrap();
+        let (path, _) = write_gtid_binlog_file("retry_sync_gtid", &sid, &[1, 2]);
+        // transaction 1 is complete, transaction 2 is cut off after its BEGIN
+        let master = FakeMaster::start_with_options(
+            path.clone(),
+            FakeMasterOptions {
+                drop_after: Some(7),
+                ..Default::default()
+            },
+        );
+
+        let mut b = BinlogSyncer::new(new_config(master.port))?;
+        let mut s = b
+            .start_sync_gtid(mysql::parse_gtid_set(mysql::MYSQL_FLAVOR, "")?)
+            .await?;
+
+        // the fake master ignores the GTID set and always serves the whole file
+        let events = get_events(&mut s, 8 + 10).await;
+        assert_eq!(
+            events[6].header.as_ref().unwrap().event_type,
+            EventType::GtidEvent
+        );
+        assert_eq!(query(&events[7]), "BEGIN");
+
+        let dump = master.commands(mysql::COM_BINLOG_DUMP_GTID);
+        assert_eq!(dump.len(), 2);
+        let committed = mysql::parse_gtid_set(mysql::MYSQL_FLAVOR, &format!("{}:1", sid))?;
+        assert_eq!(&dump[1][22..], &committed.encode()?[..]);
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_progress_tracking() -> Result<(), ReplicationError> {
+        let sid = uuid::Uuid::parse_str("de278ad0-2106-11e4-9f8e-6edd0ca20947").unwrap();
+        let (path, offsets) = write_gtid_binlog_file("progress", &sid, &[1, 2]);
+        // transaction 2 is cut off after its BEGIN
+        let master = FakeMaster::start_with_options(
+            path.clone(),
+            FakeMasterOptions {
+                drop_after: Some(7),
+                ..Default::default()
+            },
+        );
+
+        let mut cfg = new_config(master.port);
+        cfg.disable_retry_sync = true;
+        let mut b = BinlogSyncer::new(cfg)?;
+        let mut s = b
+            .start_sync_gtid(mysql::parse_gtid_set(
+                mysql::MYSQL_FLAVOR,
+                &format!("{}:100", sid),
+            )?)
+            .await?;
+
+        let events = get_events(&mut s, 8).await;
+        match &events[5].event {
+            Some(EventEnum::XIDEvent(e)) => {
+                assert_eq!(
+                    e.gset.as_ref().unwrap().to_string(),
+                    format!("{}:1:100", sid)
+                );
+            }
+            _ => panic!("transaction 1 must end with a xid event"),
+        }
+        let (ctx, _handle) = tokio_context::context::Context::new();
+        assert!(s.get_event(ctx).await.is_err());
+
+        // the progress stops at the end of transaction 1
+        assert_eq!(b.position().pos, offsets[5]);
+        assert_eq!(b.get_next_position().pos, offsets[7]);
+        assert_eq!(b.gtid_set().unwrap().to_string(), format!("{}:1:100", sid));
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[test]
+    fn test_new_binlog_syncer_with_invalid_filter() {
+        let mut cfg = new_config(3306);
+        cfg.filter.do_table = vec!["t".to_string()];
+        assert!(BinlogSyncer::new(cfg).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_filter() -> Result<(), ReplicationError> {
+        let (path, offsets) = write_binlog_file("filter");
+        let master = FakeMaster::start(path.clone());
+
+        let mut cfg = new_config(master.port);
+        cfg.filter.ignore_db = vec!["test".to_string()];
+        let mut b = BinlogSyncer::new(cfg)?;
+        let mut s = b
+            .start_sync(Position {
+                name: BINLOG_NAME.to_string(),
+                pos: 4,
+            })
+            .await?;
+
+        // the INSERT of the ignored database is dropped, BEGIN is never filtered
+        let events = get_events(&mut s, 4).await;
+        assert!(events.iter().all(|e| !e.filtered));
+        assert_eq!(query(&events[2]), "BEGIN");
+        match &events[3].event {
+            Some(EventEnum::XIDEvent(e)) => assert_eq!(e.xid, 7),
+            _ => panic!("the INSERT must be filtered out"),
+        }
+        assert_eq!(b.position().pos, events[3].header.as_ref().unwrap().log_pos);
+        assert!(b.position().pos > offsets[3]);
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_filter_checkpoint() -> Result<(), ReplicationError> {
+        let mut bodies = vec![(EventType::FormatDescriptionEvent, format_description_body())];
+        bodies.push((
+            EventType::QueryEvent,
+            query_body("test", "CREATE TABLE t (a INT)"),
+        ));
+        bodies.extend(transaction_bodies(7));
+        let (path, offsets) = write_binlog_events("filter_checkpoint", bodies);
+        let master = FakeMaster::start(path.clone());
+        let store = Arc::new(MemoryCheckpointStore::new());
+
+        let mut cfg = new_config(master.port);
+        cfg.filter.ignore_db = vec!["test".to_string()];
+        cfg.checkpoint_store = Some(store.clone());
+        let mut b = BinlogSyncer::new(cfg)?;
+        let mut s = b
+            .start_sync(Position {
+                name: BINLOG_NAME.to_string(),
+                pos: 4,
+            })
+            .await?;
+
+        // the filtered DDL ends a transaction, it's sent for its checkpoint, the filtered INSERT isn't
+        let events = get_events(&mut s, 5).await;
+        assert!(events[2].filtered);
+        assert_eq!(query(&events[2]), "CREATE TABLE t (a INT)");
+        assert_eq!(
+            events[2].checkpoint.as_ref().unwrap().position.pos,
+            offsets[2]
+        );
+        assert_eq!(query(&events[3]), "BEGIN");
+        assert!(!events[4].filtered);
+        assert!(events[4].checkpoint.is_some());
+
+        b.save_checkpoint(&events[2])?;
+        assert_eq!(store.load()?.unwrap().position.pos, offsets[2]);
+        b.save_checkpoint(&events[4])?;
+        assert_eq!(
+            store.load()?.unwrap().position.pos,
+            events[4].header.as_ref().unwrap().log_pos
+        );
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_sync_from_checkpoint() -> Result<(), ReplicationError> {
+        let (path, _) = write_binlog_file("checkpoint");
+        let master = FakeMaster::start(path.clone());
+        let store = Arc::new(MemoryCheckpointStore::new());
+        let initial = Checkpoint {
+            position: Position {
+                name: BINLOG_NAME.to_string(),
+                pos: 4,
+            },
+            gset: None,
+        };
+
+        let mut cfg = new_config(master.port);
+        cfg.checkpoint_store = Some(store.clone());
+        let mut b = BinlogSyncer::new(cfg)?;
+        let mut s = b.start_sync_from_checkpoint(initial.clone()).await?;
+
+        // only the event ending the transaction carries a checkpoint
+        let events = get_events(&mut s, 5).await;
+        assert!(events[..4].iter().all(|e| e.checkpoint.is_none()));
+        let end = events[4].header.as_ref().unwrap().log_pos;
+        assert_eq!(
+            events[4].checkpoint.as_ref().unwrap().position,
+            Position {
+                name: BINLOG_NAME.to_string(),
+                pos: end,
+            }
+        );
+        // nothing is saved until the consumer says so
+        b.save_checkpoint(&events[3])?;
+        assert!(store.load()?.is_none());
+        b.save_checkpoint(&events[4])?;
+        assert_eq!(store.load()?.unwrap().position.pos, end);
+        b.close().await;
+
+        // a new syncer resumes after the saved transaction
+        let mut cfg = new_config(master.port);
+        cfg.checkpoint_store = Some(store.clone());
+        let mut b = BinlogSyncer::new(cfg)?;
+        let mut s = b.start_sync_from_checkpoint(initial).await?;
+        let _ = get_events(&mut s, 2).await;
+
+        let dump = master.commands(mysql::COM_BINLOG_DUMP);
+        assert_eq!(dump.len(), 2);
+        assert_eq!(LittleEndian::read_u32(&dump[1]), end);
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retry_sync_exceeds_max_reconnect_attempts() -> Result<(), ReplicationError> {
+        let (path, _) = write_binlog_file("retry_sync_max_attempts");
+        let master = FakeMaster::start_with_options(
+            path.clone(),
+            FakeMasterOptions {
+                drop_after: Some(3),
+                deny_reconnect: true,
+                ..Default::default()
+            },
+        );
+
+        let mut cfg = new_config(master.port);
+        cfg.max_reconnect_attempts = 2;
+        let mut b = BinlogSyncer::new(cfg)?;
+        let mut s = b
+            .start_sync(Position {
+                name: BINLOG_NAME.to_string(),
+                pos: 4,
+            })
+            .await?;
+
+        let _ = get_events(&mut s, 4).await;
+        let (ctx, _handle) = tokio_context::context::Context::new();
+        let rs = tokio::time::timeout(std::time::Duration::from_secs(10), s.get_event(ctx))
+            .await
+            .expect("timed out waiting for the sync error");
+        assert!(rs.unwrap_err().to_string().contains("Access denied"));
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_close_while_retrying() -> Result<(), ReplicationError> {
+        let (path, _) = write_binlog_file("close_while_retrying");
+        let master = FakeMaster::start_with_options(
+            path.clone(),
+            FakeMasterOptions {
+                drop_after: Some(3),
+                deny_reconnect: true,
+                ..Default::default()
+            },
+        );
+
+        let mut b = BinlogSyncer::new(new_config(master.port))?;
+        let mut s = b
+            .start_sync(Position {
+                name: BINLOG_NAME.to_string(),
+                pos: 4,
+            })
+            .await?;
+
+        let _ = get_events(&mut s, 4).await;
+        // the first retry failed, the dump task waits 2s for the next one
+        tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+
+        // the context of the task is cancelled, close doesn't wait for the retry
+        let start = std::time::Instant::now();
+        assert!(b.close().await.is_empty());
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+
+        let (ctx, _handle) = tokio_context::context::Context::new();
+        let err = s.get_event(ctx).await.unwrap_err();
+        assert_eq!(err.to_string(), ERR_SYNC_CLOSED);
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_disable_retry_sync() -> Result<(), ReplicationError> {
+        let (path, _) = write_binlog_file("disable_retry_sync");
+        let master = FakeMaster::start_with_options(
+            path.clone(),
+            FakeMasterOptions {
+                drop_after: Some(1),
+                ..Default::default()
+            },
+        );
+
+        let mut cfg = new_config(master.port);
+        cfg.disable_retry_sync = true;
+        let mut b = BinlogSyncer::new(cfg)?;
+        let mut s = b
+            .start_sync(Position {
+                name: BINLOG_NAME.to_string(),
+                pos: 4,
+            })
+            .await?;
+
+        let _ = get_events(&mut s, 2).await;
+        let (ctx, _handle) = tokio_context::context::Context::new();
+        let rs = tokio::time::timeout(std::time::Duration::from_secs(5), s.get_event(ctx))
+            .await
+            .expect("timed out waiting for the sync error");
+        assert!(rs.is_err());
+        assert_eq!(master.commands(mysql::COM_BINLOG_DUMP).len(), 1);
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    async fn check_malformed_packet(
+        tag: &str,
+        packet: Vec<u8>,
+        semi_sync: bool,
+    ) -> Result<(), ReplicationError> {
+        let (path, _) = write_binlog_file(tag);
+        let master = FakeMaster::start_with_options(
+            path.clone(),
+            FakeMasterOptions {
+                semi_sync,
+                last_packet: Some(packet),
+                ..Default::default()
+            },
+        );
+
+        let mut cfg = new_config(master.port);
+        cfg.semi_sync_enabled = semi_sync;
+        let mut b = BinlogSyncer::new(cfg)?;
+        let mut s = b
+            .start_sync(Position {
+                name: BINLOG_NAME.to_string(),
+                pos: 4,
+            })
+            .await?;
+
+        let _ = get_events(&mut s, 5).await;
+        let (ctx, _handle) = tokio_context::context::Context::new();
+        let rs = tokio::time::timeout(std::time::Duration::from_secs(5), s.get_event(ctx))
+            .await
+            .expect("timed out waiting for the sync error");
+        assert_eq!(
+            rs.unwrap_err().to_string(),
+            MysqlError::ErrMalformPacket.to_string()
+        );
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_empty_packet() -> Result<(), ReplicationError> {
+        check_malformed_packet("empty_packet", vec![], false).await
+    }
+
+    #[tokio::test]
+    async fn test_truncated_semi_sync_header() -> Result<(), ReplicationError> {
+        check_malformed_packet(
+            "truncated_semi_sync_header",
+            vec![mysql::OK_HEADER, SEMI_SYNC_INDICATOR],
+            true,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_read_timeout_with_heartbeat() -> Result<(), ReplicationError> {
+        let (path, _) = write_binlog_file("read_timeout");
+        let master = FakeMaster::start(path.clone());
+
+        // the fake master sends no heartbeat, it looks dead once the events are sent
+        let mut cfg = new_config(master.port);
+        cfg.heartbeat_period = std::time::Duration::from_millis(50);
+        cfg.read_timeout = std::time::Duration::from_millis(300);
+        cfg.recv_buffer_size = 256 * 1024;
+        cfg.disable_retry_sync = true;
+        let mut b = BinlogSyncer::new(cfg)?;
+        let mut s = b
+            .start_sync(Position {
+                name: BINLOG_NAME.to_string(),
+                pos: 4,
+            })
+            .await?;
+
+        let _ = get_events(&mut s, 5).await;
+        let (ctx, _handle) = tokio_context::context::Context::new();
+        let rs = tokio::time::timeout(std::time::Duration::from_secs(5), s.get_event(ctx))
+            .await
+            .expect("timed out waiting for the read timeout");
+        let err = rs.unwrap_err();
+        assert!(err.is_timeout(), "{}", err);
+
+        assert!(queries(&master).contains(&"SET @master_heartbeat_period=50000000;".to_string()));
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_semi_sync_ack() -> Result<(), ReplicationError> {
+        let (path, _) = write_binlog_file("semi_sync_ack");
+        let master = FakeMaster::start_with_options(
+            path.clone(),
+            FakeMasterOptions {
+                semi_sync: true,
+                ..Default::default()
+            },
+        );
+
+        let mut cfg = new_config(master.port);
+        cfg.semi_sync_enabled = true;
+        let mut b = BinlogSyncer::new(cfg)?;
+        let mut s = b
+            .start_sync(Position {
+                name: BINLOG_NAME.to_string(),
+                pos: 4,
+            })
+            .await?;
+
+        let events = get_events(&mut s, 5).await;
+        assert!(queries(&master).contains(&"SET @rpl_semi_sync_slave = 1;".to_string()));
+        assert_eq!(query(&events[3]), "INSERT INTO t VALUES (1)");
+        assert!(events[..4].iter().all(|e| e.semi_sync_ack_pos.is_none()));
+
+        let file_size = std::fs::metadata(&path)?.len() as u32;
+        assert_eq!(
+            events[4].semi_sync_ack_pos,
+            Some(Position {
+                name: BINLOG_NAME.to_string(),
+                pos: file_size,
+            })
+        );
+
+        // nothing is acknowledged until the consumer asks for it
+        b.reply_semi_sync_ack(&events[3]).await?;
+        assert!(master.commands(SEMI_SYNC_INDICATOR).is_empty());
+
+        b.reply_semi_sync_ack(&events[4]).await?;
+        let mut acks = master.commands(SEMI_SYNC_INDICATOR);
+        for _ in 0..100 {
+            if !acks.is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            acks = master.commands(SEMI_SYNC_INDICATOR);
+        }
+        assert_eq!(acks.len(), 1);
+        assert_eq!(LittleEndian::read_u64(&acks[0]), file_size as u64);
+        assert_eq!(&acks[0][8..], BINLOG_NAME.as_bytes());
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_semi_sync_not_supported_by_master() -> Result<(), ReplicationError> {
+        let (path, _) = write_binlog_file("semi_sync_not_supported");
+        let master = FakeMaster::start(path.clone());
+
+        let mut cfg = new_config(master.port);
+        cfg.semi_sync_enabled = true;
+        let mut b = BinlogSyncer::new(cfg)?;
+        let mut s = b
+            .start_sync(Position {
+                name: BINLOG_NAME.to_string(),
+                pos: 4,
+            })
+            .await?;
+
+        let events = get_events(&mut s, 5).await;
+        assert!(events.iter().all(|e| e.semi_sync_ack_pos.is_none()));
+        assert!(!queries(&master).contains(&"SET @rpl_semi_sync_slave = 1;".to_string()));
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+}
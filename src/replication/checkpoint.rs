@@ -0,0 +1,145 @@
+use crate::error::ReplicationError;
+use crate::mysql;
+use crate::mysql::{GtidSetEnum, Position};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// Checkpoint is where a consumer resumes the sync, the position and the GTID set right after a committed transaction.
+#[derive(Debug, Clone, Default)]
+pub struct Checkpoint {
+    pub position: Position,
+    // none if the sync is position based
+    pub gset: Option<GtidSetEnum>,
+}
+
+// CheckpointStore keeps the last checkpoint of a consumer across restarts,
+// see BinlogSyncer::start_sync_from_checkpoint and BinlogSyncer::save_checkpoint.
+pub trait CheckpointStore: Send + Sync {
+    // Load returns the saved checkpoint, none if nothing was saved yet.
+    fn load(&self) -> Result<Option<Checkpoint>, ReplicationError>;
+
+    // Save replaces the saved checkpoint, it's durable once Save returns.
+    fn save(&self, checkpoint: &Checkpoint) -> Result<(), ReplicationError>;
+}
+
+// MemoryCheckpointStore keeps the checkpoint in memory, e.g. for tests or a consumer restarting the sync in process.
+#[derive(Debug, Default)]
+pub struct MemoryCheckpointStore {
+    _checkpoint: Mutex<Option<Checkpoint>>,
+}
+
+impl MemoryCheckpointStore {
+    pub fn new() -> MemoryCheckpointStore {
+        MemoryCheckpointStore::default()
+    }
+}
+
+impl CheckpointStore for MemoryCheckpointStore {
+    fn load(&self) -> Result<Option<Checkpoint>, ReplicationError> {
+        Ok(self._checkpoint.lock().unwrap().clone())
+    }
+
+    fn save(&self, checkpoint: &Checkpoint) -> Result<(), ReplicationError> {
+        *self._checkpoint.lock().unwrap() = Some(checkpoint.clone());
+        Ok(())
+    }
+}
+
+// the content of the checkpoint file, the GTID set is kept as a string with its flavor
+#[derive(Debug, Serialize, Deserialize)]
+struct CheckpointFile {
+    name: String,
+    pos: u32,
+    flavor: Option<String>,
+    gtid_set: Option<String>,
+}
+
+// FileCheckpointStore saves the checkpoint as JSON in a local file. The file is written to a temporary file first,
+// synced and renamed over the old one, so a crash leaves either the old or the new checkpoint.
+#[derive(Debug)]
+pub struct FileCheckpointStore {
+    _path: PathBuf,
+}
+
+impl FileCheckpointStore {
+    pub fn new<P: AsRef<Path>>(path: P) -> FileCheckpointStore {
+        FileCheckpointStore {
+            _path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self._path
+    }
+
+    fn _tmp_path(&self) -> PathBuf {
+        let mut name = self._path.file_name().unwrap_or_default().to_os_string();
+        name.push(".tmp");
+        self._path.with_file_name(name)
+    }
+}
+
+impl CheckpointStore for FileCheckpointStore {
+    fn load(&self) -> Result<Option<Checkpoint>, ReplicationError> {
+        let data = match fs::read(&self._path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let f: CheckpointFile = serde_json::from_slice(&data).map_err(|e| {
+            ReplicationError::new(format!(
+                "invalid checkpoint file {}: {}",
+                self._path.display(),
+                e
+            ))
+        })?;
+        let gset = match (&f.flavor, &f.gtid_set) {
+            (Some(flavor), Some(s)) => Some(mysql::parse_gtid_set(flavor, s)?),
+            _ => None,
+        };
+
+        Ok(Some(Checkpoint {
+            position: Position {
+                name: f.name,
+                pos: f.pos,
+            },
+            gset,
+        }))
+    }
+
+    fn save(&self, checkpoint: &Checkpoint) -> Result<(), ReplicationError> {
+        let flavor = checkpoint.gset.as_ref().map(|g| match g {
+            GtidSetEnum::MysqlGTIDSet(_) => mysql::MYSQL_FLAVOR.to_string(),
+            GtidSetEnum::MariadbGTIDSet(_) => mysql::MARIA_DB_FLAVOR.to_string(),
+        });
+        let data = serde_json::to_vec(&CheckpointFile {
+            name: checkpoint.position.name.clone(),
+            pos: checkpoint.position.pos,
+            flavor,
+            gtid_set: checkpoint.gset.as_ref().map(|g| g.to_string()),
+        })?;
+
+        let tmp_path = self._tmp_path();
+        let mut f = fs::File::create(&tmp_path)?;
+        f.write_all(&data)?;
+        f.sync_all()?;
+        drop(f);
+
+        fs::rename(&tmp_path, &self._path)?;
+        // the rename is durable once the directory is synced
+        if let Some(dir) = self._path.parent() {
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            fs::File::open(dir)?.sync_all()?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+#[cfg(test)]
+mod tests {
+    use crate::error::ReplicationError;
+    use crate::mysql;
+    use crate::mysql::Position;
+    use crate::replication::{
+        Checkpoint, CheckpointStore, FileCheckpointStore, MemoryCheckpointStore,
+    };
+
+    fn checkpoint(flavor: Option<(&str, &str)>) -> Result<Checkpoint, ReplicationError> {
+        let gset = match flavor {
+            Some((flavor, s)) => Some(mysql::parse_gtid_set(flavor, s)?),
+            None => None,
+        };
+        Ok(Checkpoint {
+            position: Position {
+                name: "mysql-bin.000003".to_string(),
+                pos: 1234,
+            },
+            gset,
+        })
+    }
+
+    fn temp_path(tag: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!(
+            "checkpoint_test_{}_{}.json",
+            std::process::id(),
+            tag
+        ))
+    }
+
+    #[test]
+    fn test_memory_checkpoint_store() -> Result<(), ReplicationError> {
+        let store = MemoryCheckpointStore::new();
+        assert!(store.load()?.is_none());
+
+        store.save(&checkpoint(None)?)?;
+        let c = store.load()?.unwrap();
+        assert_eq!(c.position, checkpoint(None)?.position);
+        assert!(c.gset.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_checkpoint_store() -> Result<(), ReplicationError> {
+        let path = temp_path("file");
+        let store = FileCheckpointStore::new(&path);
+        assert!(store.load()?.is_none());
+
+        store.save(&checkpoint(None)?)?;
+        let c = store.load()?.unwrap();
+        assert_eq!(c.position, checkpoint(None)?.position);
+        assert!(c.gset.is_none());
+
+        // the new checkpoint replaces the old one, no temporary file is left
+        let gtid = "de278ad0-2106-11e4-9f8e-6edd0ca20947:1-10";
+        store.save(&checkpoint(Some((mysql::MYSQL_FLAVOR, gtid)))?)?;
+        let c = FileCheckpointStore::new(&path).load()?.unwrap();
+        assert_eq!(c.position.pos, 1234);
+        assert!(c
+            .gset
+            .unwrap()
+            .equal(&mysql::parse_gtid_set(mysql::MYSQL_FLAVOR, gtid)?));
+        let dir = std::fs::read_dir(path.parent().unwrap())?;
+        let tmp_name = format!("{}.tmp", path.file_name().unwrap().to_string_lossy());
+        assert!(dir
+            .filter_map(|e| e.ok())
+            .all(|e| e.file_name().to_string_lossy() != tmp_name));
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_checkpoint_store_mariadb() -> Result<(), ReplicationError> {
+        let path = temp_path("mariadb");
+        let store = FileCheckpointStore::new(&path);
+
+        store.save(&checkpoint(Some((
+            mysql::MARIA_DB_FLAVOR,
+            "0-1-100,1-2-200",
+        )))?)?;
+        let gset = store.load()?.unwrap().gset.unwrap();
+        assert!(gset.equal(&mysql::parse_gtid_set(
+            mysql::MARIA_DB_FLAVOR,
+            "0-1-100,1-2-200"
+        )?));
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_checkpoint_store_invalid_file() -> Result<(), ReplicationError> {
+        let path = temp_path("invalid");
+        std::fs::write(&path, b"mysql-bin.000003:1234")?;
+
+        let err = FileCheckpointStore::new(&path).load().unwrap_err();
+        assert!(err.to_string().contains("invalid checkpoint file"));
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
 use crate::error::ReplicationError;
 use crate::replication::RowsEvent;
-use std::rc::Rc;
+use std::sync::Arc;
 
-pub type RowsEventDecodeFunc = Rc<dyn Fn(&mut RowsEvent, &[u8]) -> Result<(), ReplicationError>>;
+pub type RowsEventDecodeFunc =
+    Arc<dyn Fn(&mut RowsEvent, &[u8]) -> Result<(), ReplicationError> + Send + Sync>;
@@ -225,14 +225,38 @@ impl Event for RotateEvent {
     }
 
     fn decode(&mut self, data: &[u8]) -> Result<(), ReplicationError> {
-        let mut rdr = Cursor::new(data);
-        self.position = rdr.read_u64::<LittleEndian>()?;
-        self.next_log_name = data[rdr.position() as usize..].to_vec();
+        *self = RotateEventRef::decode(data)?.to_owned();
 
         Ok(())
     }
 }
 
+// RotateEventRef is a RotateEvent borrowing the next log name from the event body.
+#[derive(Debug, Default, Clone)]
+pub struct RotateEventRef<'a> {
+    pub position: u64,
+    pub next_log_name: &'a [u8],
+}
+
+impl<'a> RotateEventRef<'a> {
+    pub fn decode(data: &'a [u8]) -> Result<RotateEventRef<'a>, ReplicationError> {
+        let mut rdr = Cursor::new(data);
+        let position = rdr.read_u64::<LittleEndian>()?;
+
+        Ok(RotateEventRef {
+            position,
+            next_log_name: &data[rdr.position() as usize..],
+        })
+    }
+
+    pub fn to_owned(&self) -> RotateEvent {
+        RotateEvent {
+            position: self.position,
+            next_log_name: self.next_log_name.to_vec(),
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct PreviousGTIDsEvent {
     pub gtid_sets: String,
@@ -376,34 +400,78 @@ impl Event for QueryEvent {
     }
 
     fn decode(&mut self, data: &[u8]) -> Result<(), ReplicationError> {
+        let e = QueryEventRef::decode(data)?;
+        self.slave_proxy_id = e.slave_proxy_id;
+        self.execution_time = e.execution_time;
+        self.error_code = e.error_code;
+        self.status_vars = e.status_vars.to_vec();
+        self.schema = e.schema.to_vec();
+
+        if self.compressed {
+            self.query = decompress_mariadb_data(e.query)?;
+        } else {
+            self.query = e.query.to_vec();
+        }
+
+        Ok(())
+    }
+}
+
+// QueryEventRef is a QueryEvent borrowing the status vars, the schema and the query from the event body.
+// The query of a mariadb QUERY_COMPRESSED_EVENT is the compressed one.
+#[derive(Debug, Default, Clone)]
+pub struct QueryEventRef<'a> {
+    pub slave_proxy_id: u32,
+    pub execution_time: u32,
+    pub error_code: u16,
+    pub status_vars: &'a [u8],
+    pub schema: &'a [u8],
+    pub query: &'a [u8],
+}
+
+impl<'a> QueryEventRef<'a> {
+    pub fn decode(data: &'a [u8]) -> Result<QueryEventRef<'a>, ReplicationError> {
         let mut rdr = Cursor::new(data);
-        self.slave_proxy_id = rdr.read_u32::<LittleEndian>()?;
-        self.execution_time = rdr.read_u32::<LittleEndian>()?;
+        let slave_proxy_id = rdr.read_u32::<LittleEndian>()?;
+        let execution_time = rdr.read_u32::<LittleEndian>()?;
         let schema_length = rdr.read_u8()?;
-        self.error_code = rdr.read_u16::<LittleEndian>()?;
+        let error_code = rdr.read_u16::<LittleEndian>()?;
 
         let status_vars_length = rdr.read_u16::<LittleEndian>()?;
 
         let status_vars_start = rdr.position() as usize;
         let status_vars_stop = status_vars_start + status_vars_length as usize;
-        self.status_vars = data[status_vars_start..status_vars_stop].to_vec();
+        let status_vars = &data[status_vars_start..status_vars_stop];
         rdr.seek(SeekFrom::Current(status_vars_length as i64))?;
 
         let schema_start = rdr.position() as usize;
         let schema_stop = schema_start + schema_length as usize;
-        self.schema = data[schema_start..schema_stop].to_vec();
+        let schema = &data[schema_start..schema_stop];
         rdr.seek(SeekFrom::Current(schema_length as i64))?;
 
         //skip 0x00
         rdr.seek(SeekFrom::Current(1))?;
 
-        if self.compressed {
-            self.query = decompress_mariadb_data(&data[rdr.position() as usize..])?;
-        } else {
-            self.query = data[rdr.position() as usize..].to_vec();
-        }
+        Ok(QueryEventRef {
+            slave_proxy_id,
+            execution_time,
+            error_code,
+            status_vars,
+            schema,
+            query: &data[rdr.position() as usize..],
+        })
+    }
 
-        Ok(())
+    pub fn to_owned(&self) -> QueryEvent {
+        QueryEvent {
+            slave_proxy_id: self.slave_proxy_id,
+            execution_time: self.execution_time,
+            error_code: self.error_code,
+            status_vars: self.status_vars.to_vec(),
+            schema: self.schema.to_vec(),
+            query: self.query.to_vec(),
+            ..Default::default()
+        }
     }
 }
 
@@ -783,6 +851,117 @@ impl Event for IntVarEvent {
     }
 }
 
+// the fields of the body of a HeartbeatLogEventV2, each one is a type, a length and a value
+const OTW_HB_HEADER_END_MARK: u64 = 0;
+const OTW_HB_LOG_FILENAME_FIELD: u64 = 1;
+const OTW_HB_LOG_POSITION_FIELD: u64 = 2;
+
+// HeartbeatEvent is sent by the master when it has no event to send in the heartbeat period,
+// log_pos is the position of the master in the binlog file, taken from the event header.
+#[derive(Debug, Default, Clone)]
+pub struct HeartbeatEvent {
+    pub log_name: Vec<u8>,
+    pub log_pos: u64,
+}
+
+impl Event for HeartbeatEvent {
+    fn dump<W: Write>(&mut self, writer: &mut W) -> Result<(), ReplicationError> {
+        writeln!(
+            writer,
+            "Log name: {}",
+            String::from_utf8_lossy(&self.log_name)
+        )?;
+        writeln!(writer, "Log position: {}", self.log_pos)?;
+
+        writeln!(writer)?;
+        Ok(())
+    }
+
+    fn decode(&mut self, data: &[u8]) -> Result<(), ReplicationError> {
+        self.log_name = data.to_vec();
+
+        Ok(())
+    }
+}
+
+// HeartbeatEventV2 is the heartbeat of MySQL 8.0.26+, its position is a field of the body,
+// so it can go beyond the 4GB of the header.
+#[derive(Debug, Default, Clone)]
+pub struct HeartbeatEventV2 {
+    pub log_name: Vec<u8>,
+    pub log_pos: u64,
+}
+
+impl Event for HeartbeatEventV2 {
+    fn dump<W: Write>(&mut self, writer: &mut W) -> Result<(), ReplicationError> {
+        writeln!(
+            writer,
+            "Log name: {}",
+            String::from_utf8_lossy(&self.log_name)
+        )?;
+        writeln!(writer, "Log position: {}", self.log_pos)?;
+
+        writeln!(writer)?;
+        Ok(())
+    }
+
+    fn decode(&mut self, data: &[u8]) -> Result<(), ReplicationError> {
+        let mut pos = 0_usize;
+        while pos < data.len() {
+            let typ = HeartbeatEventV2::_read_length_encoded_int(data, &mut pos)?;
+            if typ == OTW_HB_HEADER_END_MARK {
+                break;
+            }
+
+            let length = HeartbeatEventV2::_read_length_encoded_int(data, &mut pos)? as usize;
+            if data.len() - pos < length {
+                return Err(ReplicationError::new(format!(
+                    "invalid heartbeat v2 field {}, {} bytes left, while {} expected",
+                    typ,
+                    data.len() - pos,
+                    length
+                )));
+            }
+            let value = &data[pos..pos + length];
+            pos += length;
+
+            match typ {
+                OTW_HB_LOG_FILENAME_FIELD => self.log_name = value.to_vec(),
+                OTW_HB_LOG_POSITION_FIELD => {
+                    self.log_pos = HeartbeatEventV2::_read_length_encoded_int(value, &mut 0)?
+                }
+                // fields of later versions are skipped
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl HeartbeatEventV2 {
+    fn _read_length_encoded_int(data: &[u8], pos: &mut usize) -> Result<u64, ReplicationError> {
+        let b = &data[*pos..];
+        let size = match b.first() {
+            Some(0xfc) => 3,
+            Some(0xfd) => 4,
+            Some(0xfe) => 9,
+            Some(_) => 1,
+            None => 0,
+        };
+        if size == 0 || b.len() < size {
+            return Err(ReplicationError::new(format!(
+                "invalid heartbeat v2 event, length encoded int truncated at {}",
+                *pos
+            )));
+        }
+
+        let (v, _, n) = length_encoded_int(b);
+        *pos += n;
+        Ok(v)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use byteorder::{BigEndian, ReadBytesExt};
@@ -1,9 +1,11 @@
 use crate::error::ReplicationError;
 use crate::replication::{
     BeginLoadQueryEvent, Event, EventHeader, ExecuteLoadQueryEvent, FormatDescriptionEvent,
-    GTIDEvent, GenericEvent, IntVarEvent, MariadbAnnotateRowsEvent, MariadbBinlogCheckPointEvent,
-    MariadbGTIDEvent, MariadbGTIDListEvent, PreviousGTIDsEvent, QueryEvent, RotateEvent, RowsEvent,
-    RowsQueryEvent, TableMapEvent, TransactionPayloadEvent, XIDEvent,
+    GTIDEvent, GenericEvent, HeartbeatEvent, HeartbeatEventV2, IntVarEvent,
+    MariadbAnnotateRowsEvent, MariadbBinlogCheckPointEvent, MariadbGTIDEvent, MariadbGTIDListEvent,
+    PreviousGTIDsEvent, QueryEvent, QueryEventRef, RotateEvent, RotateEventRef, RowsEvent,
+    RowsEventRef, RowsQueryEvent, RowsQueryEventRef, TableMapEvent, TransactionPayloadEvent,
+    XIDEvent,
 };
 use std::io::Write;
 
@@ -27,6 +29,8 @@ pub enum EventEnum {
     MariadbGTIDListEvent(MariadbGTIDListEvent),
     IntVarEvent(IntVarEvent),
     TransactionPayloadEvent(TransactionPayloadEvent),
+    HeartbeatEvent(HeartbeatEvent),
+    HeartbeatEventV2(HeartbeatEventV2),
     GenericEvent(GenericEvent),
 }
 
@@ -51,6 +55,8 @@ impl EventEnum {
             EventEnum::MariadbGTIDListEvent(ref mut r) => r.decode(data),
             EventEnum::IntVarEvent(ref mut r) => r.decode(data),
             EventEnum::TransactionPayloadEvent(ref mut r) => r.decode(data),
+            EventEnum::HeartbeatEvent(ref mut r) => r.decode(data),
+            EventEnum::HeartbeatEventV2(ref mut r) => r.decode(data),
             EventEnum::GenericEvent(ref mut r) => r.decode(data),
         }
     }
@@ -75,7 +81,33 @@ impl EventEnum {
             EventEnum::MariadbGTIDListEvent(ref mut r) => r.dump(writer),
             EventEnum::IntVarEvent(ref mut r) => r.dump(writer),
             EventEnum::TransactionPayloadEvent(ref mut r) => r.dump(writer),
+            EventEnum::HeartbeatEvent(ref mut r) => r.dump(writer),
+            EventEnum::HeartbeatEventV2(ref mut r) => r.dump(writer),
             EventEnum::GenericEvent(ref mut r) => r.dump(writer),
         }
     }
 }
+
+// EventRef is the event of a BinlogEventRef. The events carrying large strings or rows borrow them
+// from the parsed buffer, the other events are decoded as BinlogParser::parse does.
+#[derive(Debug, Clone)]
+pub enum EventRef<'a> {
+    QueryEvent(QueryEventRef<'a>),
+    RowsQueryEvent(RowsQueryEventRef<'a>),
+    RotateEvent(RotateEventRef<'a>),
+    RowsEvent(RowsEventRef<'a>),
+    Owned(Box<EventEnum>),
+}
+
+impl<'a> EventRef<'a> {
+    // ToOwned copies the borrowed fields and decodes the rows of a rows event.
+    pub fn to_owned(&self) -> Result<EventEnum, ReplicationError> {
+        Ok(match self {
+            EventRef::QueryEvent(e) => EventEnum::QueryEvent(e.to_owned()),
+            EventRef::RowsQueryEvent(e) => EventEnum::RowsQueryEvent(e.to_owned()),
+            EventRef::RotateEvent(e) => EventEnum::RotateEvent(e.to_owned()),
+            EventRef::RowsEvent(e) => EventEnum::RowsEvent(e.to_owned()?),
+            EventRef::Owned(e) => e.as_ref().clone(),
+        })
+    }
+}
@@ -4,7 +4,8 @@ mod tests {
     use crate::mysql::MariadbGTID;
     use crate::replication::IntVarEventType;
     use crate::replication::{
-        Event, GTIDEvent, IntVarEvent, MariadbGTIDEvent, MariadbGTIDListEvent,
+        Event, GTIDEvent, HeartbeatEvent, HeartbeatEventV2, IntVarEvent, MariadbGTIDEvent,
+        MariadbGTIDListEvent,
     };
 
     #[test]
@@ -160,4 +161,47 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_heartbeat_event() -> Result<(), ReplicationError> {
+        // the body is the log file name, the position is in the event header
+        let mut ev = HeartbeatEvent::default();
+        ev.decode(b"mysql-bin.000003")?;
+        assert_eq!(ev.log_name, b"mysql-bin.000003");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_heartbeat_event_v2() -> Result<(), ReplicationError> {
+        // log file name field, an unknown field, log position field 0x1_0000_0004 (over 4GB), end mark
+        let mut data = vec![0x01, 0x10];
+        data.extend(b"mysql-bin.000003");
+        data.extend([0x07, 0x02, 0xaa, 0xbb]);
+        data.extend([
+            0x02, 0x09, 0xfe, 0x04, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+        ]);
+        data.push(0x00);
+
+        let mut ev = HeartbeatEventV2::default();
+        ev.decode(&data)?;
+        assert_eq!(ev.log_name, b"mysql-bin.000003");
+        assert_eq!(ev.log_pos, 0x1_0000_0004);
+
+        // a small position takes one byte
+        let mut ev = HeartbeatEventV2::default();
+        ev.decode(&[0x01, 0x01, b'a', 0x02, 0x01, 0x78, 0x00])?;
+        assert_eq!(ev.log_name, b"a");
+        assert_eq!(ev.log_pos, 0x78);
+
+        // truncated fields
+        assert!(HeartbeatEventV2::default()
+            .decode(&[0x01, 0x10, b'a'])
+            .is_err());
+        assert!(HeartbeatEventV2::default()
+            .decode(&[0x02, 0x09, 0xfe, 0x04])
+            .is_err());
+
+        Ok(())
+    }
 }
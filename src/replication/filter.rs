@@ -0,0 +1,131 @@
+use crate::error::ReplicationError;
+
+// ReplicationFilter holds the replication filter rules of a MySQL replica, see
+// https://dev.mysql.com/doc/refman/8.0/en/replication-rules.html
+//
+// The table rules are "db.table", the wild table rules are patterns of both parts, where `%` matches any
+// sequence of characters, `_` matches one character and `\` escapes them. The names are case sensitive.
+#[derive(Debug, Clone, Default)]
+pub struct ReplicationFilter {
+    // --replicate-do-db
+    pub do_db: Vec<String>,
+    // --replicate-ignore-db
+    pub ignore_db: Vec<String>,
+    // --replicate-do-table
+    pub do_table: Vec<String>,
+    // --replicate-ignore-table
+    pub ignore_table: Vec<String>,
+    // --replicate-wild-do-table
+    pub wild_do_table: Vec<String>,
+    // --replicate-wild-ignore-table
+    pub wild_ignore_table: Vec<String>,
+}
+
+impl ReplicationFilter {
+    pub fn new() -> ReplicationFilter {
+        ReplicationFilter::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.do_db.is_empty()
+            && self.ignore_db.is_empty()
+            && self.do_table.is_empty()
+            && self.ignore_table.is_empty()
+            && self.wild_do_table.is_empty()
+            && self.wild_ignore_table.is_empty()
+    }
+
+    // Validate checks that every table rule is "db.table".
+    pub fn validate(&self) -> Result<(), ReplicationError> {
+        for rule in self
+            .do_table
+            .iter()
+            .chain(&self.ignore_table)
+            .chain(&self.wild_do_table)
+            .chain(&self.wild_ignore_table)
+        {
+            if !rule.contains('.') {
+                return Err(ReplicationError::new(format!(
+                    "invalid table rule {}, it must be db.table",
+                    rule
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    // DbOk is Rpl_filter::db_ok, a statement of db is replicated if db is in do_db, or if there is no do_db
+    // and db is not in ignore_db. A statement without a default database is always replicated.
+    pub fn db_ok(&self, db: &[u8]) -> bool {
+        if db.is_empty() {
+            return true;
+        }
+
+        if !self.do_db.is_empty() {
+            return self.do_db.iter().any(|d| d.as_bytes() == db);
+        }
+
+        !self.ignore_db.iter().any(|d| d.as_bytes() == db)
+    }
+
+    // TableOk tells whether the changes of db.table are replicated, the database rules are checked first
+    // and then the table rules in the order of MySQL: do_table, ignore_table, wild_do_table and wild_ignore_table.
+    // A table matching no table rule is replicated only if there is no do rule.
+    pub fn table_ok(&self, db: &[u8], table: &[u8]) -> bool {
+        if !self.db_ok(db) {
+            return false;
+        }
+
+        let exact = |rule: &String| match rule.split_once('.') {
+            Some((d, t)) => d.as_bytes() == db && t.as_bytes() == table,
+            None => false,
+        };
+        let wild = |rule: &String| match rule.split_once('.') {
+            Some((d, t)) => wild_match(d.as_bytes(), db) && wild_match(t.as_bytes(), table),
+            None => false,
+        };
+
+        if self.do_table.iter().any(exact) {
+            return true;
+        }
+        if self.ignore_table.iter().any(exact) {
+            return false;
+        }
+        if self.wild_do_table.iter().any(wild) {
+            return true;
+        }
+        if self.wild_ignore_table.iter().any(wild) {
+            return false;
+        }
+
+        self.do_table.is_empty() && self.wild_do_table.is_empty()
+    }
+
+    // QueryOk tells whether a query event is replicated, it's matched on its schema. Like MySQL, the transaction
+    // control statements (BEGIN, COMMIT, ROLLBACK, SAVEPOINT and XA) are never filtered.
+    pub fn query_ok(&self, schema: &[u8], query: &[u8]) -> bool {
+        is_trans_keyword(query) || self.db_ok(schema)
+    }
+}
+
+fn is_trans_keyword(query: &[u8]) -> bool {
+    const KEYWORDS: [&[u8]; 5] = [b"BEGIN", b"COMMIT", b"ROLLBACK", b"SAVEPOINT", b"XA "];
+
+    KEYWORDS
+        .iter()
+        .any(|k| query.len() >= k.len() && query[..k.len()].eq_ignore_ascii_case(k))
+}
+
+// WildMatch matches name against a pattern of the wild table rules.
+pub fn wild_match(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some(b'%') => (0..=name.len()).any(|i| wild_match(&pattern[1..], &name[i..])),
+        Some(b'_') => !name.is_empty() && wild_match(&pattern[1..], &name[1..]),
+        Some(b'\\') if pattern.len() > 1 => {
+            name.first() == Some(&pattern[1]) && wild_match(&pattern[2..], &name[1..])
+        }
+        Some(c) => name.first() == Some(c) && wild_match(&pattern[1..], &name[1..]),
+    }
+}
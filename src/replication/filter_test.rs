@@ -0,0 +1,96 @@
+#[cfg(test)]
+mod tests {
+    use crate::replication::{wild_match, ReplicationFilter};
+
+    fn rules(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_wild_match() {
+        assert!(wild_match(b"db%", b"db"));
+        assert!(wild_match(b"db%", b"db_1"));
+        assert!(wild_match(b"%", b""));
+        assert!(wild_match(b"t_", b"t1"));
+        assert!(!wild_match(b"t_", b"t"));
+        assert!(!wild_match(b"t_", b"t12"));
+        assert!(wild_match(b"a%b%c", b"aXXbYYc"));
+        assert!(!wild_match(b"a%b%c", b"aXXbYY"));
+        assert!(wild_match(b"my\\_db", b"my_db"));
+        assert!(!wild_match(b"my\\_db", b"myXdb"));
+        assert!(!wild_match(b"Db", b"db"));
+    }
+
+    #[test]
+    fn test_db_ok() {
+        let f = ReplicationFilter::new();
+        assert!(f.is_empty());
+        assert!(f.db_ok(b"db1"));
+
+        let mut f = ReplicationFilter::new();
+        f.do_db = rules(&["db1"]);
+        // ignore_db is not checked if there is a do_db
+        f.ignore_db = rules(&["db1", "db2"]);
+        assert!(f.db_ok(b"db1"));
+        assert!(!f.db_ok(b"db2"));
+        assert!(!f.db_ok(b"db3"));
+        assert!(f.db_ok(b""));
+
+        let mut f = ReplicationFilter::new();
+        f.ignore_db = rules(&["db2"]);
+        assert!(f.db_ok(b"db1"));
+        assert!(!f.db_ok(b"db2"));
+    }
+
+    #[test]
+    fn test_table_ok() {
+        let mut f = ReplicationFilter::new();
+        f.do_table = rules(&["db1.t1"]);
+        f.ignore_table = rules(&["db1.t1", "db1.t2"]);
+        f.wild_do_table = rules(&["db%.log\\_%"]);
+        f.wild_ignore_table = rules(&["db2.%"]);
+        assert!(f.validate().is_ok());
+
+        // do_table comes before ignore_table
+        assert!(f.table_ok(b"db1", b"t1"));
+        assert!(!f.table_ok(b"db1", b"t2"));
+        // wild_do_table comes before wild_ignore_table
+        assert!(f.table_ok(b"db2", b"log_1"));
+        assert!(!f.table_ok(b"db2", b"logs"));
+        // no rule matches and there are do rules
+        assert!(!f.table_ok(b"db1", b"t3"));
+
+        let mut f = ReplicationFilter::new();
+        f.ignore_table = rules(&["db1.t1"]);
+        f.wild_ignore_table = rules(&["db2.tmp%"]);
+        assert!(!f.table_ok(b"db1", b"t1"));
+        assert!(!f.table_ok(b"db2", b"tmp_1"));
+        assert!(f.table_ok(b"db2", b"t1"));
+
+        // the database rules are checked first
+        f.ignore_db = rules(&["db3"]);
+        assert!(!f.table_ok(b"db3", b"t1"));
+    }
+
+    #[test]
+    fn test_query_ok() {
+        let mut f = ReplicationFilter::new();
+        f.ignore_db = rules(&["db1"]);
+        assert!(!f.query_ok(b"db1", b"INSERT INTO t VALUES (1)"));
+        assert!(f.query_ok(b"db2", b"INSERT INTO t VALUES (1)"));
+        assert!(f.query_ok(b"db1", b"BEGIN"));
+        assert!(f.query_ok(b"db1", b"commit"));
+        assert!(f.query_ok(b"db1", b"XA END 'x'"));
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut f = ReplicationFilter::new();
+        f.wild_ignore_table = rules(&["db%"]);
+        assert!(f
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .contains("invalid table rule db%"));
+    }
+}
@@ -1,16 +1,24 @@
 pub mod binlog_event;
 pub mod binlogstreamer;
+mod binlogstreamer_test;
 pub mod binlogsyncer;
+mod binlogsyncer_test;
+pub mod checkpoint;
+mod checkpoint_test;
 pub mod common;
 pub mod consts;
 pub mod decode_helper;
 pub mod event;
 pub mod event_enum;
 mod event_test;
+pub mod filter;
+mod filter_test;
 pub mod generic_event;
 pub mod json_binary;
 pub mod parser;
 mod parser_test;
+pub mod rewrite;
+mod rewrite_test;
 pub mod row_event;
 mod row_event_test;
 pub mod time;
@@ -20,11 +28,14 @@ pub mod transaction_payload_event;
 pub use binlog_event::*;
 pub use binlogstreamer::*;
 pub use binlogsyncer::*;
+pub use checkpoint::*;
 pub use consts::*;
 pub use event::*;
 pub use event_enum::*;
+pub use filter::*;
 pub use generic_event::*;
 pub use json_binary::*;
+pub use rewrite::*;
 pub use row_event::*;
 pub use time::*;
 pub use transaction_payload_event::*;
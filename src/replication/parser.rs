@@ -1,29 +1,63 @@
 use crate::error::{EventError, ReplicationError};
+use crate::mysql;
 use crate::replication::{
-    common, BeginLoadQueryEvent, BinlogEvent, Event, EventEnum, EventHeader, EventType,
-    ExecuteLoadQueryEvent, FormatDescriptionEvent, GTIDEvent, GenericEvent, IntVarEvent,
-    MariadbAnnotateRowsEvent, MariadbBinlogCheckPointEvent, MariadbGTIDEvent, MariadbGTIDListEvent,
-    PreviousGTIDsEvent, QueryEvent, RotateEvent, RowsEvent, RowsQueryEvent, TableMapEvent,
+    common, BeginLoadQueryEvent, BinlogEvent, BinlogEventRef, Event, EventEnum, EventHeader,
+    EventRef, EventType, ExecuteLoadQueryEvent, FormatDescriptionEvent, GTIDEvent, GenericEvent,
+    HeartbeatEvent, HeartbeatEventV2, IntVarEvent, MariadbAnnotateRowsEvent,
+    MariadbBinlogCheckPointEvent, MariadbGTIDEvent, MariadbGTIDListEvent, PreviousGTIDsEvent,
+    QueryEvent, QueryEventRef, ReplicationFilter, RewriteRules, RotateEvent, RotateEventRef,
+    RowsEvent, RowsEventRef, RowsQueryEvent, RowsQueryEventRef, TableMapEvent,
     TransactionPayloadEvent, XIDEvent, BINLOG_CHECKSUM_ALG_CRC32, BINLOG_CHECKSUM_LENGTH,
     BINLOG_FILE_HEADER, ERR_MISSING_TABLE_MAP_EVENT, EVENT_HEADER_SIZE, ROWS_EVENT_STMT_END_FLAG,
 };
 use byteorder::{LittleEndian, WriteBytesExt};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io;
 use std::io::{Read, Seek, SeekFrom};
+use std::ops::{Deref, DerefMut};
 use std::sync::atomic;
 use std::sync::atomic::Ordering;
+use std::sync::Arc;
 
 // ErrChecksumMismatch indicates binlog checksum mismatch.
 pub const ERR_CHECKSUM_MISMATCH: &str = "binlog checksum mismatch, data may be corrupted";
 
+// TableMaps is a HashMap of the table maps by table id, it takes a TableMapEvent as well as an Arc of one.
+#[derive(Debug, Default, Clone)]
+pub struct TableMaps(HashMap<u64, Arc<TableMapEvent>>);
+
+impl TableMaps {
+    pub fn insert<T: Into<Arc<TableMapEvent>>>(
+        &mut self,
+        table_id: u64,
+        table: T,
+    ) -> Option<Arc<TableMapEvent>> {
+        self.0.insert(table_id, table.into())
+    }
+}
+
+impl Deref for TableMaps {
+    type Target = HashMap<u64, Arc<TableMapEvent>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for TableMaps {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
 #[derive(Default)]
 pub struct BinlogParser {
     // "mysql" or "mariadb", if not set, use "mysql" by default
     pub flavor: String,
     pub format: Option<FormatDescriptionEvent>,
-    pub tables: HashMap<u64, TableMapEvent>,
+    // the table maps of the current statement, shared with its rows events
+    pub tables: TableMaps,
     // for rawMode, we only parse FormatDescriptionEvent and RotateEvent
     pub raw_mode: bool,
     pub parse_time: bool,
@@ -32,8 +66,17 @@ pub struct BinlogParser {
     pub stop_processing: atomic::AtomicU32,
     pub use_decimal: bool,
     pub ignore_json_decode_err: bool,
+    // keep the row bytes of the rows events undecoded, see RowsEvent::rows_iter
+    pub lazy_rows_decoding: bool,
     pub verify_checksum: bool,
     pub rows_event_decode_func: Option<common::RowsEventDecodeFunc>,
+    // the events it filters out are marked filtered and not passed to on_event
+    pub filter: ReplicationFilter,
+    // applied to the events passing the filter
+    pub rewrite_rules: RewriteRules,
+    // the ids of the filtered tables of the current statement, the filter matches the names of the source
+    // while the table maps in tables may be renamed
+    _filtered_tables: HashSet<u64>,
 }
 
 impl BinlogParser {
@@ -199,7 +242,7 @@ impl BinlogParser {
             )));
         }
 
-        let e = match self._parse_event(&h, body, &raw_data) {
+        let (e, filtered) = match self._parse_event(&h, body, &raw_data) {
             Ok(v) => v,
             Err(e) => {
                 if e.to_string() == ERR_MISSING_TABLE_MAP_EVENT {
@@ -208,11 +251,17 @@ impl BinlogParser {
                 return Err(e);
             }
         };
+        if filtered {
+            return Ok(false);
+        }
 
         on_event(&BinlogEvent {
             raw_data,
             header: Some(h),
             event: Some(e),
+            semi_sync_ack_pos: None,
+            checkpoint: None,
+            filtered,
         })?;
 
         Ok(false)
@@ -268,6 +317,10 @@ impl BinlogParser {
         self.ignore_json_decode_err = ignore_json_decode_err;
     }
 
+    pub fn set_lazy_rows_decoding(&mut self, lazy: bool) {
+        self.lazy_rows_decoding = lazy;
+    }
+
     pub fn set_verify_checksum(&mut self, verify: bool) {
         self.verify_checksum = verify;
     }
@@ -283,6 +336,14 @@ impl BinlogParser {
         self.rows_event_decode_func = rows_event_decode_func;
     }
 
+    pub fn set_filter(&mut self, filter: ReplicationFilter) {
+        self.filter = filter;
+    }
+
+    pub fn set_rewrite_rules(&mut self, rewrite_rules: RewriteRules) {
+        self.rewrite_rules = rewrite_rules;
+    }
+
     fn _parse_header(&self, data: &[u8]) -> Result<EventHeader, ReplicationError> {
         let mut h = EventHeader::default();
         h.decode(data)?;
@@ -295,18 +356,13 @@ impl BinlogParser {
         h: &EventHeader,
         data: &[u8],
         row_data: &[u8],
-    ) -> Result<EventEnum, ReplicationError> {
+    ) -> Result<(EventEnum, bool), ReplicationError> {
         let mut data = data;
 
         let mut e = if h.event_type == EventType::FormatDescriptionEvent {
             EventEnum::FormatDescriptionEvent(FormatDescriptionEvent::default())
         } else {
-            if let Some(format) = &self.format {
-                if format.check_sum_algorithm == BINLOG_CHECKSUM_ALG_CRC32 {
-                    self._verify_crc32_checksum(row_data)?;
-                    data = &data[..(data.len() - BINLOG_CHECKSUM_LENGTH)];
-                }
-            }
+            data = self._strip_checksum(data, row_data)?;
 
             if h.event_type == EventType::RotateEvent {
                 EventEnum::RotateEvent(RotateEvent::default())
@@ -347,7 +403,7 @@ impl BinlogParser {
                     | EventType::MariadbDeleteRowsCompressedEventV1
                     | EventType::PartialUpdateRowsEvent => {
                         // Extension of UPDATE_ROWS_EVENT, allowing partial values according to binlog_row_value_options
-                        EventEnum::RowsEvent(self._new_rows_event(h))
+                        EventEnum::RowsEvent(self._new_rows_event(h, data))
                     }
                     EventType::RowsQueryEvent => {
                         EventEnum::RowsQueryEvent(RowsQueryEvent::default())
@@ -383,6 +439,13 @@ impl BinlogParser {
                     EventType::TransactionPayloadEvent => {
                         EventEnum::TransactionPayloadEvent(self._new_transaction_payload_event())
                     }
+                    EventType::HeartbeatEvent => EventEnum::HeartbeatEvent(HeartbeatEvent {
+                        log_pos: h.log_pos as u64,
+                        ..Default::default()
+                    }),
+                    EventType::HeartbeatLogEventV2 => {
+                        EventEnum::HeartbeatEventV2(HeartbeatEventV2::default())
+                    }
                     _ => EventEnum::GenericEvent(GenericEvent::default()),
                 }
             } else {
@@ -390,8 +453,13 @@ impl BinlogParser {
             }
         };
 
+        let mut filtered = false;
         let rs = if let EventEnum::RowsEvent(ref mut re) = e {
-            if self.rows_event_decode_func.is_some() {
+            filtered = self._is_filtered_table(re.table.as_deref());
+            if filtered {
+                // the rows of a filtered table are not decoded
+                re.decode_header(data).map(|_| ())
+            } else if self.rows_event_decode_func.is_some() {
                 self.rows_event_decode_func.as_ref().unwrap()(re, &data)
             } else {
                 e.decode(&data)
@@ -411,18 +479,36 @@ impl BinlogParser {
             }));
         }
 
-        if let EventEnum::TableMapEvent(ref te) = e {
-            self.tables.insert(te.table_id, te.clone());
+        match &mut e {
+            EventEnum::TableMapEvent(te) => {
+                // the table map of a filtered table is kept, its rows events are recognized as filtered
+                filtered = !self.filter.table_ok(&te.schema, &te.table);
+                if filtered {
+                    self._filtered_tables.insert(te.table_id);
+                } else {
+                    self._filtered_tables.remove(&te.table_id);
+                    self.rewrite_rules.rewrite_table_map(te);
+                }
+                self.tables.insert(te.table_id, Arc::new(te.clone()));
+            }
+            EventEnum::QueryEvent(qe) => {
+                filtered = !self.filter.query_ok(&qe.schema, &qe.query);
+                if !filtered {
+                    self.rewrite_rules.rewrite_query(qe);
+                }
+            }
+            _ => {}
         }
 
         if let EventEnum::RowsEvent(ref re) = e {
             if (re.flags & ROWS_EVENT_STMT_END_FLAG as u16) > 0 {
                 // Refer https://github.com/alibaba/canal/blob/38cc81b7dab29b51371096fb6763ca3a8432ffee/dbsync/src/main/java/com/taobao/tddl/dbsync/binlog/event/RowsLogEvent.java#L176
-                self.tables = HashMap::<u64, TableMapEvent>::new();
+                self.tables.clear();
+                self._filtered_tables.clear();
             }
         }
 
-        Ok(e)
+        Ok((e, filtered))
     }
 
     // Parse: Given the bytes for a a binary log event: return the decoded event.
@@ -446,15 +532,165 @@ impl BinlogParser {
             )));
         }
 
-        let e = self._parse_event(&h, data, &raw_data)?;
+        let (e, filtered) = self._parse_event(&h, data, raw_data)?;
 
         return Ok(BinlogEvent {
             raw_data: raw_data.to_vec(),
             header: Some(h),
             event: Some(e),
+            semi_sync_ack_pos: None,
+            checkpoint: None,
+            filtered,
         });
     }
 
+    // ParseRef is Parse borrowing data, the query, rotate and rows events reference it instead of copying it
+    // and the rows of a rows event are only decoded by BinlogEventRef::to_owned.
+    // The other events are decoded as Parse does, the parser state is updated the same way.
+    pub fn parse_ref<'a>(
+        &mut self,
+        data: &'a [u8],
+    ) -> Result<BinlogEventRef<'a>, ReplicationError> {
+        let raw_data = data;
+        let h = self._parse_header(data)?;
+        let data = &data[EVENT_HEADER_SIZE..];
+        let event_len = h.event_size as isize - EVENT_HEADER_SIZE as isize;
+
+        if data.len() as isize != event_len {
+            return Err(ReplicationError::new(format!(
+                "invalid data size {} in event {}, less event length {}",
+                data.len(),
+                h.event_type,
+                event_len,
+            )));
+        }
+
+        let borrowed = match h.event_type {
+            EventType::RotateEvent => true,
+            EventType::QueryEvent | EventType::RowsQueryEvent => !self.raw_mode,
+            ref t => !self.raw_mode && Self::_is_rows_event(t),
+        };
+        if !borrowed {
+            let (e, filtered) = self._parse_event(&h, data, raw_data)?;
+            return Ok(BinlogEventRef {
+                raw_data,
+                header: h,
+                event: EventRef::Owned(Box::new(e)),
+                filtered,
+            });
+        }
+
+        let data = self._strip_checksum(data, raw_data)?;
+        let rs = match h.event_type {
+            EventType::RotateEvent => RotateEventRef::decode(data).map(EventRef::RotateEvent),
+            EventType::QueryEvent => QueryEventRef::decode(data).map(EventRef::QueryEvent),
+            EventType::RowsQueryEvent => {
+                RowsQueryEventRef::decode(data).map(EventRef::RowsQueryEvent)
+            }
+            _ => self
+                ._decode_rows_event_ref(&h, data)
+                .map(EventRef::RowsEvent),
+        };
+        let event = rs.map_err(|err| {
+            ReplicationError::EventError(EventError {
+                header: h.clone(),
+                err: err.to_string(),
+                data: data.to_vec(),
+            })
+        })?;
+
+        let filtered = match &event {
+            EventRef::QueryEvent(qe) => !self.filter.query_ok(qe.schema, qe.query),
+            EventRef::RowsEvent(re) => self._is_filtered_table(re.table.as_deref()),
+            _ => false,
+        };
+        if let EventRef::RowsEvent(re) = &event {
+            if (re.flags & ROWS_EVENT_STMT_END_FLAG as u16) > 0 {
+                self.tables.clear();
+                self._filtered_tables.clear();
+            }
+        }
+        // a renamed schema can't be borrowed
+        let event = match event {
+            EventRef::QueryEvent(qe)
+                if !filtered && self.rewrite_rules.rewrite_db(qe.schema).is_some() =>
+            {
+                let mut e = qe.to_owned();
+                self.rewrite_rules.rewrite_query(&mut e);
+                EventRef::Owned(Box::new(EventEnum::QueryEvent(e)))
+            }
+            e => e,
+        };
+
+        Ok(BinlogEventRef {
+            raw_data,
+            header: h,
+            event,
+            filtered,
+        })
+    }
+
+    fn _is_filtered_table(&self, table: Option<&TableMapEvent>) -> bool {
+        table.is_some_and(|t| self._filtered_tables.contains(&t.table_id))
+    }
+
+    fn _is_rows_event(t: &EventType) -> bool {
+        matches!(
+            t,
+            EventType::WriteRowsEventv0
+                | EventType::UpdateRowsEventv0
+                | EventType::DeleteRowsEventv0
+                | EventType::WriteRowsEventv1
+                | EventType::UpdateRowsEventv1
+                | EventType::DeleteRowsEventv1
+                | EventType::WriteRowsEventv2
+                | EventType::UpdateRowsEventv2
+                | EventType::DeleteRowsEventv2
+                | EventType::MariadbWriteRowsCompressedEventV1
+                | EventType::MariadbUpdateRowsCompressedEventV1
+                | EventType::MariadbDeleteRowsCompressedEventV1
+                | EventType::PartialUpdateRowsEvent
+        )
+    }
+
+    fn _decode_rows_event_ref<'a>(
+        &mut self,
+        h: &EventHeader,
+        data: &'a [u8],
+    ) -> Result<RowsEventRef<'a>, ReplicationError> {
+        let e = self._new_rows_event_ref(h, data);
+        if data.len() < e.table_id_size as usize + 2 {
+            return Err(ReplicationError::new(format!(
+                "invalid rows event, data size {} too small",
+                data.len()
+            )));
+        }
+        if e.table.is_none() {
+            return Err(ReplicationError::new(format!(
+                "{}, table id {}",
+                ERR_MISSING_TABLE_MAP_EVENT, e.table_id
+            )));
+        }
+
+        Ok(e)
+    }
+
+    // the event body without the checksum, the checksum is verified if verify_checksum is set
+    fn _strip_checksum<'a>(
+        &self,
+        data: &'a [u8],
+        raw_data: &[u8],
+    ) -> Result<&'a [u8], ReplicationError> {
+        if let Some(format) = &self.format {
+            if format.check_sum_algorithm == BINLOG_CHECKSUM_ALG_CRC32 {
+                self._verify_crc32_checksum(raw_data)?;
+                return Ok(&data[..(data.len() - BINLOG_CHECKSUM_LENGTH)]);
+            }
+        }
+
+        Ok(data)
+    }
+
     fn _verify_crc32_checksum(&self, raw_data: &[u8]) -> Result<(), ReplicationError> {
         if !self.verify_checksum {
             return Ok(());
@@ -477,58 +713,38 @@ impl BinlogParser {
         Ok(())
     }
 
-    fn _new_rows_event(&self, h: &EventHeader) -> RowsEvent {
-        let mut e = RowsEvent::default();
+    fn _new_rows_event(&self, h: &EventHeader, data: &[u8]) -> RowsEvent {
+        self._new_rows_event_ref(h, data).new_rows_event()
+    }
+
+    fn _new_rows_event_ref<'a>(&self, h: &EventHeader, data: &'a [u8]) -> RowsEventRef<'a> {
         let post_header_len = self.format.as_ref().unwrap().event_type_header_lengths
             [(h.event_type.clone() as usize) - 1];
-        if post_header_len == 6 {
-            e.table_id_size = 4
-        } else {
-            e.table_id_size = 6
-        }
+        let table_id_size = if post_header_len == 6 { 4 } else { 6 };
+
+        let mut e = RowsEventRef {
+            event_type: h.event_type.clone(),
+            table_id_size,
+            table: None,
+            table_id: 0,
+            flags: 0,
+            data,
+            parse_time: self.parse_time,
+            timestamp_string_location: self.timestamp_string_location,
+            use_decimal: self.use_decimal,
+            ignore_json_decode_err: self.ignore_json_decode_err,
+            lazy_rows: self.lazy_rows_decoding,
+            decode_func: self.rows_event_decode_func.clone(),
+        };
 
-        e.need_bitmap2 = false;
-        e.tables = self.tables.clone();
-        e.event_type = h.event_type.clone();
-        e.parse_time = self.parse_time;
-        e.timestamp_string_location = self.timestamp_string_location.clone();
-        e.use_decimal = self.use_decimal;
-        e.ignore_json_decode_err = self.ignore_json_decode_err;
-
-        match h.event_type {
-            EventType::WriteRowsEventv0 => e.version = 0,
-            EventType::UpdateRowsEventv0 => e.version = 0,
-            EventType::DeleteRowsEventv0 => e.version = 0,
-            EventType::WriteRowsEventv1 => e.version = 1,
-            EventType::UpdateRowsEventv1 => {
-                e.version = 1;
-                e.need_bitmap2 = true;
-            }
-            EventType::DeleteRowsEventv1 => e.version = 1,
-            EventType::WriteRowsEventv2 => e.version = 2,
-            EventType::UpdateRowsEventv2 => {
-                e.version = 2;
-                e.need_bitmap2 = true;
-            }
-            EventType::DeleteRowsEventv2 => e.version = 2,
-            EventType::MariadbWriteRowsCompressedEventV1 => {
-                e.version = 1;
-                e.compressed = true;
-            }
-            EventType::MariadbUpdateRowsCompressedEventV1 => {
-                e.version = 1;
-                e.compressed = true;
-                e.need_bitmap2 = true;
-            }
-            EventType::MariadbDeleteRowsCompressedEventV1 => {
-                e.version = 1;
-                e.compressed = true;
-            }
-            EventType::PartialUpdateRowsEvent => {
-                e.version = 2;
-                e.need_bitmap2 = true;
-            }
-            _ => {}
+        // the table id and the flags lead the post header, RowsEvent::decode_header fails if the table map is missing
+        let n = table_id_size as usize;
+        if data.len() >= n {
+            e.table_id = mysql::fixed_length_int(&data[..n]);
+            e.table = self.tables.get(&e.table_id).cloned();
+        }
+        if data.len() >= n + 2 {
+            e.flags = u16::from_le_bytes([data[n], data[n + 1]]);
         }
 
         e
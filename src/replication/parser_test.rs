@@ -4,10 +4,11 @@ mod tests {
     use crate::error::ReplicationError;
     use crate::replication::parser::BinlogParser;
     use crate::replication::{
-        EnumRowImageType, EventType, FormatDescriptionEvent, RowsEvent, TableMapEvent,
+        EnumRowImageType, EventEnum, EventRef, EventType, FormatDescriptionEvent,
+        ReplicationFilter, RewriteRules, RowsEvent, TableMapEvent, ERR_MISSING_TABLE_MAP_EVENT,
     };
     use std::io::BufReader;
-    use std::rc::Rc;
+    use std::sync::Arc;
 
     #[test]
     fn test_index_out_of_range() -> Result<(), ReplicationError> {
@@ -305,6 +306,44 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_parse_heartbeat_event() -> Result<(), ReplicationError> {
+        let mut parser = BinlogParser::new();
+
+        // HeartbeatEvent at position 0x04d2 of mysql-bin.000002
+        let mut data = vec![
+            0x00, 0x00, 0x00, 0x00, 0x1b, 0x65, 0x00, 0x00, 0x00, 0x23, 0x00, 0x00, 0x00, 0xd2,
+            0x04, 0x00, 0x00, 0x00, 0x00,
+        ];
+        data.extend(b"mysql-bin.000002");
+        let e = parser.parse(&data)?;
+        match &e.event {
+            Some(EventEnum::HeartbeatEvent(ev)) => {
+                assert_eq!(ev.log_name, b"mysql-bin.000002");
+                assert_eq!(ev.log_pos, 0x04d2);
+            }
+            _ => panic!("not a heartbeat event: {:?}", e.event),
+        }
+
+        // HeartbeatLogEventV2, the position is in the body
+        let mut data = vec![
+            0x00, 0x00, 0x00, 0x00, 0x29, 0x65, 0x00, 0x00, 0x00, 0x29, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x10,
+        ];
+        data.extend(b"mysql-bin.000002");
+        data.extend([0x02, 0x01, 0x78, 0x00]);
+        let e = parser.parse(&data)?;
+        match &e.event {
+            Some(EventEnum::HeartbeatEventV2(ev)) => {
+                assert_eq!(ev.log_name, b"mysql-bin.000002");
+                assert_eq!(ev.log_pos, 0x78);
+            }
+            _ => panic!("not a heartbeat v2 event: {:?}", e.event),
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn test_rows_event_decode_func() -> Result<(), ReplicationError> {
         struct Case {
@@ -376,7 +415,7 @@ mod tests {
         ];
 
         let mut parser = BinlogParser::new();
-        parser.set_rows_event_decode_func(Some(Rc::new(|re, bs| {
+        parser.set_rows_event_decode_func(Some(Arc::new(|re, bs| {
             let _ = re.decode_header(bs)?;
 
             Ok(())
@@ -391,6 +430,249 @@ mod tests {
         Ok(())
     }
 
+    fn event_data(event_type: u8, body: &[u8]) -> Vec<u8> {
+        let mut data = vec![0x00, 0x00, 0x00, 0x00, event_type, 0x01, 0x00, 0x00, 0x00];
+        data.extend((19 + body.len() as u32).to_le_bytes());
+        data.extend([0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        data.extend(body);
+        data
+    }
+
+    #[test]
+    fn test_parse_ref() -> Result<(), ReplicationError> {
+        let mut parser = BinlogParser::new();
+
+        // the query event borrows the schema and the query
+        let mut body = vec![
+            0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00,
+        ];
+        body.extend([0x00, 0x00]);
+        body.extend(b"db\0BEGIN");
+        let data = event_data(0x02, &body);
+        let e = parser.parse_ref(&data)?;
+        assert_eq!(EventType::QueryEvent, e.header.event_type);
+        match &e.event {
+            EventRef::QueryEvent(q) => {
+                assert_eq!(q.schema, b"db");
+                assert_eq!(q.query, b"BEGIN");
+                assert!(std::ptr::eq(
+                    q.query.as_ptr(),
+                    data[data.len() - 5..].as_ptr()
+                ));
+            }
+            _ => panic!("not a query event: {:?}", e.event),
+        }
+        let owned = e.to_owned()?;
+        assert_eq!(owned.raw_data, data);
+        match &owned.event {
+            Some(EventEnum::QueryEvent(q)) => {
+                assert_eq!(q.slave_proxy_id, 1);
+                assert_eq!(q.query, b"BEGIN");
+            }
+            _ => panic!("not a query event: {:?}", owned.event),
+        }
+
+        // the rotate event borrows the next log name
+        let mut body = 4_u64.to_le_bytes().to_vec();
+        body.extend(b"mysql-bin.000002");
+        let data = event_data(0x04, &body);
+        match parser.parse_ref(&data)?.event {
+            EventRef::RotateEvent(r) => {
+                assert_eq!(r.position, 4);
+                assert_eq!(r.next_log_name, b"mysql-bin.000002");
+            }
+            e => panic!("not a rotate event: {:?}", e),
+        }
+
+        // FORMAT_DESCRIPTION_EVENT, TABLE MAP EVENT db.tbl(INT) and rows INT(1) with CRC32 checksums
+        let events = vec![
+            vec![
+                0x64, 0x61, 0x72, 0x63, 0xf, 0xb, 0x0, 0x0, 0x0, 0x77, 0x0, 0x0, 0x0, 0x7b, 0x0,
+                0x0, 0x0, 0x1, 0x0, 0x4, 0x0, 0x35, 0x2e, 0x37, 0x2e, 0x32, 0x32, 0x2d, 0x6c, 0x6f,
+                0x67, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+                0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+                0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x64, 0x61, 0x72, 0x63, 0x13, 0x38,
+                0xd, 0x0, 0x8, 0x0, 0x12, 0x0, 0x4, 0x4, 0x4, 0x4, 0x12, 0x0, 0x0, 0x5f, 0x0, 0x4,
+                0x1a, 0x8, 0x0, 0x0, 0x0, 0x8, 0x8, 0x8, 0x2, 0x0, 0x0, 0x0, 0xa, 0xa, 0xa, 0x2a,
+                0x2a, 0x0, 0x12, 0x34, 0x0, 0x1, 0xb8, 0x78, 0x9d, 0xfe,
+            ],
+            vec![
+                0x8d, 0x61, 0x72, 0x63, 0x13, 0xb, 0x0, 0x0, 0x0, 0x2c, 0x0, 0x0, 0x0, 0xa7, 0x0,
+                0x0, 0x0, 0x1, 0x0, 0x6c, 0x0, 0x0, 0x0, 0x0, 0x0, 0x1, 0x0, 0x2, 0x64, 0x62, 0x0,
+                0x3, 0x74, 0x62, 0x6c, 0x0, 0x1, 0x3, 0x0, 0x0, 0x63, 0x17, 0xe6, 0xf0,
+            ],
+        ];
+        let rows = vec![
+            0xb6, 0x61, 0x72, 0x63, 0x1e, 0xb, 0x0, 0x0, 0x0, 0x28, 0x0, 0x0, 0x0, 0xcf, 0x0, 0x0,
+            0x0, 0x1, 0x0, 0x6c, 0x0, 0x0, 0x0, 0x0, 0x0, 0x1, 0x0, 0x2, 0x0, 0x1, 0xff, 0x0, 0x1,
+            0x0, 0x0, 0x0, 0xf9, 0xf7, 0x89, 0x2a,
+        ];
+
+        let mut parser = BinlogParser::new();
+        parser.set_verify_checksum(true);
+        let mut expected = BinlogParser::new();
+        for data in &events {
+            match parser.parse_ref(data)?.event {
+                EventRef::Owned(_) => {}
+                e => panic!("not an owned event: {:?}", e),
+            }
+            let _ = expected.parse(data)?;
+        }
+
+        let e = parser.parse_ref(&rows)?;
+        let re = match &e.event {
+            EventRef::RowsEvent(re) => re,
+            _ => panic!("not a rows event: {:?}", e.event),
+        };
+        assert_eq!(re.table_id, 0x6c);
+        assert_eq!(re.flags, 1);
+        assert_eq!(re.table.as_ref().unwrap().schema, b"db");
+        assert_eq!(re.table.as_ref().unwrap().table, b"tbl");
+        // the checksum is not part of the body
+        assert_eq!(re.data.len(), rows.len() - 19 - 4);
+        // the statement ended, its table maps are dropped as parse does
+        assert!(parser.tables.is_empty());
+
+        let owned = e.to_owned()?;
+        let expected = expected.parse(&rows)?;
+        match (&owned.event, &expected.event) {
+            (Some(EventEnum::RowsEvent(re)), Some(EventEnum::RowsEvent(expected))) => {
+                assert_eq!(re.rows, expected.rows);
+                assert_eq!(re.rows, vec![vec![DecodeFieldData::Isize(1)]]);
+            }
+            _ => panic!("not a rows event: {:?}", owned.event),
+        }
+
+        // the table map of the rows event is gone
+        let err = parser.parse_ref(&rows).unwrap_err();
+        assert!(err.to_string().contains(ERR_MISSING_TABLE_MAP_EVENT));
+
+        // the rows are decoded by to_owned with lazy rows decoding too
+        let mut parser = BinlogParser::new();
+        parser.set_lazy_rows_decoding(true);
+        for data in &events {
+            let _ = parser.parse_ref(data)?;
+        }
+        match parser.parse_ref(&rows)?.to_owned()?.event {
+            Some(EventEnum::RowsEvent(re)) => {
+                assert_eq!(re.rows, vec![vec![DecodeFieldData::Isize(1)]]);
+                assert!(re.rows_data.is_empty());
+            }
+            e => panic!("not a rows event: {:?}", e),
+        }
+
+        Ok(())
+    }
+
+    // a format description event, the table map of db.tbl and a write rows event of it
+    fn table_events() -> (Vec<Vec<u8>>, Vec<u8>) {
+        let events = vec![
+            vec![
+                0x64, 0x61, 0x72, 0x63, 0xf, 0xb, 0x0, 0x0, 0x0, 0x77, 0x0, 0x0, 0x0, 0x7b, 0x0,
+                0x0, 0x0, 0x1, 0x0, 0x4, 0x0, 0x35, 0x2e, 0x37, 0x2e, 0x32, 0x32, 0x2d, 0x6c, 0x6f,
+                0x67, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+                0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+                0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x64, 0x61, 0x72, 0x63, 0x13, 0x38,
+                0xd, 0x0, 0x8, 0x0, 0x12, 0x0, 0x4, 0x4, 0x4, 0x4, 0x12, 0x0, 0x0, 0x5f, 0x0, 0x4,
+                0x1a, 0x8, 0x0, 0x0, 0x0, 0x8, 0x8, 0x8, 0x2, 0x0, 0x0, 0x0, 0xa, 0xa, 0xa, 0x2a,
+                0x2a, 0x0, 0x12, 0x34, 0x0, 0x1, 0xb8, 0x78, 0x9d, 0xfe,
+            ],
+            vec![
+                0x8d, 0x61, 0x72, 0x63, 0x13, 0xb, 0x0, 0x0, 0x0, 0x2c, 0x0, 0x0, 0x0, 0xa7, 0x0,
+                0x0, 0x0, 0x1, 0x0, 0x6c, 0x0, 0x0, 0x0, 0x0, 0x0, 0x1, 0x0, 0x2, 0x64, 0x62, 0x0,
+                0x3, 0x74, 0x62, 0x6c, 0x0, 0x1, 0x3, 0x0, 0x0, 0x63, 0x17, 0xe6, 0xf0,
+            ],
+        ];
+        let rows = vec![
+            0xb6, 0x61, 0x72, 0x63, 0x1e, 0xb, 0x0, 0x0, 0x0, 0x28, 0x0, 0x0, 0x0, 0xcf, 0x0, 0x0,
+            0x0, 0x1, 0x0, 0x6c, 0x0, 0x0, 0x0, 0x0, 0x0, 0x1, 0x0, 0x2, 0x0, 0x1, 0xff, 0x0, 0x1,
+            0x0, 0x0, 0x0, 0xf9, 0xf7, 0x89, 0x2a,
+        ];
+
+        (events, rows)
+    }
+
+    #[test]
+    fn test_filter() -> Result<(), ReplicationError> {
+        let (events, rows) = table_events();
+
+        let mut filter = ReplicationFilter::new();
+        filter.ignore_table = vec!["db.tbl".to_string()];
+        let mut parser = BinlogParser::new();
+        parser.set_filter(filter);
+
+        assert!(!parser.parse(&events[0])?.filtered);
+        // the table map of the ignored table is kept for its rows events
+        assert!(parser.parse(&events[1])?.filtered);
+        assert!(parser.tables.contains_key(&0x6c));
+        let e = parser.parse(&rows)?;
+        assert!(e.filtered);
+        match &e.event {
+            Some(EventEnum::RowsEvent(re)) => {
+                assert_eq!(re.table_id, 0x6c);
+                assert!(re.rows.is_empty());
+            }
+            _ => panic!("not a rows event: {:?}", e.event),
+        }
+
+        parser.parse(&events[1])?;
+        assert!(parser.parse_ref(&rows)?.filtered);
+
+        // the filtered events are not passed to on_event
+        let mut data = vec![];
+        for e in events.iter().chain([&rows]) {
+            data.extend(e);
+        }
+        let seen = std::cell::RefCell::new(vec![]);
+        parser.parse_reader(&mut BufReader::new(&*data), &|e| {
+            seen.borrow_mut()
+                .push(e.header.as_ref().unwrap().event_type.clone());
+            Ok(())
+        })?;
+        assert_eq!(seen.into_inner(), vec![EventType::FormatDescriptionEvent]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rewrite_rules() -> Result<(), ReplicationError> {
+        let (events, rows) = table_events();
+
+        let mut filter = ReplicationFilter::new();
+        filter.do_table = vec!["db.tbl".to_string()];
+        let mut rules = RewriteRules::new();
+        rules.add_db_rule("db", "db_new");
+        rules.add_table_rule(r"^db_new\.(.*)$", "target.${1}_new")?;
+        let mut parser = BinlogParser::new();
+        parser.set_filter(filter);
+        parser.set_rewrite_rules(rules);
+
+        parser.parse(&events[0])?;
+        // the filter is matched against the names of the source
+        let e = parser.parse(&events[1])?;
+        assert!(!e.filtered);
+        match &e.event {
+            Some(EventEnum::TableMapEvent(te)) => {
+                assert_eq!(te.schema, b"target");
+                assert_eq!(te.table, b"tbl_new");
+            }
+            _ => panic!("not a table map event: {:?}", e.event),
+        }
+        let e = parser.parse(&rows)?;
+        assert!(!e.filtered);
+        match &e.event {
+            Some(EventEnum::RowsEvent(re)) => {
+                let table = re.table.as_ref().unwrap();
+                assert_eq!(table.schema, b"target");
+                assert_eq!(table.table, b"tbl_new");
+                assert_eq!(re.rows.len(), 1);
+            }
+            _ => panic!("not a rows event: {:?}", e.event),
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn test_rows_event_decode_image_with_empty_json() -> Result<(), ReplicationError> {
         let data = vec![
@@ -405,7 +687,7 @@ mod tests {
 
         let mut e = RowsEvent::default();
         e.event_type = EventType::PartialUpdateRowsEvent;
-        e.table = table.clone();
+        e.table = Some(Arc::new(table.clone()));
         e.column_count = table.column_type.len() as u64;
         let n = e.decode_image(&data, &bitmap, EnumRowImageType::UpdateAI)?;
         assert_eq!(data.len(), n as usize);
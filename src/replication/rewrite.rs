@@ -0,0 +1,89 @@
+use crate::error::ReplicationError;
+use crate::replication::{QueryEvent, TableMapEvent};
+use regex::bytes::Regex;
+
+// RewriteRules renames the databases and tables of the events, like --replicate-rewrite-db of a MySQL replica.
+// The parser applies them after the replication filter, so the filter rules use the names of the source.
+#[derive(Debug, Clone, Default)]
+pub struct RewriteRules {
+    // the database from is renamed to to, the first matching rule wins
+    pub db_rules: Vec<(String, String)>,
+
+    // the table rules are matched against "db.table" after the database rules, the first matching one
+    // replaces it like Regex::replace and its result is split at the first '.' into the new database and table.
+    // A result without '.' only renames the table.
+    pub table_rules: Vec<(Regex, String)>,
+}
+
+impl RewriteRules {
+    pub fn new() -> RewriteRules {
+        RewriteRules::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.db_rules.is_empty() && self.table_rules.is_empty()
+    }
+
+    pub fn add_db_rule(&mut self, from: &str, to: &str) {
+        self.db_rules.push((from.to_string(), to.to_string()));
+    }
+
+    // AddTableRule adds a table rule, replacement may refer to the groups of pattern, e.g.
+    // add_table_rule(r"^shard_\d+\.(orders|users)_\d+$", "main.$1").
+    pub fn add_table_rule(
+        &mut self,
+        pattern: &str,
+        replacement: &str,
+    ) -> Result<(), ReplicationError> {
+        let re = Regex::new(pattern)
+            .map_err(|e| ReplicationError::new(format!("invalid table rule {}: {}", pattern, e)))?;
+        self.table_rules.push((re, replacement.to_string()));
+
+        Ok(())
+    }
+
+    // RewriteDb returns the new name of db, none if no rule renames it.
+    pub fn rewrite_db(&self, db: &[u8]) -> Option<&str> {
+        self.db_rules
+            .iter()
+            .find(|(from, _)| from.as_bytes() == db)
+            .map(|(_, to)| to.as_str())
+    }
+
+    // RewriteTable returns the new database and table of db.table, none if no rule renames them.
+    pub fn rewrite_table(&self, db: &[u8], table: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+        let renamed = self.rewrite_db(db);
+        let db = renamed.map_or(db, |to| to.as_bytes());
+
+        if !self.table_rules.is_empty() {
+            let mut name = db.to_vec();
+            name.push(b'.');
+            name.extend(table);
+            if let Some((re, replacement)) =
+                self.table_rules.iter().find(|(re, _)| re.is_match(&name))
+            {
+                let name = re.replace(&name, replacement.as_bytes());
+                return Some(match name.iter().position(|c| *c == b'.') {
+                    Some(i) => (name[..i].to_vec(), name[i + 1..].to_vec()),
+                    None => (db.to_vec(), name.to_vec()),
+                });
+            }
+        }
+
+        renamed.map(|to| (to.as_bytes().to_vec(), table.to_vec()))
+    }
+
+    pub fn rewrite_table_map(&self, e: &mut TableMapEvent) {
+        if let Some((schema, table)) = self.rewrite_table(&e.schema, &e.table) {
+            e.schema = schema;
+            e.table = table;
+        }
+    }
+
+    // RewriteQuery renames the default database of the query, the statement itself is not rewritten.
+    pub fn rewrite_query(&self, e: &mut QueryEvent) {
+        if let Some(to) = self.rewrite_db(&e.schema) {
+            e.schema = to.as_bytes().to_vec();
+        }
+    }
+}
@@ -0,0 +1,72 @@
+#[cfg(test)]
+mod tests {
+    use crate::error::ReplicationError;
+    use crate::replication::{QueryEvent, RewriteRules, TableMapEvent};
+
+    #[test]
+    fn test_rewrite_db() {
+        let mut rules = RewriteRules::new();
+        assert!(rules.is_empty());
+        rules.add_db_rule("db1", "db2");
+        assert!(!rules.is_empty());
+
+        assert_eq!(rules.rewrite_db(b"db1"), Some("db2"));
+        assert_eq!(rules.rewrite_db(b"db3"), None);
+        assert_eq!(
+            rules.rewrite_table(b"db1", b"t"),
+            Some((b"db2".to_vec(), b"t".to_vec()))
+        );
+        assert_eq!(rules.rewrite_table(b"db3", b"t"), None);
+
+        let mut e = QueryEvent {
+            schema: b"db1".to_vec(),
+            query: b"INSERT INTO db1.t VALUES (1)".to_vec(),
+            ..Default::default()
+        };
+        rules.rewrite_query(&mut e);
+        assert_eq!(e.schema, b"db2");
+        // the statement is kept as it is
+        assert_eq!(e.query, b"INSERT INTO db1.t VALUES (1)");
+    }
+
+    #[test]
+    fn test_rewrite_table() -> Result<(), ReplicationError> {
+        let mut rules = RewriteRules::new();
+        rules.add_db_rule("legacy", "shard_0");
+        rules.add_table_rule(r"^shard_\d+\.(orders|users)_\d+$", "main.$1")?;
+        // no '.' in the result, only the table is renamed
+        rules.add_table_rule(r"^shard_\d+\.tmp_(.*)$", "$1")?;
+
+        assert_eq!(
+            rules.rewrite_table(b"shard_3", b"orders_12"),
+            Some((b"main".to_vec(), b"orders".to_vec()))
+        );
+        // the table rules see the renamed database
+        assert_eq!(
+            rules.rewrite_table(b"legacy", b"users_1"),
+            Some((b"main".to_vec(), b"users".to_vec()))
+        );
+        assert_eq!(
+            rules.rewrite_table(b"shard_3", b"tmp_log"),
+            Some((b"shard_3".to_vec(), b"log".to_vec()))
+        );
+        assert_eq!(rules.rewrite_table(b"shard_3", b"items_1"), None);
+
+        let mut e = TableMapEvent::default();
+        e.schema = b"shard_1".to_vec();
+        e.table = b"orders_7".to_vec();
+        rules.rewrite_table_map(&mut e);
+        assert_eq!(e.schema, b"main");
+        assert_eq!(e.table, b"orders");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_table_rule() {
+        let mut rules = RewriteRules::new();
+        let err = rules.add_table_rule("shard_(", "main").unwrap_err();
+        assert!(err.to_string().contains("invalid table rule shard_("));
+        assert!(rules.is_empty());
+    }
+}
@@ -3,7 +3,7 @@ use crate::common::row_fields::{
 };
 use crate::error::ReplicationError;
 use crate::mysql::ParseBinary;
-use crate::replication::{decode_helper, Event, EventType, FracTime, JsonBinaryDecoder};
+use crate::replication::{common, decode_helper, Event, EventType, FracTime, JsonBinaryDecoder};
 use crate::{mysql, replication, utils};
 use byteorder::{LittleEndian, ReadBytesExt};
 use chrono::{NaiveDate, NaiveDateTime};
@@ -11,7 +11,7 @@ use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter};
 use std::io;
 use std::io::{Cursor, Seek, SeekFrom, Write};
-use std::rc::Rc;
+use std::sync::Arc;
 
 pub const ERR_MISSING_TABLE_MAP_EVENT: &str = "invalid table id, no corresponding table map event";
 
@@ -890,6 +890,7 @@ pub struct RowsEvent {
     pub version: isize,
 
     pub table_id_size: isize,
+    // the table maps to look table_id up in when table is not set, the parser sets table instead
     pub tables: HashMap<u64, TableMapEvent>,
     pub need_bitmap2: bool,
 
@@ -898,7 +899,8 @@ pub struct RowsEvent {
 
     pub event_type: EventType,
 
-    pub table: TableMapEvent,
+    // the table map of table_id, the parser shares it with all the rows events of the table
+    pub table: Option<Arc<TableMapEvent>>,
 
     pub table_id: u64,
 
@@ -919,11 +921,11 @@ pub struct RowsEvent {
         ColumnBitmap1, ColumnBitmap2 and SkippedColumns are not set on the full row image.
     */
     // len = (ColumnCount + 7) / 8
-    pub column_bitmap1: Rc<Vec<u8>>,
+    pub column_bitmap1: Arc<Vec<u8>>,
 
     // if UPDATE_ROWS_EVENTv1 or v2, or PARTIAL_UPDATE_ROWS_EVENT
     // len = (ColumnCount + 7) / 8
-    pub column_bitmap2: Rc<Vec<u8>>,
+    pub column_bitmap2: Arc<Vec<u8>>,
 
     // rows: all return types from RowsEvent.decodeValue()
     pub rows: Vec<Vec<DecodeFieldData>>,
@@ -933,6 +935,11 @@ pub struct RowsEvent {
     pub timestamp_string_location: Option<chrono_tz::Tz>,
     pub use_decimal: bool,
     pub ignore_json_decode_err: bool,
+
+    // if set, decode keeps the row bytes in rows_data instead of decoding rows, see rows_iter
+    pub lazy_rows: bool,
+    // the undecoded rows, uncompressed for mariadb *_COMPRESSED_EVENT_V1
+    pub rows_data: Vec<u8>,
 }
 
 impl Event for RowsEvent {
@@ -957,8 +964,16 @@ impl Event for RowsEvent {
         let pos = self.decode_header(data)?;
         if self.compressed {
             let uncompressed_data = mysql::decompress_mariadb_data(&data[pos as usize..])?;
+            if self.lazy_rows {
+                self.rows_data = uncompressed_data;
+                return Ok(());
+            }
             return self.decode_data(0, &uncompressed_data);
         }
+        if self.lazy_rows {
+            self.rows_data = data[pos as usize..].to_vec();
+            return Ok(());
+        }
         self.decode_data(pos, data)
     }
 }
@@ -1040,20 +1055,20 @@ impl RowsEvent {
         let bit_count = bitmap_byte_size(self.column_count as isize);
         let start = rdr.position() as usize;
         let stop = start + bit_count as usize;
-        self.column_bitmap1 = Rc::new(data[start..stop].to_vec());
+        self.column_bitmap1 = Arc::new(data[start..stop].to_vec());
         rdr.seek(SeekFrom::Current(bit_count as i64))?;
 
         if self.need_bitmap2 {
             let start = rdr.position() as usize;
             let stop = start + bit_count as usize;
-            self.column_bitmap2 = Rc::new(data[start..stop].to_vec());
+            self.column_bitmap2 = Arc::new(data[start..stop].to_vec());
             rdr.seek(SeekFrom::Current(bit_count as i64))?;
         }
 
-        if let Some(table) = self.tables.get(&self.table_id) {
-            self.table = table.clone()
-        } else {
-            if self.tables.len() > 0 {
+        if self.table.is_none() {
+            if let Some(table) = self.tables.get(&self.table_id) {
+                self.table = Some(Arc::new(table.clone()))
+            } else if !self.tables.is_empty() {
                 return Err(ReplicationError::new(format!(
                     "invalid table id {}, no corresponding table map event",
                     self.table_id
@@ -1080,17 +1095,7 @@ impl RowsEvent {
         self.skipped_columns = Vec::<Vec<isize>>::with_capacity(rows_len);
         self.rows = Vec::<Vec<DecodeFieldData>>::with_capacity(rows_len);
 
-        let row_image_type = match self.event_type {
-            EventType::WriteRowsEventv0
-            | EventType::WriteRowsEventv1
-            | EventType::WriteRowsEventv2
-            | EventType::MariadbWriteRowsCompressedEventV1 => EnumRowImageType::WriteAI,
-            EventType::DeleteRowsEventv0
-            | EventType::DeleteRowsEventv1
-            | EventType::DeleteRowsEventv2
-            | EventType::MariadbDeleteRowsCompressedEventV1 => EnumRowImageType::DeleteBI,
-            _ => EnumRowImageType::UpdateBI,
-        };
+        let row_image_type = self._row_image_type();
 
         let mut pos = pos as usize;
         while pos < data.len() {
@@ -1116,6 +1121,31 @@ impl RowsEvent {
         Ok(())
     }
 
+    // the type of the first image of a row, the second one of an update row is UpdateAI
+    fn _row_image_type(&self) -> EnumRowImageType {
+        match self.event_type {
+            EventType::WriteRowsEventv0
+            | EventType::WriteRowsEventv1
+            | EventType::WriteRowsEventv2
+            | EventType::MariadbWriteRowsCompressedEventV1 => EnumRowImageType::WriteAI,
+            EventType::DeleteRowsEventv0
+            | EventType::DeleteRowsEventv1
+            | EventType::DeleteRowsEventv2
+            | EventType::MariadbDeleteRowsCompressedEventV1 => EnumRowImageType::DeleteBI,
+            _ => EnumRowImageType::UpdateBI,
+        }
+    }
+
+    // RowsIter decodes the rows kept in rows_data one at a time, with the after image for the update events.
+    // It yields nothing unless the event was decoded with lazy_rows, the rows are in rows then.
+    pub fn rows_iter(&self) -> RowsIter<'_> {
+        RowsIter {
+            _event: self,
+            _pos: 0,
+            _done: false,
+        }
+    }
+
     fn _is_bit_set(&self, bitmap: &[u8], i: isize) -> bool {
         bitmap[i as usize >> 3] & (1 << ((i as usize) & 7)) > 0
     }
@@ -1133,7 +1163,24 @@ impl RowsEvent {
         bitmap: &[u8],
         row_image_type: EnumRowImageType,
     ) -> Result<isize, ReplicationError> {
+        let (image, n) = self._decode_image(data, bitmap, row_image_type)?;
+        self.rows.push(image.row);
+        self.skipped_columns.push(image.skipped_columns);
+
+        Ok(n)
+    }
+
+    fn _decode_image(
+        &self,
+        data: &[u8],
+        bitmap: &[u8],
+        row_image_type: EnumRowImageType,
+    ) -> Result<(RowImage, isize), ReplicationError> {
         // Rows_log_event::print_verbose_one_row()
+        let table = self.table.clone().ok_or(ReplicationError::new(format!(
+            "{}, table id {}",
+            ERR_MISSING_TABLE_MAP_EVENT, self.table_id
+        )))?;
         let mut pos = 0;
         let is_partial_json_update = false;
         let mut partial_bitmap = Vec::<u8>::new();
@@ -1150,7 +1197,7 @@ impl RowsEvent {
                     & (EnumBinlogRowValueOptions::PartialJsonUpdates as u8)
                     != 0;
             if is_partial_json_update {
-                let byte_count = bitmap_byte_size(self.table.json_column_count() as isize) as usize;
+                let byte_count = bitmap_byte_size(table.json_column_count() as isize) as usize;
                 partial_bitmap = data[pos..pos + byte_count].to_vec();
                 pos += byte_count;
             }
@@ -1196,18 +1243,21 @@ impl RowsEvent {
 
             let (field_data, n) = self.decode_value(
                 &data[pos..],
-                self.table.column_type[i as usize],
-                self.table.column_meta[i as usize],
+                table.column_type[i as usize],
+                table.column_meta[i as usize],
                 is_partial,
             )?;
             row[i as usize] = field_data;
             pos += n as usize;
         }
 
-        self.rows.push(row);
-        self.skipped_columns.push(skips);
-
-        Ok(pos as isize)
+        Ok((
+            RowImage {
+                row,
+                skipped_columns: skips,
+            },
+            pos as isize,
+        ))
     }
 
     fn _parse_frac_time(&self, t: &DecodeDatetime) -> DecodeDatetime {
@@ -1225,7 +1275,7 @@ impl RowsEvent {
 
     // see mysql sql/log_event.cc log_event_print_value
     pub fn decode_value(
-        &mut self,
+        &self,
         data: &[u8],
         tp: u8,
         meta: u16,
@@ -1487,7 +1537,7 @@ impl RowsEvent {
 
     // decodeJsonBinary decodes the JSON binary encoding data and returns
     // the common JSON encoding data.
-    fn _decode_json_binary(&mut self, data: &[u8]) -> Result<Vec<u8>, ReplicationError> {
+    fn _decode_json_binary(&self, data: &[u8]) -> Result<Vec<u8>, ReplicationError> {
         let mut d = JsonBinaryDecoder {
             use_decimal: self.use_decimal,
             ignore_decode_err: self.ignore_json_decode_err,
@@ -1509,7 +1559,7 @@ impl RowsEvent {
         Ok(serde_json::to_vec(&v)?)
     }
 
-    fn _decode_json_partial_binary(&mut self, data: &[u8]) -> Result<JsonDiff, ReplicationError> {
+    fn _decode_json_partial_binary(&self, data: &[u8]) -> Result<JsonDiff, ReplicationError> {
         // see Json_diff_vector::read_binary() in mysql-server/sql/json_diff.cc
         let operation_number = JsonDiffOperation::from(data[0]);
         match operation_number {
@@ -1561,6 +1611,172 @@ impl RowsEvent {
     }
 }
 
+// RowImage is one image of a row, the values of the skipped columns are None.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RowImage {
+    pub row: Vec<DecodeFieldData>,
+    pub skipped_columns: Vec<isize>,
+}
+
+// RowsIter is the iterator of RowsEvent::rows_iter, it yields a row image and, for the update events, its after image.
+// It stops after the first error.
+pub struct RowsIter<'a> {
+    _event: &'a RowsEvent,
+    _pos: usize,
+    _done: bool,
+}
+
+impl RowsIter<'_> {
+    fn _next(&mut self) -> Result<(RowImage, Option<RowImage>), ReplicationError> {
+        let e = self._event;
+        let data = &e.rows_data;
+
+        let (image, n) =
+            e._decode_image(&data[self._pos..], &e.column_bitmap1, e._row_image_type())?;
+        self._pos += n as usize;
+
+        if !e.need_bitmap2 {
+            return Ok((image, None));
+        }
+
+        let (after, n) = e._decode_image(
+            &data[self._pos..],
+            &e.column_bitmap2,
+            EnumRowImageType::UpdateAI,
+        )?;
+        self._pos += n as usize;
+
+        Ok((image, Some(after)))
+    }
+}
+
+impl Iterator for RowsIter<'_> {
+    type Item = Result<(RowImage, Option<RowImage>), ReplicationError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self._done || self._pos >= self._event.rows_data.len() {
+            return None;
+        }
+
+        let rs = self._next();
+        if rs.is_err() {
+            self._done = true;
+        }
+
+        Some(rs)
+    }
+}
+
+// RowsEventRef is a RowsEvent borrowing the event body, only the table id and the flags are decoded,
+// the rows are decoded by to_owned. It lets a consumer skip the rows events of a table without decoding them.
+#[derive(Clone)]
+pub struct RowsEventRef<'a> {
+    pub event_type: EventType,
+    pub table_id_size: isize,
+
+    // the table map of table_id, none if the table map event is missing
+    pub table: Option<Arc<TableMapEvent>>,
+    pub table_id: u64,
+    pub flags: u16,
+
+    // the event body without the checksum
+    pub data: &'a [u8],
+
+    pub parse_time: bool,
+    pub timestamp_string_location: Option<chrono_tz::Tz>,
+    pub use_decimal: bool,
+    pub ignore_json_decode_err: bool,
+    pub lazy_rows: bool,
+    pub decode_func: Option<common::RowsEventDecodeFunc>,
+}
+
+impl std::fmt::Debug for RowsEventRef<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RowsEventRef")
+            .field("event_type", &self.event_type)
+            .field("table_id_size", &self.table_id_size)
+            .field("table", &self.table)
+            .field("table_id", &self.table_id)
+            .field("flags", &self.flags)
+            .field("data", &self.data)
+            .field("parse_time", &self.parse_time)
+            .field("timestamp_string_location", &self.timestamp_string_location)
+            .field("use_decimal", &self.use_decimal)
+            .field("ignore_json_decode_err", &self.ignore_json_decode_err)
+            .field("lazy_rows", &self.lazy_rows)
+            .field("decode_func", &self.decode_func.is_some())
+            .finish()
+    }
+}
+
+impl<'a> RowsEventRef<'a> {
+    // NewRowsEvent returns the RowsEvent to decode the body into, its rows are not decoded yet.
+    pub fn new_rows_event(&self) -> RowsEvent {
+        let mut e = RowsEvent {
+            table_id_size: self.table_id_size,
+            table: self.table.clone(),
+            event_type: self.event_type.clone(),
+            parse_time: self.parse_time,
+            timestamp_string_location: self.timestamp_string_location,
+            use_decimal: self.use_decimal,
+            ignore_json_decode_err: self.ignore_json_decode_err,
+            lazy_rows: self.lazy_rows,
+            ..Default::default()
+        };
+
+        match self.event_type {
+            EventType::WriteRowsEventv0 => e.version = 0,
+            EventType::UpdateRowsEventv0 => e.version = 0,
+            EventType::DeleteRowsEventv0 => e.version = 0,
+            EventType::WriteRowsEventv1 => e.version = 1,
+            EventType::UpdateRowsEventv1 => {
+                e.version = 1;
+                e.need_bitmap2 = true;
+            }
+            EventType::DeleteRowsEventv1 => e.version = 1,
+            EventType::WriteRowsEventv2 => e.version = 2,
+            EventType::UpdateRowsEventv2 => {
+                e.version = 2;
+                e.need_bitmap2 = true;
+            }
+            EventType::DeleteRowsEventv2 => e.version = 2,
+            EventType::MariadbWriteRowsCompressedEventV1 => {
+                e.version = 1;
+                e.compressed = true;
+            }
+            EventType::MariadbUpdateRowsCompressedEventV1 => {
+                e.version = 1;
+                e.compressed = true;
+                e.need_bitmap2 = true;
+            }
+            EventType::MariadbDeleteRowsCompressedEventV1 => {
+                e.version = 1;
+                e.compressed = true;
+            }
+            EventType::PartialUpdateRowsEvent => {
+                e.version = 2;
+                e.need_bitmap2 = true;
+            }
+            _ => {}
+        }
+
+        e
+    }
+
+    // ToOwned decodes the rows, with the decode func of the parser if it's set.
+    // The rows are decoded into rows even if lazy_rows is set, rows_data is left empty.
+    pub fn to_owned(&self) -> Result<RowsEvent, ReplicationError> {
+        let mut e = self.new_rows_event();
+        e.lazy_rows = false;
+        match &self.decode_func {
+            Some(f) => f(&mut e, self.data)?,
+            None => e.decode(self.data)?,
+        }
+
+        Ok(e)
+    }
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct RowsQueryEvent {
     pub query: Vec<u8>,
@@ -1582,6 +1798,31 @@ impl Event for RowsQueryEvent {
     }
 }
 
+// RowsQueryEventRef is a RowsQueryEvent borrowing the query from the event body.
+#[derive(Debug, Default, Clone)]
+pub struct RowsQueryEventRef<'a> {
+    pub query: &'a [u8],
+}
+
+impl<'a> RowsQueryEventRef<'a> {
+    pub fn decode(data: &'a [u8]) -> Result<RowsQueryEventRef<'a>, ReplicationError> {
+        if data.is_empty() {
+            return Err(ReplicationError::new(
+                "invalid rows query event, data is empty".to_string(),
+            ));
+        }
+
+        // ignore length byte 1
+        Ok(RowsQueryEventRef { query: &data[1..] })
+    }
+
+    pub fn to_owned(&self) -> RowsQueryEvent {
+        RowsQueryEvent {
+            query: self.query.to_vec(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -3,10 +3,11 @@ mod tests {
     use crate::common::row_fields::{DecodeDatetime, DecodeDecimal, DecodeFieldData, DecodeJson};
     use crate::error::ReplicationError;
     use crate::mysql;
-    use crate::replication::{decode_helper, Event, RowsEvent, TableMapEvent};
+    use crate::replication::{decode_helper, Event, EventType, RowsEvent, TableMapEvent};
     use bigdecimal::BigDecimal;
     use std::collections::HashMap;
     use std::str::FromStr;
+    use std::sync::Arc;
 
     // These are cases from the mysql test cases
     /*
@@ -2324,6 +2325,58 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_lazy_rows() -> Result<(), ReplicationError> {
+        let table_map_event_data =
+            b"\xd3\x01\x00\x00\x00\x00\x01\x00\x04test\x00\nfunnytable\x00\x01\x01\x00\x01";
+        let mut table_map_event = TableMapEvent::default();
+        table_map_event.table_id_size = 6;
+        table_map_event.decode(table_map_event_data)?;
+
+        // update funnytable set v = v + 1 on the rows 1 and 3
+        let data =
+            b"\xd3\x01\x00\x00\x00\x00\x01\x00\x02\x00\x01\xff\xff\xfe\x01\xfe\x02\xfe\x03\xfe\x04";
+
+        let mut rows = RowsEvent {
+            table_id_size: 6,
+            table: Some(Arc::new(table_map_event)),
+            version: 2,
+            need_bitmap2: true,
+            event_type: EventType::UpdateRowsEventv2,
+            ..Default::default()
+        };
+
+        let mut lazy = rows.clone();
+        lazy.lazy_rows = true;
+        lazy.decode(data)?;
+        assert!(lazy.rows.is_empty());
+        assert_eq!(lazy.rows_data, b"\xfe\x01\xfe\x02\xfe\x03\xfe\x04");
+
+        let decoded = lazy.rows_iter().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(decoded.len(), 2);
+        rows.decode(data)?;
+        for (i, (before, after)) in decoded.iter().enumerate() {
+            assert_eq!(before.row, rows.rows[2 * i]);
+            assert_eq!(after.as_ref().unwrap().row, rows.rows[2 * i + 1]);
+        }
+        assert_eq!(DecodeFieldData::Isize(3), decoded[1].0.row[0]);
+        assert_eq!(
+            DecodeFieldData::Isize(4),
+            decoded[1].1.as_ref().unwrap().row[0]
+        );
+
+        // the iterator stops after an error
+        lazy.table = None;
+        let mut it = lazy.rows_iter();
+        assert!(it.next().unwrap().is_err());
+        assert!(it.next().is_none());
+
+        // rows_iter yields nothing for the rows decoded eagerly
+        assert_eq!(rows.rows_iter().count(), 0);
+
+        Ok(())
+    }
+
     // mysql> desc aset;
     // +--------+---------------------------------------------------------------------------------------+------+-----+---------+-------+
     // | Field  | Type                                                                                  | Null | Key | Default | Extra |
@@ -3606,7 +3659,7 @@ mod tests {
 
     #[bench]
     fn benchmark_int(b: &mut test::Bencher) {
-        let mut e = RowsEvent::default();
+        let e = RowsEvent::default();
 
         let int_datas = get_int_data();
         b.iter(|| {